@@ -1,7 +1,9 @@
 //! Code analysis engine
 
-use crate::{AgentConfig, AgentError, ContributionResult};
+use crate::{is_lfs_pointer, AgentConfig, AgentError, EventBus, RepoStore};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RepoHealth {
@@ -16,32 +18,113 @@ pub struct CodePatterns {
     pub confidence: f32,
 }
 
+/// Project files checked by `analyze_repo_health`: candidate names, issue, recommendation
+const HEALTH_CHECKS: &[(&[&str], &str, &str)] = &[
+    (&["README", "README.md", "README.rst"], "Missing README", "Add a README describing the project"),
+    (&["LICENSE", "LICENSE.md", "COPYING"], "Missing license", "Add a LICENSE file"),
+    (&["tests", "test", "spec", "__tests__"], "Missing tests", "Add unit tests"),
+    (&[".github/workflows"], "Missing CI", "Add a GitHub Actions workflow"),
+];
+
+/// Marker files recognized by `detect_code_patterns`
+const PATTERN_MARKERS: &[(&str, &str)] = &[
+    ("Cargo.toml", "Rust crate"),
+    ("package.json", "Node.js package"),
+    ("pyproject.toml", "Python package"),
+    ("requirements.txt", "Python package"),
+    ("go.mod", "Go module"),
+    ("pom.xml", "Maven project"),
+    ("Dockerfile", "Containerized"),
+    ("docker-compose.yml", "Containerized"),
+    (".github/workflows", "GitHub Actions"),
+];
+
 pub struct CodeAnalyzer {
-    config: AgentConfig,
+    store: Arc<RepoStore>,
+    events: Arc<EventBus>,
 }
 
 impl CodeAnalyzer {
-    pub fn new(config: &AgentConfig) -> Result<Self, AgentError> {
+    pub fn new(
+        _config: &AgentConfig,
+        store: Arc<RepoStore>,
+        events: Arc<EventBus>,
+    ) -> Result<Self, AgentError> {
         Ok(Self {
-            config: config.clone(),
+            store,
+            events,
         })
     }
     
     pub async fn analyze_repo_health(&self, repo: &str) -> Result<RepoHealth, AgentError> {
-        // Placeholder implementation
+        let checkout = self.store.acquire_updated(repo).await?;
+        
+        let mut issues = Vec::new();
+        let mut recommendations = Vec::new();
+        for (index, (candidates, issue, recommendation)) in HEALTH_CHECKS.iter().enumerate() {
+            if !any_exists(checkout.path(), candidates) {
+                self.events.log(repo, *issue);
+                issues.push(issue.to_string());
+                recommendations.push(recommendation.to_string());
+            }
+            let percent = (index + 1) as f32 * 100.0 / HEALTH_CHECKS.len() as f32;
+            self.events.progress(repo, "health", percent);
+        }
+        
         Ok(RepoHealth {
-            score: 0.85,
-            issues: vec!["Missing tests".to_string()],
-            recommendations: vec!["Add unit tests".to_string()],
+            score: 1.0 - issues.len() as f32 / HEALTH_CHECKS.len() as f32,
+            issues,
+            recommendations,
         })
     }
     
     pub async fn detect_code_patterns(&self, repo: &str) -> Result<CodePatterns, AgentError> {
-        // Placeholder implementation
+        let checkout = self.store.acquire_updated(repo).await?;
+        
+        let mut patterns: Vec<String> = Vec::new();
+        for (index, (marker, pattern)) in PATTERN_MARKERS.iter().enumerate() {
+            let found = any_exists(checkout.path(), &[marker]);
+            if found && !patterns.iter().any(|p| p == pattern) {
+                patterns.push(pattern.to_string());
+            }
+            let percent = (index + 1) as f32 * 100.0 / PATTERN_MARKERS.len() as f32;
+            self.events.progress(repo, "patterns", percent);
+        }
+        
         Ok(CodePatterns {
-            patterns: vec!["REST API".to_string(), "Database access".to_string()],
-            confidence: 0.92,
+            confidence: if patterns.is_empty() { 0.0 } else { 0.92 },
+            patterns,
         })
     }
 }
 
+/// Whether any candidate exists with real content; unfetched LFS pointers do not count
+fn any_exists(root: &Path, candidates: &[&str]) -> bool {
+    candidates.iter().any(|candidate| {
+        let path = root.join(candidate);
+        path.exists() && !is_lfs_pointer(&path)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const POINTER: &str = "version https://git-lfs.github.com/spec/v1\n\
+        oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\n\
+        size 12345\n";
+    
+    #[test]
+    fn lfs_pointers_do_not_count_as_project_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("LICENSE"), POINTER).unwrap();
+        std::fs::write(dir.path().join("README.md"), "# Project\n").unwrap();
+        std::fs::create_dir(dir.path().join("tests")).unwrap();
+        
+        assert!(!any_exists(dir.path(), &["LICENSE", "LICENSE.md", "COPYING"]));
+        assert!(any_exists(dir.path(), &["README", "README.md"]));
+        assert!(any_exists(dir.path(), &["tests"]));
+        assert!(!any_exists(dir.path(), &["Cargo.toml"]));
+    }
+}
@@ -1,7 +1,9 @@
 //! Automation engine for intelligent operations
 
-use crate::{AgentConfig, AgentError, ContributionResult};
+use crate::{AgentConfig, AgentError, ContributionResult, FileChangeKind, GitEngine, RepoStore};
+use futures::executor::block_on;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AISuggestions {
@@ -11,21 +13,46 @@ pub struct AISuggestions {
 }
 
 pub struct AutomationEngine {
-    config: AgentConfig,
+    store: Arc<RepoStore>,
+    git: Arc<GitEngine>,
 }
 
 impl AutomationEngine {
-    pub fn new(config: &AgentConfig) -> Result<Self, AgentError> {
+    pub fn new(
+        _config: &AgentConfig,
+        store: Arc<RepoStore>,
+        git: Arc<GitEngine>,
+    ) -> Result<Self, AgentError> {
         Ok(Self {
-            config: config.clone(),
+            store,
+            git,
         })
     }
     
     pub async fn generate_ai_suggestions(&self, repo: &str) -> Result<AISuggestions, AgentError> {
+        let checkout = self.store.acquire(repo).await?;
+        let git = Arc::clone(&self.git);
+        let status = checkout.run(move |repository| block_on(git.status(repository))).await?;
+        
+        // Deletions cannot be staged by path, so only files present on disk are proposed
+        let mut files_to_change: Vec<String> = status.staged.iter()
+            .chain(status.unstaged.iter())
+            .filter(|entry| entry.kind != FileChangeKind::Deleted)
+            .map(|entry| entry.path.clone())
+            .chain(status.untracked.iter().cloned())
+            .collect();
+        files_to_change.sort();
+        files_to_change.dedup();
+        
         // Placeholder - in real implementation, this would use ML models
+        let commit_message = match files_to_change.len() {
+            1..=3 => format!("chore: update {}", files_to_change.join(", ")),
+            count => format!("chore: update {} files", count),
+        };
+        
         Ok(AISuggestions {
-            commit_message: "feat: improve repository structure".to_string(),
-            files_to_change: vec!["README.md".to_string()],
+            commit_message,
+            files_to_change,
             confidence: 0.89,
         })
     }
@@ -36,15 +63,19 @@ impl AutomationEngine {
         message: Option<String>,
         suggestions: AISuggestions,
     ) -> Result<ContributionResult, AgentError> {
-        // Placeholder implementation
-        Ok(ContributionResult {
-            commit_hash: "abc123def456".to_string(),
-            message: message.unwrap_or(suggestions.commit_message),
-            files_changed: suggestions.files_to_change,
-            insertions: 10,
-            deletions: 2,
-            ai_confidence: suggestions.confidence,
-        })
+        if suggestions.files_to_change.is_empty() {
+            return Err(AgentError::GitError(format!("nothing to commit in {}", repo)));
+        }
+        
+        let checkout = self.store.acquire(repo).await?;
+        let git = Arc::clone(&self.git);
+        let message = message.unwrap_or(suggestions.commit_message);
+        
+        checkout.run(move |repository| {
+            let files: Vec<&str> = suggestions.files_to_change.iter().map(String::as_str).collect();
+            let commit_hash = block_on(git.create_commit(repository, &message, &files))?;
+            block_on(git.contribution_result(repository, &commit_hash, suggestions.confidence))
+        }).await
     }
 }
 
@@ -0,0 +1,247 @@
+//! Operations fanned out across many repositories
+
+use crate::scheduler::repo_key;
+use crate::{AgentError, GitHubAgent, JobId, Operation, OperationResult, SyncTransport};
+use futures::stream::{self, StreamExt};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use uuid::Uuid;
+
+/// Repositories a batch runs against
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BatchTarget {
+    /// Explicit `owner/name` list
+    Repos(Vec<String>),
+    /// Every non-archived repository in `org` tagged with all of `topics`
+    Query {
+        org: Option<String>,
+        topics: Vec<String>,
+    },
+}
+
+/// What a batch does to each repository
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BatchOperation {
+    SmartCommit {
+        message: Option<String>,
+    },
+    AnalyzeRepo,
+    SyncRepo {
+        transport: SyncTransport,
+        mirror: Option<String>,
+    },
+    HealthCheck,
+}
+
+impl BatchOperation {
+    /// The single-repository operation for `repo`
+    pub fn for_repo(&self, repo: &str, session_id: Uuid) -> Operation {
+        let repo = repo.to_string();
+        match self {
+            BatchOperation::SmartCommit { message } => Operation::SmartCommit {
+                repo,
+                message: message.clone(),
+                session_id,
+            },
+            BatchOperation::AnalyzeRepo => Operation::AnalyzeRepo { repo, session_id },
+            BatchOperation::SyncRepo { transport, mirror } => Operation::SyncRepo {
+                repo,
+                transport: transport.clone(),
+                mirror: mirror.clone(),
+                session_id,
+            },
+            BatchOperation::HealthCheck => Operation::HealthCheck { repo, session_id },
+        }
+    }
+}
+
+/// Aggregated outcome of a batch; one repository failing does not fail the batch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchReport {
+    /// Number of repositories the target resolved to
+    pub repos: usize,
+    pub succeeded: BTreeMap<String, OperationResult>,
+    /// Error message by repository
+    pub failed: BTreeMap<String, String>,
+}
+
+impl BatchReport {
+    /// Some repositories succeeded and some failed
+    pub fn is_partial_failure(&self) -> bool {
+        !self.succeeded.is_empty() && !self.failed.is_empty()
+    }
+    
+    /// Mean health score over repositories whose health was checked
+    pub fn average_health_score(&self) -> Option<f32> {
+        let scores: Vec<f32> = self.succeeded.values()
+            .filter_map(|result| match result {
+                OperationResult::AnalyzeRepo(health) | OperationResult::HealthCheck(health) => Some(health.score),
+                _ => None,
+            })
+            .collect();
+        if scores.is_empty() {
+            None
+        } else {
+            Some(scores.iter().sum::<f32>() / scores.len() as f32)
+        }
+    }
+}
+
+/// Jobs submitted by a batch; unfinished ones are cancelled with the batch
+struct ChildJobs<'a> {
+    agent: &'a GitHubAgent,
+    ids: Mutex<Vec<JobId>>,
+}
+
+impl Drop for ChildJobs<'_> {
+    fn drop(&mut self) {
+        for id in self.ids.lock().drain(..) {
+            self.agent.cancel(id);
+        }
+    }
+}
+
+impl GitHubAgent {
+    /// Queue `operation` for every repository of `target`, at most
+    /// `max_concurrent` at a time
+    pub async fn submit_batch(
+        &self,
+        target: BatchTarget,
+        operation: BatchOperation,
+        max_concurrent: usize,
+        session_id: Uuid,
+    ) -> Result<JobId, AgentError> {
+        self.submit(Operation::Batch {
+            target,
+            operation,
+            max_concurrent,
+            session_id,
+        }).await
+    }
+    
+    /// Resolve `target` and run `operation` on each repository as its own job.
+    /// Child jobs are keyed by `batch_id`, so a replayed batch reattaches to
+    /// children that were themselves replayed instead of duplicating them.
+    pub(crate) async fn run_batch(
+        &self,
+        batch_id: JobId,
+        target: &BatchTarget,
+        operation: &BatchOperation,
+        max_concurrent: usize,
+        session_id: Uuid,
+    ) -> Result<BatchReport, AgentError> {
+        let repos = match target {
+            BatchTarget::Repos(repos) => repos.clone(),
+            BatchTarget::Query { org, topics } => {
+                self.github_client.search_repo_names(&search_query(org.as_deref(), topics)).await?
+            }
+        };
+        let mut seen = HashSet::new();
+        let repos: Vec<String> = repos.into_iter()
+            .filter(|repo| seen.insert(repo_key(repo)))
+            .collect();
+        
+        let children = ChildJobs {
+            agent: self,
+            ids: Mutex::new(Vec::new()),
+        };
+        let outcomes: Vec<(String, Result<OperationResult, AgentError>)> = stream::iter(repos.iter().cloned())
+            .map(|repo| {
+                let children = &children;
+                let child = operation.for_repo(&repo, session_id);
+                async move {
+                    let key = format!("batch:{}:{}", batch_id, repo);
+                    let result = match self.submit_idempotent(child, key).await {
+                        Ok(id) => {
+                            children.ids.lock().push(id);
+                            self.await_result(id).await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    (repo, result)
+                }
+            })
+            .buffer_unordered(max_concurrent.max(1))
+            .collect()
+            .await;
+        
+        let mut report = BatchReport {
+            repos: repos.len(),
+            ..BatchReport::default()
+        };
+        for (repo, result) in outcomes {
+            match result {
+                Ok(result) => {
+                    report.succeeded.insert(repo, result);
+                }
+                Err(e) => {
+                    report.failed.insert(repo, e.to_string());
+                }
+            }
+        }
+        
+        Ok(report)
+    }
+}
+
+/// GitHub search query for non-archived repositories in `org` with all `topics`
+fn search_query(org: Option<&str>, topics: &[String]) -> String {
+    let mut qualifiers: Vec<String> = org.iter()
+        .map(|org| format!("org:{}", org))
+        .chain(topics.iter().map(|topic| format!("topic:{}", topic)))
+        .collect();
+    qualifiers.push("archived:false".to_string());
+    qualifiers.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AgentConfig;
+    use git2::{Repository, Signature};
+    use std::path::Path;
+    use tempfile::TempDir;
+    
+    /// A repository at `path` with one commit on `main`
+    fn init_remote(path: &Path) {
+        let repo = Repository::init(path).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        std::fs::write(path.join("README.md"), "hello\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("README.md")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        repo.commit(Some("refs/heads/main"), &signature, &signature, "initial", &tree, &[]).unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+    }
+    
+    #[tokio::test]
+    async fn failing_repositories_do_not_hide_the_others() {
+        let dir = TempDir::new().unwrap();
+        init_remote(&dir.path().join("remotes/o/good.git"));
+        init_remote(&dir.path().join("remotes/o/other.git"));
+        let config = AgentConfig {
+            repo_cache_root: Some(dir.path().join("cache")),
+            repo_remote_base: Some(format!("file://{}", dir.path().join("remotes").display())),
+            ..AgentConfig::default()
+        };
+        let agent = GitHubAgent::new(config).await.unwrap();
+        
+        let target = BatchTarget::Repos(vec![
+            "o/good".to_string(),
+            "o/missing".to_string(),
+            "https://github.com/O/Good.git".to_string(),
+            "o/other".to_string(),
+            "O/GOOD".to_string(),
+        ]);
+        let operation = BatchOperation::SyncRepo {
+            transport: SyncTransport::Network,
+            mirror: None,
+        };
+        let report = agent.run_batch(JobId::new(), &target, &operation, 2, Uuid::nil()).await.unwrap();
+        
+        assert_eq!(report.repos, 3);
+        assert_eq!(report.succeeded.keys().collect::<Vec<_>>(), vec!["o/good", "o/other"]);
+        assert_eq!(report.failed.keys().collect::<Vec<_>>(), vec!["o/missing"]);
+    }
+}
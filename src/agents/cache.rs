@@ -0,0 +1,299 @@
+//! Conditional-request cache for GitHub API responses
+
+use crate::AgentError;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use uuid::Uuid;
+
+/// A response that can be revalidated with `If-None-Match` or `If-Modified-Since`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedResponse {
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+    /// Pagination `Link` header, which a 304 does not repeat
+    #[serde(default)]
+    pub(crate) link: Option<String>,
+    pub(crate) body: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    response: CachedResponse,
+    last_used: DateTime<Utc>,
+}
+
+impl CacheEntry {
+    fn size_bytes(&self) -> u64 {
+        (self.key.len() + self.response.body.len()) as u64
+    }
+}
+
+/// Responses keyed by auth identity and URL, evicted least-recently-used
+/// first once over capacity. With a directory, each entry is also kept in
+/// its own file so the cache survives restarts.
+pub(crate) struct ResponseCache {
+    capacity_bytes: u64,
+    dir: Option<PathBuf>,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl ResponseCache {
+    /// Open the cache, loading entries persisted in `dir` by earlier runs
+    pub(crate) fn open(capacity_bytes: u64, dir: Option<&Path>) -> Result<Self, AgentError> {
+        let cache = Self {
+            capacity_bytes,
+            dir: dir.map(Path::to_path_buf),
+            entries: Mutex::new(HashMap::new()),
+        };
+        
+        if let Some(dir) = dir {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| AgentError::InternalError(format!("cannot create response cache: {}", e)))?;
+            let files = std::fs::read_dir(dir)
+                .map_err(|e| AgentError::InternalError(format!("cannot read response cache: {}", e)))?;
+            
+            let mut entries = cache.entries.lock();
+            for file in files.flatten() {
+                let path = file.path();
+                // Staging files left by an interrupted write
+                if path.extension().is_some_and(|extension| extension == "tmp") {
+                    let _ = std::fs::remove_file(&path);
+                    continue;
+                }
+                let entry = std::fs::read(&path).ok()
+                    .and_then(|data| serde_json::from_slice::<CacheEntry>(&data).ok());
+                match entry {
+                    Some(entry) => {
+                        entries.insert(entry.key.clone(), entry);
+                    }
+                    // Torn or foreign files are dropped
+                    None => {
+                        let _ = std::fs::remove_file(&path);
+                    }
+                }
+            }
+            for path in cache.evict(&mut entries) {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+        
+        Ok(cache)
+    }
+    
+    pub(crate) fn get(&self, key: &str) -> Option<CachedResponse> {
+        let mut entries = self.entries.lock();
+        let entry = entries.get_mut(key)?;
+        entry.last_used = Utc::now();
+        Some(entry.response.clone())
+    }
+    
+    pub(crate) async fn insert(&self, key: String, response: CachedResponse) {
+        let entry = CacheEntry {
+            key: key.clone(),
+            response,
+            last_used: Utc::now(),
+        };
+        if entry.size_bytes() > self.capacity_bytes {
+            return;
+        }
+        
+        // Persistence is best effort; a failed write only costs a refetch after restart
+        if let Some(path) = self.file_for(&key) {
+            if let Ok(data) = serde_json::to_vec(&entry) {
+                // Unique per write, so concurrent inserts of one key never share a file
+                let staging = path.with_extension(format!("{}.tmp", Uuid::new_v4().simple()));
+                if fs::write(&staging, data).await.is_ok() {
+                    let _ = fs::rename(&staging, &path).await;
+                }
+            }
+        }
+        
+        let evicted = {
+            let mut entries = self.entries.lock();
+            entries.insert(key, entry);
+            self.evict(&mut entries)
+        };
+        for path in evicted {
+            let _ = fs::remove_file(path).await;
+        }
+    }
+    
+    /// Drop least-recently-used entries until the cache fits, returning the
+    /// files to delete once the lock is released
+    fn evict(&self, entries: &mut HashMap<String, CacheEntry>) -> Vec<PathBuf> {
+        let mut evicted = Vec::new();
+        let mut total: u64 = entries.values().map(CacheEntry::size_bytes).sum();
+        if total <= self.capacity_bytes {
+            return evicted;
+        }
+        
+        let mut candidates: Vec<(DateTime<Utc>, String)> = entries.values()
+            .map(|entry| (entry.last_used, entry.key.clone()))
+            .collect();
+        candidates.sort();
+        
+        for (_, key) in candidates {
+            if total <= self.capacity_bytes {
+                break;
+            }
+            if let Some(entry) = entries.remove(&key) {
+                evicted.extend(self.file_for(&key));
+                total = total.saturating_sub(entry.size_bytes());
+            }
+        }
+        evicted
+    }
+    
+    fn file_for(&self, key: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        Some(dir.join(format!("{}.json", digest_name(key))))
+    }
+}
+
+/// Cache key for `url` requested as `identity`, e.g. `user/123`. Keying by
+/// who is asking rather than by token keeps entries valid across token
+/// refreshes while never sharing them between accounts.
+pub(crate) fn cache_key(url: &str, identity: &str) -> String {
+    format!("{} {}", identity, url)
+}
+
+fn digest_name(data: impl AsRef<[u8]>) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, data.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentConfig, GitHubClient};
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    
+    fn response(body: &str) -> CachedResponse {
+        CachedResponse {
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+            link: None,
+            body: body.to_string(),
+        }
+    }
+    
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir).unwrap()
+            .map(|file| file.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+    
+    #[tokio::test]
+    async fn least_recently_used_entries_are_evicted_first() {
+        let dir = TempDir::new().unwrap();
+        // Room for two of the 11-byte entries below
+        let cache = ResponseCache::open(30, Some(dir.path())).unwrap();
+        cache.insert("a".to_string(), response("0123456789")).await;
+        cache.insert("b".to_string(), response("0123456789")).await;
+        assert!(cache.get("a").is_some());
+        
+        cache.insert("c".to_string(), response("0123456789")).await;
+        
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+        let mut expected = vec![format!("{}.json", digest_name("a")), format!("{}.json", digest_name("c"))];
+        expected.sort();
+        assert_eq!(files(dir.path()), expected);
+    }
+    
+    #[tokio::test]
+    async fn entries_are_reloaded_and_torn_files_deleted() {
+        let dir = TempDir::new().unwrap();
+        {
+            let cache = ResponseCache::open(1024, Some(dir.path())).unwrap();
+            cache.insert("user/1 https://api.github.com/repos/o/r".to_string(), CachedResponse {
+                etag: None,
+                last_modified: Some("Tue, 01 Oct 2024 00:00:00 GMT".to_string()),
+                link: Some("<https://api.github.com/x?page=2>; rel=\"next\"".to_string()),
+                body: "{\"id\":1}".to_string(),
+            }).await;
+        }
+        let kept = files(dir.path());
+        std::fs::write(dir.path().join("torn.json"), "{\"key\":\"user/1 https://api.gi").unwrap();
+        std::fs::write(dir.path().join("leftover.0123.tmp"), "{}").unwrap();
+        
+        let cache = ResponseCache::open(1024, Some(dir.path())).unwrap();
+        
+        let reloaded = cache.get("user/1 https://api.github.com/repos/o/r").unwrap();
+        assert_eq!(reloaded.body, "{\"id\":1}");
+        assert_eq!(reloaded.last_modified.as_deref(), Some("Tue, 01 Oct 2024 00:00:00 GMT"));
+        assert_eq!(reloaded.link.as_deref(), Some("<https://api.github.com/x?page=2>; rel=\"next\""));
+        assert_eq!(files(dir.path()), kept);
+        
+        // Reopening with less room evicts from disk as well
+        drop(cache);
+        let cache = ResponseCache::open(8, Some(dir.path())).unwrap();
+        assert!(cache.get("user/1 https://api.github.com/repos/o/r").is_none());
+        assert!(files(dir.path()).is_empty());
+    }
+    
+    /// Serve `/items` once with validators and a `Link` header, then answer
+    /// requests that revalidate with 304 and any other with 500
+    async fn revalidating_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let link = format!("<{0}/items?page=2>; rel=\"next\", <{0}/items?page=4>; rel=\"last\"", base);
+        tokio::spawn(async move {
+            let mut served = false;
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut data = Vec::new();
+                let mut buf = [0u8; 4096];
+                while !data.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let read = stream.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    data.extend_from_slice(&buf[..read]);
+                }
+                let request = String::from_utf8_lossy(&data).to_ascii_lowercase();
+                let reply = if request.contains("if-none-match: \"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\netag: \"v1\"\r\nconnection: close\r\n\r\n".to_string()
+                } else if served {
+                    "HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_string()
+                } else {
+                    served = true;
+                    format!(
+                        "HTTP/1.1 200 OK\r\netag: \"v1\"\r\nlink: {}\r\ncontent-type: application/json\r\ncontent-length: 7\r\nconnection: close\r\n\r\n[1,2,3]",
+                        link
+                    )
+                };
+                let _ = stream.write_all(reply.as_bytes()).await;
+            }
+        });
+        base
+    }
+    
+    #[tokio::test]
+    async fn not_modified_replays_the_cached_body_and_link() {
+        let base = revalidating_server().await;
+        let client = GitHubClient::new(&AgentConfig {
+            github_api_url: Some(base.clone()),
+            ..AgentConfig::default()
+        }).await.unwrap();
+        let url = format!("{}/items", base);
+        
+        let fresh: (Vec<u32>, Option<String>) = client.get_page(&url, &[]).await.unwrap();
+        let replayed: (Vec<u32>, Option<String>) = client.get_page(&url, &[]).await.unwrap();
+        
+        let next = Some(format!("{}/items?page=2", base));
+        assert_eq!(fresh, (vec![1, 2, 3], next.clone()));
+        assert_eq!(replayed, (vec![1, 2, 3], next));
+    }
+}
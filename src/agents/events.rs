@@ -0,0 +1,188 @@
+//! Live job lifecycle and progress events
+
+use crate::scheduler::repo_key;
+use crate::JobId;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before a slow one starts missing them
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Event published on `GitHubAgent::subscribe`. Progress and log events carry
+/// the job running on their repository, if the work belongs to one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEvent {
+    Queued {
+        job: JobId,
+        operation: String,
+        repo: String,
+    },
+    Started {
+        job: JobId,
+        /// 1 for the first attempt, higher for retries
+        attempt: u32,
+    },
+    Progress {
+        job: Option<JobId>,
+        repo: String,
+        /// e.g. `receiving`, `checkout`, `health`
+        stage: String,
+        percent: f32,
+    },
+    Log {
+        job: Option<JobId>,
+        repo: String,
+        line: String,
+    },
+    Completed {
+        job: JobId,
+    },
+    Failed {
+        job: JobId,
+        error: String,
+    },
+    /// The OAuth device flow is waiting for the user to enter `user_code`
+    DeviceAuthorization {
+        verification_uri: String,
+        user_code: String,
+        expires_at: DateTime<Utc>,
+    },
+}
+
+/// Broadcasts `AgentEvent`s from the scheduler and the engines to subscribers
+pub struct EventBus {
+    sender: broadcast::Sender<AgentEvent>,
+    /// Job currently running on each repository
+    running: DashMap<String, JobId>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self {
+            sender,
+            running: DashMap::new(),
+        }
+    }
+    
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<AgentEvent> {
+        self.sender.subscribe()
+    }
+    
+    pub(crate) fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+    
+    pub(crate) fn emit(&self, event: AgentEvent) {
+        // No subscribers is not an error
+        let _ = self.sender.send(event);
+    }
+    
+    /// Attribute progress on `repo` to `job` until `detach`
+    pub(crate) fn attach(&self, repo: &str, job: JobId) {
+        self.running.insert(repo_key(repo), job);
+    }
+    
+    pub(crate) fn detach(&self, repo: &str, job: JobId) {
+        self.running.remove_if(&repo_key(repo), |_, running| *running == job);
+    }
+    
+    pub(crate) fn progress(&self, repo: &str, stage: &str, percent: f32) {
+        self.emit(AgentEvent::Progress {
+            job: self.job_for(repo),
+            repo: repo.to_string(),
+            stage: stage.to_string(),
+            percent: percent.clamp(0.0, 100.0),
+        });
+    }
+    
+    pub(crate) fn log(&self, repo: &str, line: impl Into<String>) {
+        self.emit(AgentEvent::Log {
+            job: self.job_for(repo),
+            repo: repo.to_string(),
+            line: line.into(),
+        });
+    }
+    
+    fn job_for(&self, repo: &str) -> Option<JobId> {
+        self.running.get(&repo_key(repo)).map(|job| *job)
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentConfig, GitHubAgent, Operation, SyncTransport};
+    use git2::{Repository, Signature};
+    use std::path::Path;
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use uuid::Uuid;
+    
+    fn sync(repo: &str) -> Operation {
+        Operation::SyncRepo {
+            repo: repo.to_string(),
+            transport: SyncTransport::Network,
+            mirror: None,
+            session_id: Uuid::nil(),
+        }
+    }
+    
+    /// Names of the lifecycle events of `job`'s first attempt, up to and
+    /// including the one that ends it
+    async fn lifecycle(events: &mut broadcast::Receiver<AgentEvent>, job: JobId) -> Vec<&'static str> {
+        let mut seen = Vec::new();
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(30), events.recv()).await
+                .expect("job did not finish")
+                .unwrap();
+            let name = match event {
+                AgentEvent::Queued { job: id, .. } if id == job => "queued",
+                AgentEvent::Started { job: id, attempt: 1 } if id == job => "started",
+                AgentEvent::Completed { job: id } if id == job => "completed",
+                AgentEvent::Failed { job: id, .. } if id == job => "failed",
+                _ => continue,
+            };
+            seen.push(name);
+            if matches!(name, "completed" | "failed") {
+                return seen;
+            }
+        }
+    }
+    
+    #[tokio::test]
+    async fn jobs_report_queued_started_then_their_outcome() {
+        let dir = TempDir::new().unwrap();
+        let remote = dir.path().join("remotes/o/r.git");
+        let repo = Repository::init(&remote).unwrap();
+        std::fs::write(remote.join("README.md"), "hello\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("README.md")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[]).unwrap();
+        
+        let agent = GitHubAgent::new(AgentConfig {
+            repo_cache_root: Some(dir.path().join("cache")),
+            repo_remote_base: Some(format!("file://{}", dir.path().join("remotes").display())),
+            ..AgentConfig::default()
+        }).await.unwrap();
+        let mut events = agent.subscribe();
+        
+        let succeeding = agent.submit(sync("o/r")).await.unwrap();
+        assert_eq!(lifecycle(&mut events, succeeding).await, vec!["queued", "started", "completed"]);
+        
+        let failing = agent.submit(sync("not a repository")).await.unwrap();
+        assert_eq!(lifecycle(&mut events, failing).await, vec!["queued", "started", "failed"]);
+    }
+}
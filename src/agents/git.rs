@@ -1,26 +1,1085 @@
 //! Ultra-fast Git operations engine
 
-use crate::{AgentConfig, AgentError};
-use git2::{Repository, Signature};
-use std::path::Path;
+use crate::{
+    lfs_endpoint_for_remote, AgentConfig, AgentError, CommitSigner, ContributionResult, LfsClient,
+    LfsPointer, LFS_POINTER_MAX_SIZE,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::future::BoxFuture;
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::{
+    AnnotatedCommit, AutotagOption, Blame, BlameOptions, Branch, BranchType, Commit, Cred, CredentialType, Delta, Diff, DiffDelta, DiffOptions,
+    FetchOptions, FetchPrune, ObjectType, Oid, Patch, PushOptions, Rebase, Reference, RemoteCallbacks,
+    Repository, RepositoryState, RevparseMode, Revwalk, Signature, Sort, StashApplyOptions,
+    StashFlags, Status, StatusOptions, SubmoduleIgnore, SubmoduleStatus, SubmoduleUpdateOptions,
+    Tree, TreeWalkMode,
+    TreeWalkResult, WorktreeAddOptions, WorktreeLockStatus, WorktreePruneOptions,
+};
+use regex::Regex;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs;
+use tokio::sync::mpsc;
+
+/// Maximum number of times a credential provider is asked per remote operation
+const MAX_CREDENTIAL_ATTEMPTS: usize = 3;
+
+/// First line of a version 2 git bundle
+const BUNDLE_SIGNATURE_V2: &str = "# v2 git bundle";
+
+/// First line of a version 3 git bundle, which adds capability lines
+const BUNDLE_SIGNATURE_V3: &str = "# v3 git bundle";
+
+/// Where bundle imports land, as if fetched from `origin`
+const BUNDLE_IMPORT_REFSPECS: &[&str] = &[
+    "refs/heads/*:refs/remotes/origin/*",
+    "refs/tags/*:refs/tags/*",
+];
+
+/// Index entry flag marking the presence of extended flags
+const INDEX_ENTRY_EXTENDED: u16 = 0x4000;
+
+/// Extended index entry flag excluding the path from the working tree
+const INDEX_ENTRY_SKIP_WORKTREE: u16 = 0x4000;
+
+/// Source of credentials for authenticated remote operations
+pub trait CredentialProvider: Send + Sync {
+    /// Obtain whatever `credentials` needs, such as a fresh token, before a
+    /// remote operation starts; `credentials` runs inside git2 callbacks,
+    /// which must not wait on the network
+    fn prepare(&self) -> BoxFuture<'_, Result<(), AgentError>> {
+        Box::pin(async { Ok(()) })
+    }
+    
+    /// Produce credentials for `url`, given the credential types the transport accepts
+    fn credentials(
+        &self,
+        url: &str,
+        username_from_url: Option<&str>,
+        allowed: CredentialType,
+    ) -> Result<Cred, git2::Error>;
+}
+
+/// Personal access or installation token sent over HTTPS basic auth
+#[derive(Debug, Clone)]
+pub struct TokenCredentials {
+    pub username: String,
+    pub token: String,
+}
+
+impl TokenCredentials {
+    /// Token credentials using the `x-access-token` username GitHub expects
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            username: "x-access-token".to_string(),
+            token: token.into(),
+        }
+    }
+}
+
+impl CredentialProvider for TokenCredentials {
+    fn credentials(
+        &self,
+        _url: &str,
+        _username_from_url: Option<&str>,
+        allowed: CredentialType,
+    ) -> Result<Cred, git2::Error> {
+        if !allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            return Err(git2::Error::from_str("token credentials require an HTTPS remote"));
+        }
+        Cred::userpass_plaintext(&self.username, &self.token)
+    }
+}
+
+/// Keys held by a running SSH agent
+#[derive(Debug, Clone, Default)]
+pub struct SshAgentCredentials {
+    /// Username to use when the remote URL does not carry one
+    pub username: Option<String>,
+}
+
+impl CredentialProvider for SshAgentCredentials {
+    fn credentials(
+        &self,
+        _url: &str,
+        username_from_url: Option<&str>,
+        allowed: CredentialType,
+    ) -> Result<Cred, git2::Error> {
+        let username = ssh_username(username_from_url, self.username.as_deref());
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username);
+        }
+        Cred::ssh_key_from_agent(username)
+    }
+}
+
+/// Private key read from disk
+#[derive(Debug, Clone)]
+pub struct SshKeyCredentials {
+    pub username: Option<String>,
+    pub private_key: PathBuf,
+    pub public_key: Option<PathBuf>,
+    pub passphrase: Option<String>,
+}
+
+impl CredentialProvider for SshKeyCredentials {
+    fn credentials(
+        &self,
+        _url: &str,
+        username_from_url: Option<&str>,
+        allowed: CredentialType,
+    ) -> Result<Cred, git2::Error> {
+        let username = ssh_username(username_from_url, self.username.as_deref());
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username);
+        }
+        Cred::ssh_key(
+            username,
+            self.public_key.as_deref(),
+            &self.private_key,
+            self.passphrase.as_deref(),
+        )
+    }
+}
+
+/// Credentials resolved through the configured `credential.helper`
+#[derive(Debug, Clone, Default)]
+pub struct CredentialHelperCredentials {
+    /// Git config file to read the helper from; the user's default config when unset
+    pub config_path: Option<PathBuf>,
+}
+
+impl CredentialProvider for CredentialHelperCredentials {
+    fn credentials(
+        &self,
+        url: &str,
+        username_from_url: Option<&str>,
+        _allowed: CredentialType,
+    ) -> Result<Cred, git2::Error> {
+        let config = match &self.config_path {
+            Some(path) => git2::Config::open(path)?,
+            None => git2::Config::open_default()?,
+        };
+        Cred::credential_helper(&config, url, username_from_url)
+    }
+}
+
+fn ssh_username<'a>(from_url: Option<&'a str>, configured: Option<&'a str>) -> &'a str {
+    from_url.or(configured).unwrap_or("git")
+}
+
+/// How `pull` integrates the fetched branch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PullMode {
+    /// Refuse to pull unless the local branch can be fast-forwarded
+    FastForwardOnly,
+    /// Create a merge commit when the branches have diverged
+    Merge,
+}
+
+/// Result of a pull
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PullOutcome {
+    UpToDate,
+    FastForward { commit: String },
+    Merged { commit: String },
+}
+
+/// Stage of a clone reported through `CloneProgress`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CloneStage {
+    Receiving,
+    Resolving,
+    Checkout,
+}
+
+/// Progress snapshot emitted while cloning
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloneProgress {
+    pub stage: CloneStage,
+    pub current: usize,
+    pub total: usize,
+    pub received_bytes: usize,
+}
+
+impl CloneProgress {
+    pub fn percent(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.current as f32 * 100.0 / self.total as f32
+        }
+    }
+}
+
+/// Callback invoked with clone progress updates
+pub type CloneProgressCallback = Arc<dyn Fn(&CloneProgress) + Send + Sync>;
+
+/// Callback invoked with each line of remote output, e.g. `Counting objects: 10% (1/10)`
+pub type RemoteMessageCallback = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Ref to check out instead of the remote's default branch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CloneReference {
+    Branch(String),
+    Tag(String),
+}
+
+/// Options for `GitEngine::clone_repo_with`
+#[derive(Clone, Default)]
+pub struct CloneOptions {
+    depth: Option<u32>,
+    reference: Option<CloneReference>,
+    bare: bool,
+    sparse_paths: Vec<String>,
+    recurse_submodules: bool,
+    fetch_lfs: bool,
+    progress: Option<CloneProgressCallback>,
+    remote_message: Option<RemoteMessageCallback>,
+}
+
+impl CloneOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Fetch only the last `depth` commits of history
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+    
+    /// Fetch and check out only `branch`
+    pub fn branch(mut self, branch: impl Into<String>) -> Self {
+        self.reference = Some(CloneReference::Branch(branch.into()));
+        self
+    }
+    
+    /// Fetch only `tag` and check it out as a detached `HEAD`
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.reference = Some(CloneReference::Tag(tag.into()));
+        self
+    }
+    
+    /// Clone without a working tree
+    pub fn bare(mut self, bare: bool) -> Self {
+        self.bare = bare;
+        self
+    }
+    
+    /// Restrict the working tree to these files and directories
+    pub fn sparse_paths<I, S>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.sparse_paths = paths
+            .into_iter()
+            .map(|p| p.into().trim_matches('/').to_string())
+            .filter(|p| !p.is_empty())
+            .collect();
+        self
+    }
+    
+    /// Initialize and update submodules, recursively
+    pub fn recurse_submodules(mut self, recurse: bool) -> Self {
+        self.recurse_submodules = recurse;
+        self
+    }
+    
+    /// Replace LFS pointer files with their content after checkout
+    pub fn fetch_lfs(mut self, fetch: bool) -> Self {
+        self.fetch_lfs = fetch;
+        self
+    }
+    
+    /// Report progress through `callback`
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&CloneProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+    
+    /// Report progress by sending snapshots on `tx`
+    pub fn progress_channel(self, tx: mpsc::UnboundedSender<CloneProgress>) -> Self {
+        self.on_progress(move |progress| {
+            let _ = tx.send(progress.clone());
+        })
+    }
+    
+    /// Report lines the remote prints while packing through `callback`
+    pub fn on_remote_message<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.remote_message = Some(Arc::new(callback));
+        self
+    }
+    
+    fn report(&self, progress: CloneProgress) {
+        if let Some(callback) = &self.progress {
+            callback(&progress);
+        }
+    }
+    
+    fn report_remote_message(&self, data: &[u8]) {
+        if let Some(callback) = &self.remote_message {
+            let text = String::from_utf8_lossy(data);
+            for line in text.split(['\r', '\n']).map(str::trim).filter(|line| !line.is_empty()) {
+                callback(line);
+            }
+        }
+    }
+}
+
+/// Name and email recorded on commits
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitIdentity {
+    pub name: String,
+    pub email: String,
+}
+
+impl CommitIdentity {
+    pub fn new(name: impl Into<String>, email: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            email: email.into(),
+        }
+    }
+    
+    fn signature(&self) -> Result<Signature<'static>, AgentError> {
+        Signature::now(&self.name, &self.email)
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+}
+
+/// Per-call overrides for `GitEngine::create_commit_with`
+#[derive(Debug, Clone, Default)]
+pub struct CommitOptions {
+    pub author: Option<CommitIdentity>,
+    pub committer: Option<CommitIdentity>,
+}
+
+/// How a path differs between two sides of a comparison
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileChangeKind {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+    TypeChange,
+}
+
+/// A changed path reported by `GitEngine::status`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusEntry {
+    pub path: String,
+    /// Original path when `kind` is `Renamed`
+    pub old_path: Option<String>,
+    pub kind: FileChangeKind,
+}
+
+/// Working tree and index state relative to `HEAD`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkingTreeStatus {
+    /// Changes between `HEAD` and the index
+    pub staged: Vec<StatusEntry>,
+    /// Changes between the index and the working tree
+    pub unstaged: Vec<StatusEntry>,
+    pub untracked: Vec<String>,
+    pub conflicted: Vec<String>,
+}
+
+impl WorkingTreeStatus {
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty()
+            && self.unstaged.is_empty()
+            && self.untracked.is_empty()
+            && self.conflicted.is_empty()
+    }
+}
+
+/// Sides compared by `GitEngine::diff`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiffTarget {
+    /// Unstaged changes
+    WorkdirToIndex,
+    /// Staged changes
+    IndexToHead,
+    /// Changes from one revision to another, e.g. `main` and `HEAD`
+    Revisions { from: String, to: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiffLineKind {
+    Context,
+    Addition,
+    Deletion,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub old_lineno: Option<u32>,
+    pub new_lineno: Option<u32>,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffHunk {
+    pub header: String,
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Changes to a single file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileDiff {
+    pub path: String,
+    pub old_path: Option<String>,
+    pub kind: FileChangeKind,
+    pub binary: bool,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// Structured result of `GitEngine::diff`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiffReport {
+    pub files: Vec<FileDiff>,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl DiffReport {
+    pub fn files_changed(&self) -> Vec<String> {
+        self.files.iter().map(|f| f.path.clone()).collect()
+    }
+}
+
+/// Whether a branch lives under `refs/heads` or `refs/remotes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BranchKind {
+    Local,
+    Remote,
+}
+
+/// Branch listing entry with its position relative to its upstream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BranchInfo {
+    pub name: String,
+    pub kind: BranchKind,
+    pub commit: String,
+    pub is_head: bool,
+    /// Upstream branch such as `origin/main`
+    pub upstream: Option<String>,
+    /// Commits on this branch that are not on its upstream
+    pub ahead: usize,
+    /// Commits on the upstream that are not on this branch
+    pub behind: usize,
+}
+
+/// Tag listing entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagInfo {
+    pub name: String,
+    /// Commit the tag ultimately points at
+    pub commit: String,
+    pub annotated: bool,
+    pub message: Option<String>,
+    pub tagger: Option<CommitIdentity>,
+}
+
+/// A path left conflicted by a merge, rebase, cherry-pick or revert
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictEntry {
+    pub path: String,
+    /// Blob id in the merge base, if the path existed there
+    pub ancestor: Option<String>,
+    /// Blob id on the current branch
+    pub ours: Option<String>,
+    /// Blob id on the incoming side
+    pub theirs: Option<String>,
+}
+
+/// Result of `merge`, `rebase`, `cherry_pick`, `revert` and `continue_operation`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeOutcome {
+    UpToDate,
+    FastForward { commit: String },
+    /// A new commit was written and `HEAD` points at it
+    Committed { commit: String },
+    /// The operation stopped; resolve and stage the paths, then call
+    /// `continue_operation`, or call `abort_operation`
+    Conflicted { conflicts: Vec<ConflictEntry> },
+}
+
+/// Which commits `GitEngine::log` yields with respect to merges
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeFilter {
+    #[default]
+    Include,
+    Exclude,
+    Only,
+}
+
+/// Filters for `GitEngine::log`; the default walks all of `HEAD`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogFilter {
+    /// Revision (`main`) or range (`v1.0..HEAD`, `main...topic`); `HEAD` when unset
+    pub range: Option<String>,
+    /// Only commits that change one of these paths relative to every parent
+    pub paths: Vec<String>,
+    /// Regex matched against `Name <email>` of the author
+    pub author: Option<String>,
+    /// Only commits authored at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only commits authored at or before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Regex matched against the full commit message
+    pub message_pattern: Option<String>,
+    /// Follow only the first parent of merge commits
+    pub first_parent: bool,
+    /// Never list a commit before its descendants, like `git log --topo-order`;
+    /// otherwise commits come newest first by commit time
+    #[serde(default)]
+    pub topo_order: bool,
+    pub merges: MergeFilter,
+    /// Stop after this many matching commits
+    pub limit: Option<usize>,
+}
+
+/// A commit yielded by `GitEngine::log`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitInfo {
+    pub id: String,
+    pub parents: Vec<String>,
+    pub author: CommitIdentity,
+    pub committer: CommitIdentity,
+    pub authored_at: DateTime<Utc>,
+    pub committed_at: DateTime<Utc>,
+    pub summary: String,
+    pub message: String,
+    /// Trailers such as `Signed-off-by` or `Co-authored-by`, in order
+    pub trailers: Vec<(String, String)>,
+    /// Changes relative to the first parent
+    pub files_changed: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// Lazily evaluated commit history produced by `GitEngine::log`
+pub struct CommitLog<'r> {
+    repo: &'r Repository,
+    walk: Revwalk<'r>,
+    filter: LogFilter,
+    author: Option<Regex>,
+    message: Option<Regex>,
+    remaining: Option<usize>,
+}
+
+impl<'r> CommitLog<'r> {
+    fn matches(&self, commit: &Commit<'_>) -> Result<bool, AgentError> {
+        let parents = commit.parent_count();
+        match self.filter.merges {
+            MergeFilter::Exclude if parents > 1 => return Ok(false),
+            MergeFilter::Only if parents <= 1 => return Ok(false),
+            _ => {}
+        }
+        
+        let authored_at = git_time(commit.author().when());
+        if self.filter.since.is_some_and(|since| authored_at < since)
+            || self.filter.until.is_some_and(|until| authored_at > until)
+        {
+            return Ok(false);
+        }
+        
+        if let Some(author) = &self.author {
+            let signature = commit.author();
+            let who = format!(
+                "{} <{}>",
+                signature.name().unwrap_or_default(),
+                signature.email().unwrap_or_default()
+            );
+            if !author.is_match(&who) {
+                return Ok(false);
+            }
+        }
+        
+        if let Some(message) = &self.message {
+            if !message.is_match(commit.message().unwrap_or_default()) {
+                return Ok(false);
+            }
+        }
+        
+        if !self.filter.paths.is_empty() {
+            // Like `git log -- <paths>`, hide commits that leave the paths
+            // identical to any parent, which drops uninteresting merges
+            let tree = commit.tree()
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            let parent_trees = commit.parents()
+                .map(|parent| parent.tree().map(Some))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            let parent_trees = if parent_trees.is_empty() { vec![None] } else { parent_trees };
+            
+            for parent_tree in parent_trees {
+                let mut options = DiffOptions::new();
+                for path in &self.filter.paths {
+                    options.pathspec(path);
+                }
+                let diff = self.repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut options))
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                if diff.deltas().len() == 0 {
+                    return Ok(false);
+                }
+            }
+        }
+        
+        Ok(true)
+    }
+}
+
+impl<'r> Iterator for CommitLog<'r> {
+    type Item = Result<CommitInfo, AgentError>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        
+        loop {
+            let oid = match self.walk.next()? {
+                Ok(oid) => oid,
+                Err(e) => return Some(Err(AgentError::GitError(e.to_string()))),
+            };
+            let commit = match self.repo.find_commit(oid) {
+                Ok(commit) => commit,
+                Err(e) => return Some(Err(AgentError::GitError(e.to_string()))),
+            };
+            
+            match self.matches(&commit) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+            
+            if let Some(remaining) = self.remaining.as_mut() {
+                *remaining -= 1;
+            }
+            return Some(commit_info(self.repo, &commit));
+        }
+    }
+}
+
+/// Options for `GitEngine::blame`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlameSettings {
+    /// Attribute lines whose only change was whitespace to the earlier commit
+    pub ignore_whitespace: bool,
+    /// Revisions to look through, e.g. bulk reformatting commits
+    pub ignore_revs: Vec<String>,
+    /// File listing revisions to ignore, relative to the working tree (usually
+    /// `.git-blame-ignore-revs`); falls back to `blame.ignoreRevsFile`
+    pub ignore_revs_file: Option<PathBuf>,
+}
+
+/// Attribution of a single line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlameLine {
+    /// 1-based line number in the blamed revision
+    pub line: usize,
+    pub commit: String,
+    pub author: CommitIdentity,
+    pub timestamp: DateTime<Utc>,
+    pub content: String,
+}
+
+/// Lines owned by one author
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorShare {
+    pub author: CommitIdentity,
+    pub lines: usize,
+    /// Fraction of the total lines, between 0 and 1
+    pub share: f32,
+    pub last_change: DateTime<Utc>,
+}
+
+/// Aggregated line ownership of a file or directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Ownership {
+    pub total_lines: usize,
+    /// Authors ordered by descending line count
+    pub authors: Vec<AuthorShare>,
+}
+
+impl Ownership {
+    fn from_lines(lines: &[BlameLine]) -> Self {
+        let mut ownership = Self::default();
+        for line in lines {
+            ownership.add(&line.author, 1, line.timestamp);
+        }
+        ownership.finish();
+        ownership
+    }
+    
+    fn add(&mut self, author: &CommitIdentity, lines: usize, last_change: DateTime<Utc>) {
+        self.total_lines += lines;
+        let email = author.email.to_lowercase();
+        match self.authors.iter_mut().find(|a| a.author.email.to_lowercase() == email) {
+            Some(share) => {
+                share.lines += lines;
+                share.last_change = share.last_change.max(last_change);
+            }
+            None => self.authors.push(AuthorShare {
+                author: author.clone(),
+                lines,
+                share: 0.0,
+                last_change,
+            }),
+        }
+    }
+    
+    fn merge(&mut self, other: &Ownership) {
+        for share in &other.authors {
+            self.add(&share.author, share.lines, share.last_change);
+        }
+    }
+    
+    fn finish(&mut self) {
+        let total = self.total_lines.max(1) as f32;
+        for share in &mut self.authors {
+            share.share = share.lines as f32 / total;
+        }
+        self.authors.sort_by_key(|share| Reverse(share.lines));
+    }
+    
+    /// Fewest authors who together own more than half of the lines
+    pub fn bus_factor(&self) -> usize {
+        let mut owned = 0;
+        for (idx, share) in self.authors.iter().enumerate() {
+            owned += share.lines;
+            if owned * 2 > self.total_lines {
+                return idx + 1;
+            }
+        }
+        self.authors.len()
+    }
+}
+
+/// Result of `GitEngine::blame`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileBlame {
+    pub path: String,
+    /// Commit the file was blamed at
+    pub revision: String,
+    pub lines: Vec<BlameLine>,
+    pub ownership: Ownership,
+}
+
+/// Result of `GitEngine::ownership`, keyed by repository-relative path
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OwnershipReport {
+    pub files: BTreeMap<String, Ownership>,
+    /// Every directory containing blamed files, including `""` for the root
+    pub directories: BTreeMap<String, Ownership>,
+}
+
+/// Linked worktree listing entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorktreeInfo {
+    pub name: String,
+    pub path: PathBuf,
+    /// Reason recorded by whoever locked it, e.g. the owning operation
+    pub locked: Option<String>,
+    /// False when the working directory has been deleted or moved
+    pub valid: bool,
+}
+
+/// Stash listing entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StashEntry {
+    pub index: usize,
+    pub message: String,
+    pub commit: String,
+}
+
+/// Submodule state as reported by `GitEngine::submodule_status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubmoduleState {
+    /// Listed in `.gitmodules` but not yet initialized in `.git/config`
+    Uninitialized,
+    /// Initialized but its working directory has not been checked out
+    NotCheckedOut,
+    /// Checked out at the commit recorded by the superproject
+    UpToDate,
+    /// Checked out at a different commit than the superproject records
+    OutOfDate,
+    /// Has local modifications or untracked files
+    Dirty,
+}
+
+/// Submodule listing entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmoduleInfo {
+    pub name: String,
+    /// Path relative to the top-level superproject
+    pub path: String,
+    pub url: Option<String>,
+    /// Commit recorded in the superproject index
+    pub recorded_commit: Option<String>,
+    /// Commit checked out in the submodule working directory
+    pub checked_out_commit: Option<String>,
+    pub state: SubmoduleState,
+}
+
+/// A file of the `HEAD` tree stored as an LFS pointer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LfsFile {
+    pub path: String,
+    pub pointer: LfsPointer,
+}
+
+/// Outcome of `GitEngine::fetch_lfs_objects`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LfsFetchReport {
+    /// Paths whose pointers were replaced with real content
+    pub fetched: Vec<String>,
+    /// Paths that could not be fetched, with the reason
+    pub failed: BTreeMap<String, String>,
+    pub bytes_downloaded: u64,
+}
+
+/// Ref advertised by a git bundle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleRef {
+    pub name: String,
+    pub commit: String,
+}
+
+/// Header and pack summary of a git bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleInfo {
+    pub refs: Vec<BundleRef>,
+    /// Commits the receiving repository must already have; empty for full bundles
+    pub prerequisites: Vec<String>,
+    pub object_count: u32,
+    pub size_bytes: u64,
+}
+
+/// Outcome of `GitEngine::fetch_bundle`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleFetchOutcome {
+    pub bundle: BundleInfo,
+    /// Local refs created or moved
+    pub updated: Vec<String>,
+    /// Local refs left alone because the update was not a fast-forward
+    pub rejected: Vec<String>,
+}
+
+/// Options for `GitEngine::sync`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncOptions {
+    /// Import this bundle into `origin` instead of fetching over the network
+    pub bundle: Option<PathBuf>,
+    /// Remote name or URL that receives a mirror of the primary remote
+    pub mirror_remote: Option<String>,
+}
+
+/// What `GitEngine::sync` did to one ref
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RefSyncOutcome {
+    UpToDate,
+    FastForwarded { from: String, to: String },
+    /// Local commits not yet on the upstream; nothing to do
+    Ahead { ahead: usize },
+    /// Both sides have commits the other lacks; left untouched
+    Diverged { ahead: usize, behind: usize },
+    /// The upstream branch no longer exists on the remote
+    UpstreamGone,
+    /// A fast-forward was possible but would overwrite local changes
+    Skipped { reason: String },
+    /// An imported ref was not a fast-forward of the local one; left untouched
+    Rejected { reason: String },
+    Mirrored,
+    MirrorRejected { reason: String },
+}
+
+/// Per-ref entry of a `SyncReport`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefSync {
+    pub name: String,
+    pub outcome: RefSyncOutcome,
+}
+
+/// Result of `Operation::SyncRepo`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    /// Remotes fetched, or `bundle` when a bundle was imported
+    pub fetched: Vec<String>,
+    /// Remotes that could not be fetched, with the error
+    pub fetch_failed: HashMap<String, String>,
+    /// Refs a bundle import refused to move
+    pub rejected: Vec<RefSync>,
+    /// Local branches with an upstream
+    pub branches: Vec<RefSync>,
+    /// Refs pushed to the mirror remote, when one was requested
+    pub mirrored: Vec<RefSync>,
+    /// Why the mirror push could not run at all, e.g. an unreachable mirror
+    #[serde(default)]
+    pub mirror_failed: Option<String>,
+}
+
+impl SyncReport {
+    /// Branches that need a human because they diverged from their upstream
+    pub fn diverged(&self) -> Vec<&str> {
+        self.branches.iter()
+            .filter(|branch| matches!(branch.outcome, RefSyncOutcome::Diverged { .. }))
+            .map(|branch| branch.name.as_str())
+            .collect()
+    }
+}
 
 /// High-performance Git operations engine
 pub struct GitEngine {
     config: AgentConfig,
+    credentials: Option<Arc<dyn CredentialProvider>>,
+    signer: Option<Arc<dyn CommitSigner>>,
 }
 
 impl GitEngine {
     pub fn new(config: &AgentConfig) -> Result<Self, AgentError> {
         Ok(Self {
             config: config.clone(),
+            credentials: None,
+            signer: None,
         })
     }
     
+    /// Use `provider` to authenticate fetch and push operations
+    pub fn with_credentials(mut self, provider: Arc<dyn CredentialProvider>) -> Self {
+        self.credentials = Some(provider);
+        self
+    }
+    
+    /// Sign every commit the engine creates with `signer`
+    pub fn with_signer(mut self, signer: Arc<dyn CommitSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+    
     /// Clone repository with optimizations
     pub async fn clone_repo(&self, url: &str, path: &Path) -> Result<Repository, AgentError> {
-        let repo = Repository::clone(url, path)
-            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        self.clone_repo_with(url, path, &CloneOptions::default()).await
+    }
+    
+    /// Clone repository with shallow, single-ref, sparse and submodule options
+    pub async fn clone_repo_with(
+        &self,
+        url: &str,
+        path: &Path,
+        options: &CloneOptions,
+    ) -> Result<Repository, AgentError> {
+        self.prepare_credentials().await?;
+        let mut callbacks = self.remote_callbacks();
+        callbacks.transfer_progress(|stats| {
+            // Deltas are only counted once every object has arrived
+            let resolving = stats.total_deltas() > 0 && stats.received_objects() == stats.total_objects();
+            options.report(CloneProgress {
+                stage: if resolving { CloneStage::Resolving } else { CloneStage::Receiving },
+                current: if resolving { stats.indexed_deltas() } else { stats.received_objects() },
+                total: if resolving { stats.total_deltas() } else { stats.total_objects() },
+                received_bytes: stats.received_bytes(),
+            });
+            true
+        });
+        callbacks.sideband_progress(|data| {
+            options.report_remote_message(data);
+            true
+        });
+        
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(callbacks);
+        if let Some(depth) = options.depth {
+            fetch_options.depth(depth as i32);
+        }
+        
+        let mut checkout = CheckoutBuilder::new();
+        checkout.progress(|_, current, total| {
+            options.report(CloneProgress {
+                stage: CloneStage::Checkout,
+                current,
+                total,
+                received_bytes: 0,
+            });
+        });
+        for sparse_path in &options.sparse_paths {
+            checkout.path(sparse_path.as_str());
+            checkout.path(format!("{}/*", sparse_path));
+        }
+        
+        let repo = match &options.reference {
+            Some(CloneReference::Tag(tag)) => {
+                // libgit2 clones insist on the remote's default branch, so a
+                // single-tag clone is an init followed by a narrow fetch
+                let repo = if options.bare {
+                    Repository::init_bare(path)
+                } else {
+                    Repository::init(path)
+                }.map_err(|e| AgentError::GitError(e.to_string()))?;
+                
+                let refspec = format!("+refs/tags/{0}:refs/tags/{0}", tag);
+                repo.remote_with_fetch("origin", url, &refspec)
+                    .and_then(|mut remote| remote.fetch(&[] as &[&str], Some(&mut fetch_options), None))
+                    .map_err(|e| AgentError::from_remote(e, AgentError::GitError))?;
+                
+                self.checkout_tag(&repo, tag, (!options.bare).then_some(checkout))?;
+                repo
+            }
+            reference => {
+                let mut builder = RepoBuilder::new();
+                builder.bare(options.bare);
+                builder.fetch_options(fetch_options);
+                builder.with_checkout(checkout);
+                
+                if let Some(CloneReference::Branch(branch)) = reference {
+                    let refspec = format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch);
+                    builder.branch(branch);
+                    builder.remote_create(move |repo, name, url| {
+                        repo.remote_with_fetch(name, url, &refspec)
+                    });
+                }
+                
+                builder.clone(url, path)
+                    .map_err(|e| AgentError::from_remote(e, AgentError::GitError))?
+            }
+        };
+        
+        if !options.bare {
+            if !options.sparse_paths.is_empty() {
+                self.apply_sparse_checkout(&repo, &options.sparse_paths).await?;
+            }
+            if options.recurse_submodules {
+                self.update_submodules(&repo, true)?;
+            }
+            if options.fetch_lfs {
+                self.fetch_lfs_objects(&repo).await?;
+            }
+        }
+        
         Ok(repo)
     }
     
@@ -30,6 +1089,17 @@ impl GitEngine {
         repo: &Repository,
         message: &str,
         files: &[&str],
+    ) -> Result<String, AgentError> {
+        self.create_commit_with(repo, message, files, &CommitOptions::default()).await
+    }
+    
+    /// Create commit with explicit author and committer identities
+    pub async fn create_commit_with(
+        &self,
+        repo: &Repository,
+        message: &str,
+        files: &[&str],
+        options: &CommitOptions,
     ) -> Result<String, AgentError> {
         // Staging files
         let mut index = repo.index()
@@ -50,26 +1120,3611 @@ impl GitEngine {
         let tree = repo.find_tree(tree_id)
             .map_err(|e| AgentError::GitError(e.to_string()))?;
         
-        let signature = Signature::now("GitHub Agent", "agent@github-mastery.com")
-            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let (author, committer) = self.commit_signatures(repo, options)?;
         
         let parent_commit = repo.head()
             .and_then(|h| h.target().ok_or(git2::Error::from_str("Invalid head")))
             .and_then(|oid| repo.find_commit(oid))
             .ok();
         
-        let parents = parent_commit.as_ref().map(|c| vec![c]).unwrap_or_default();
+        let parents: Vec<&Commit> = parent_commit.iter().collect();
         
-        let commit_id = repo.commit(
+        let commit_id = self.write_commit(
+            repo,
             Some("HEAD"),
-            &signature,
-            &signature,
+            &author,
+            &committer,
             message,
             &tree,
-            &parents.iter().collect::<Vec<_>>(),
-        ).map_err(|e| AgentError::GitError(e.to_string()))?;
+            &parents,
+        )?;
         
         Ok(commit_id.to_string())
     }
-}
-
+    
+    /// Fetch `refspecs` from `remote`, or its configured refspecs when empty
+    pub async fn fetch(
+        &self,
+        repo: &Repository,
+        remote: &str,
+        refspecs: &[&str],
+    ) -> Result<(), AgentError> {
+        self.prepare_credentials().await?;
+        let mut remote = repo.find_remote(remote)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(self.remote_callbacks());
+        
+        remote.fetch(refspecs, Some(&mut options), None)
+            .map_err(|e| AgentError::from_remote(e, AgentError::GitError))?;
+        
+        Ok(())
+    }
+    
+    /// Fetch `branch` from `remote` and integrate it into the checked-out branch
+    pub async fn pull(
+        &self,
+        repo: &Repository,
+        remote: &str,
+        branch: &str,
+        mode: PullMode,
+    ) -> Result<PullOutcome, AgentError> {
+        let refspec = format!("+refs/heads/{0}:refs/remotes/{1}/{0}", branch, remote);
+        self.fetch(repo, remote, &[refspec.as_str()]).await?;
+        
+        let tracking = repo.find_reference(&format!("refs/remotes/{}/{}", remote, branch))
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let fetched = repo.reference_to_annotated_commit(&tracking)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let (analysis, _) = repo.merge_analysis(&[&fetched])
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let fast_forwardable = analysis.is_up_to_date()
+            || analysis.is_fast_forward()
+            || analysis.is_unborn();
+        if mode == PullMode::FastForwardOnly && !fast_forwardable {
+            return Err(AgentError::GitError(format!(
+                "cannot fast-forward to {}/{}: branches have diverged",
+                remote, branch
+            )));
+        }
+        
+        let message = format!("Merge branch '{}' of {}", branch, remote);
+        match self.merge_annotated(repo, &fetched, true, &message)? {
+            MergeOutcome::UpToDate => Ok(PullOutcome::UpToDate),
+            MergeOutcome::FastForward { commit } => Ok(PullOutcome::FastForward { commit }),
+            MergeOutcome::Committed { commit } => Ok(PullOutcome::Merged { commit }),
+            MergeOutcome::Conflicted { conflicts } => {
+                abort_merge(repo)?;
+                let paths: Vec<_> = conflicts.into_iter().map(|c| c.path).collect();
+                Err(AgentError::GitError(format!(
+                    "merge of {}/{} has conflicts in: {}",
+                    remote, branch, paths.join(", ")
+                )))
+            }
+        }
+    }
+    
+    /// Push `refspecs` to `remote`, failing if the remote rejects any of them
+    pub async fn push(
+        &self,
+        repo: &Repository,
+        remote: &str,
+        refspecs: &[&str],
+    ) -> Result<(), AgentError> {
+        self.prepare_credentials().await?;
+        let mut remote = repo.find_remote(remote)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let rejected = RefCell::new(Vec::new());
+        let mut callbacks = self.remote_callbacks();
+        callbacks.push_update_reference(|refname, status| {
+            if let Some(reason) = status {
+                rejected.borrow_mut().push(format!("{} ({})", refname, reason));
+            }
+            Ok(())
+        });
+        
+        let mut options = PushOptions::new();
+        options.remote_callbacks(callbacks);
+        
+        remote.push(refspecs, Some(&mut options))
+            .map_err(|e| AgentError::from_remote(e, AgentError::GitError))?;
+        
+        let rejected = rejected.take();
+        if !rejected.is_empty() {
+            return Err(AgentError::GitError(format!(
+                "push rejected: {}",
+                rejected.join(", ")
+            )));
+        }
+        
+        Ok(())
+    }
+    
+    /// Summarize staged, unstaged, untracked and conflicted paths
+    pub async fn status(&self, repo: &Repository) -> Result<WorkingTreeStatus, AgentError> {
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .renames_head_to_index(true)
+            .renames_index_to_workdir(true);
+        
+        let statuses = repo.statuses(Some(&mut options))
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let index = repo.index()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut result = WorkingTreeStatus::default();
+        for entry in statuses.iter() {
+            let status = entry.status();
+            let path = entry.path().unwrap_or_default().to_string();
+            
+            if status.is_conflicted() {
+                result.conflicted.push(path);
+                continue;
+            }
+            if status.is_wt_new() {
+                result.untracked.push(path);
+                continue;
+            }
+            
+            if let Some(kind) = staged_change_kind(status) {
+                result.staged.push(status_entry(entry.head_to_index(), path.clone(), kind));
+            }
+            if let Some(kind) = unstaged_change_kind(status) {
+                // libgit2 ignores skip-worktree, so paths left out of a sparse
+                // checkout would otherwise show up as deleted
+                if kind == FileChangeKind::Deleted && is_skip_worktree(&index, &path) {
+                    continue;
+                }
+                // Nor does it run the LFS clean filter, so fetched objects
+                // would otherwise differ from the pointers they replaced
+                if kind == FileChangeKind::Modified && is_smudged_lfs(repo, &index, &path) {
+                    continue;
+                }
+                result.unstaged.push(status_entry(entry.index_to_workdir(), path, kind));
+            }
+        }
+        
+        Ok(result)
+    }
+    
+    /// Diff the working tree, index or two revisions as structured hunks
+    pub async fn diff(&self, repo: &Repository, target: &DiffTarget) -> Result<DiffReport, AgentError> {
+        let mut options = DiffOptions::new();
+        
+        let mut diff = match target {
+            DiffTarget::WorkdirToIndex => {
+                options.include_untracked(false);
+                repo.diff_index_to_workdir(None, Some(&mut options))
+            }
+            DiffTarget::IndexToHead => {
+                let head_tree = repo.head().and_then(|h| h.peel_to_tree()).ok();
+                repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut options))
+            }
+            DiffTarget::Revisions { from, to } => {
+                let from_tree = repo.revparse_single(from)
+                    .and_then(|obj| obj.peel_to_tree())
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                let to_tree = repo.revparse_single(to)
+                    .and_then(|obj| obj.peel_to_tree())
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                repo.diff_tree_to_tree(Some(&from_tree), Some(&to_tree), Some(&mut options))
+            }
+        }.map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        diff.find_similar(None)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        diff_report(&diff)
+    }
+    
+    /// Build a `ContributionResult` from the real changes of `commit_hash`
+    pub async fn contribution_result(
+        &self,
+        repo: &Repository,
+        commit_hash: &str,
+        ai_confidence: f32,
+    ) -> Result<ContributionResult, AgentError> {
+        let commit = repo.revparse_single(commit_hash)
+            .and_then(|obj| obj.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let tree = commit.tree()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree().map_err(|e| AgentError::GitError(e.to_string()))?),
+            Err(_) => None,
+        };
+        
+        let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        diff.find_similar(None)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let report = diff_report(&diff)?;
+        
+        Ok(ContributionResult {
+            commit_hash: commit.id().to_string(),
+            message: commit.message().unwrap_or_default().to_string(),
+            files_changed: report.files_changed(),
+            insertions: report.insertions as i32,
+            deletions: report.deletions as i32,
+            ai_confidence,
+        })
+    }
+    
+    /// Create a local branch at `start_point`, or at `HEAD` when `None`
+    pub async fn create_branch(
+        &self,
+        repo: &Repository,
+        name: &str,
+        start_point: Option<&str>,
+    ) -> Result<BranchInfo, AgentError> {
+        let commit = repo.revparse_single(start_point.unwrap_or("HEAD"))
+            .and_then(|obj| obj.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let branch = repo.branch(name, &commit, false)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        branch_info(repo, &branch, BranchKind::Local)
+    }
+    
+    /// Delete a local branch; unless `force`, it must be merged into `HEAD`
+    pub async fn delete_branch(
+        &self,
+        repo: &Repository,
+        name: &str,
+        force: bool,
+    ) -> Result<(), AgentError> {
+        let mut branch = repo.find_branch(name, BranchType::Local)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        if !force {
+            let tip = branch.get().peel_to_commit()
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            let head = repo.head()
+                .and_then(|h| h.peel_to_commit())
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            let merged = tip.id() == head.id()
+                || repo.graph_descendant_of(head.id(), tip.id())
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+            if !merged {
+                return Err(AgentError::GitError(format!(
+                    "branch '{}' is not fully merged into HEAD",
+                    name
+                )));
+            }
+        }
+        
+        branch.delete()
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// Rename a local branch, replacing an existing `new_name` only if `force`
+    pub async fn rename_branch(
+        &self,
+        repo: &Repository,
+        old_name: &str,
+        new_name: &str,
+        force: bool,
+    ) -> Result<BranchInfo, AgentError> {
+        let mut branch = repo.find_branch(old_name, BranchType::Local)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let renamed = branch.rename(new_name, force)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        branch_info(repo, &renamed, BranchKind::Local)
+    }
+    
+    /// Switch the working tree to a local branch, refusing to overwrite local changes
+    pub async fn checkout_branch(&self, repo: &Repository, name: &str) -> Result<(), AgentError> {
+        let branch = repo.find_branch(name, BranchType::Local)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let refname = branch.get().name()
+            .ok_or_else(|| AgentError::GitError("branch name is not valid UTF-8".to_string()))?
+            .to_string();
+        let commit = branch.get().peel_to_commit()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        repo.set_head(&refname)
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// Set the upstream of a local branch (e.g. `origin/main`), or clear it with `None`
+    pub async fn set_upstream(
+        &self,
+        repo: &Repository,
+        name: &str,
+        upstream: Option<&str>,
+    ) -> Result<(), AgentError> {
+        let mut branch = repo.find_branch(name, BranchType::Local)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        branch.set_upstream(upstream)
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// List branches of `kind` (all when `None`) with ahead/behind counts
+    pub async fn list_branches(
+        &self,
+        repo: &Repository,
+        kind: Option<BranchKind>,
+    ) -> Result<Vec<BranchInfo>, AgentError> {
+        let filter = kind.map(|kind| match kind {
+            BranchKind::Local => BranchType::Local,
+            BranchKind::Remote => BranchType::Remote,
+        });
+        let branches = repo.branches(filter)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut result = Vec::new();
+        for branch in branches {
+            let (branch, branch_type) = branch
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            // Skip symbolic refs such as `origin/HEAD`
+            if branch.get().symbolic_target().is_some() {
+                continue;
+            }
+            let kind = match branch_type {
+                BranchType::Local => BranchKind::Local,
+                BranchType::Remote => BranchKind::Remote,
+            };
+            result.push(branch_info(repo, &branch, kind)?);
+        }
+        
+        Ok(result)
+    }
+    
+    /// Create a tag at `target`; annotated when `message` is given, lightweight otherwise
+    pub async fn create_tag(
+        &self,
+        repo: &Repository,
+        name: &str,
+        target: &str,
+        message: Option<&str>,
+    ) -> Result<TagInfo, AgentError> {
+        let object = repo.revparse_single(target)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        match message {
+            Some(message) => {
+                let (_, tagger) = self.commit_signatures(repo, &CommitOptions::default())?;
+                repo.tag(name, &object, &tagger, message, false)
+            }
+            None => repo.tag_lightweight(name, &object, false),
+        }.map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let reference = repo.find_reference(&format!("refs/tags/{}", name))
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        tag_info(repo, name, &reference)
+    }
+    
+    /// Delete a lightweight or annotated tag
+    pub async fn delete_tag(&self, repo: &Repository, name: &str) -> Result<(), AgentError> {
+        repo.tag_delete(name)
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// List tags, optionally filtered by a glob such as `v1.*`
+    pub async fn list_tags(
+        &self,
+        repo: &Repository,
+        pattern: Option<&str>,
+    ) -> Result<Vec<TagInfo>, AgentError> {
+        let names = repo.tag_names(pattern)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut result = Vec::new();
+        for name in names.iter().flatten() {
+            let reference = repo.find_reference(&format!("refs/tags/{}", name))
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            result.push(tag_info(repo, name, &reference)?);
+        }
+        
+        Ok(result)
+    }
+    
+    /// Merge `revision` into the checked-out branch
+    pub async fn merge(
+        &self,
+        repo: &Repository,
+        revision: &str,
+        allow_fast_forward: bool,
+    ) -> Result<MergeOutcome, AgentError> {
+        let (object, reference) = repo.revparse_ext(revision)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let annotated = match &reference {
+            Some(reference) => repo.reference_to_annotated_commit(reference),
+            None => repo.find_annotated_commit(object.id()),
+        }.map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let message = match reference.as_ref().and_then(|r| r.shorthand()) {
+            Some(name) => format!("Merge branch '{}'", name),
+            None => format!("Merge commit '{}'", object.id()),
+        };
+        
+        self.merge_annotated(repo, &annotated, allow_fast_forward, &message)
+    }
+    
+    /// Replay the commits of the checked-out branch that are not in `onto` on top of it
+    pub async fn rebase(&self, repo: &Repository, onto: &str) -> Result<MergeOutcome, AgentError> {
+        let onto_commit = repo.revparse_single(onto)
+            .and_then(|obj| obj.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let head_commit = repo.head()
+            .and_then(|h| h.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        if head_commit.id() == onto_commit.id()
+            || repo.graph_descendant_of(head_commit.id(), onto_commit.id())
+                .map_err(|e| AgentError::GitError(e.to_string()))?
+        {
+            return Ok(MergeOutcome::UpToDate);
+        }
+        
+        if repo.graph_descendant_of(onto_commit.id(), head_commit.id())
+            .map_err(|e| AgentError::GitError(e.to_string()))?
+        {
+            repo.checkout_tree(onto_commit.as_object(), Some(CheckoutBuilder::new().safe()))
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            advance_head(repo, onto_commit.id(), &format!("rebase: fast-forward to {}", onto))?;
+            return Ok(MergeOutcome::FastForward { commit: onto_commit.id().to_string() });
+        }
+        
+        let upstream = repo.find_annotated_commit(onto_commit.id())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let mut rebase = repo.rebase(None, Some(&upstream), None, None)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        self.run_rebase(repo, &mut rebase, None)
+    }
+    
+    /// Apply the changes introduced by `revision` as a new commit on `HEAD`
+    pub async fn cherry_pick(&self, repo: &Repository, revision: &str) -> Result<MergeOutcome, AgentError> {
+        let commit = repo.revparse_single(revision)
+            .and_then(|obj| obj.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        repo.cherrypick(&commit, None)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        self.commit_in_progress(repo)
+    }
+    
+    /// Create a commit undoing the changes introduced by `revision`
+    pub async fn revert(&self, repo: &Repository, revision: &str) -> Result<MergeOutcome, AgentError> {
+        let commit = repo.revparse_single(revision)
+            .and_then(|obj| obj.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        repo.revert(&commit, None)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        self.commit_in_progress(repo)
+    }
+    
+    /// Finish a merge, rebase, cherry-pick or revert whose conflicts have been
+    /// resolved and staged
+    pub async fn continue_operation(&self, repo: &Repository) -> Result<MergeOutcome, AgentError> {
+        match repo.state() {
+            RepositoryState::RebaseMerge => {
+                let mut rebase = repo.open_rebase(None)
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                let current = rebase.operation_current()
+                    .and_then(|idx| rebase.nth(idx))
+                    .map(|op| op.id());
+                self.run_rebase(repo, &mut rebase, current)
+            }
+            RepositoryState::Merge
+            | RepositoryState::CherryPick
+            | RepositoryState::Revert => self.commit_in_progress(repo),
+            state => Err(AgentError::GitError(format!(
+                "no operation to continue (repository state: {:?})",
+                state
+            ))),
+        }
+    }
+    
+    /// Abandon an in-progress merge, rebase, cherry-pick or revert and restore
+    /// the branch to where it was before the operation started
+    pub async fn abort_operation(&self, repo: &Repository) -> Result<(), AgentError> {
+        match repo.state() {
+            RepositoryState::RebaseMerge => {
+                let mut rebase = repo.open_rebase(None)
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                rebase.abort()
+                    .map_err(|e| AgentError::GitError(e.to_string()))
+            }
+            RepositoryState::Merge
+            | RepositoryState::CherryPick
+            | RepositoryState::CherryPickSequence
+            | RepositoryState::Revert
+            | RepositoryState::RevertSequence => abort_merge(repo),
+            RepositoryState::Clean => Ok(()),
+            other => Err(AgentError::GitError(format!("cannot abort {:?} in progress", other))),
+        }
+    }
+    
+    /// Walk history matching `filter`, newest first
+    pub async fn log<'r>(
+        &self,
+        repo: &'r Repository,
+        filter: &LogFilter,
+    ) -> Result<CommitLog<'r>, AgentError> {
+        let mut walk = repo.revwalk()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let sorting = if filter.topo_order {
+            Sort::TOPOLOGICAL | Sort::TIME
+        } else {
+            Sort::TIME
+        };
+        walk.set_sorting(sorting)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let range = filter.range.as_deref().unwrap_or("HEAD");
+        let spec = repo.revparse(range)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        if spec.mode().contains(RevparseMode::SINGLE) {
+            let from = spec.from()
+                .ok_or_else(|| AgentError::GitError(format!("invalid revision: {}", range)))?;
+            walk.push(from.peel_to_commit().map(|c| c.id())
+                .map_err(|e| AgentError::GitError(e.to_string()))?)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+        } else {
+            let (from, to) = match (spec.from(), spec.to()) {
+                (Some(from), Some(to)) => (from, to),
+                _ => return Err(AgentError::GitError(format!("invalid range: {}", range))),
+            };
+            let from = from.peel_to_commit()
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            let to = to.peel_to_commit()
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            
+            walk.push(to.id())
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            if spec.mode().contains(RevparseMode::MERGE_BASE) {
+                // a...b: commits reachable from either side but not both
+                walk.push(from.id())
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                let base = repo.merge_base(from.id(), to.id())
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                walk.hide(base)
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+            } else {
+                walk.hide(from.id())
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+            }
+        }
+        
+        if filter.first_parent {
+            walk.simplify_first_parent()
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+        }
+        
+        let compile = |pattern: &Option<String>| -> Result<Option<Regex>, AgentError> {
+            pattern.as_deref()
+                .map(Regex::new)
+                .transpose()
+                .map_err(|e| AgentError::InternalError(format!("invalid log pattern: {}", e)))
+        };
+        
+        Ok(CommitLog {
+            repo,
+            walk,
+            author: compile(&filter.author)?,
+            message: compile(&filter.message_pattern)?,
+            remaining: filter.limit,
+            filter: filter.clone(),
+        })
+    }
+    
+    /// Attribute every line of `path` at `rev` to the commit that last changed it
+    pub async fn blame(
+        &self,
+        repo: &Repository,
+        path: &str,
+        rev: &str,
+        settings: &BlameSettings,
+    ) -> Result<FileBlame, AgentError> {
+        let ignored = ignored_revisions(repo, settings)?;
+        blame_file(repo, path, rev, settings, &ignored)
+    }
+    
+    /// Blame every text file under `dir` at `rev` and aggregate ownership per
+    /// file and per directory
+    pub async fn ownership(
+        &self,
+        repo: &Repository,
+        dir: &str,
+        rev: &str,
+        settings: &BlameSettings,
+    ) -> Result<OwnershipReport, AgentError> {
+        let ignored = ignored_revisions(repo, settings)?;
+        let commit = repo.revparse_single(rev)
+            .and_then(|obj| obj.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let root = commit.tree()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let dir = dir.trim_matches('/');
+        let tree = if dir.is_empty() {
+            root
+        } else {
+            root.get_path(Path::new(dir))
+                .and_then(|entry| entry.to_object(repo))
+                .and_then(|obj| obj.peel_to_tree())
+                .map_err(|e| AgentError::GitError(e.to_string()))?
+        };
+        
+        let mut files = Vec::new();
+        tree.walk(TreeWalkMode::PreOrder, |parent, entry| {
+            if entry.kind() == Some(ObjectType::Blob) {
+                if let Some(name) = entry.name() {
+                    files.push((format!("{}{}", parent, name), entry.id()));
+                }
+            }
+            TreeWalkResult::Ok
+        }).map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut report = OwnershipReport::default();
+        for (relative, blob_id) in files {
+            let is_binary = repo.find_blob(blob_id)
+                .map(|blob| blob.is_binary())
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            if is_binary {
+                continue;
+            }
+            
+            let path = if dir.is_empty() { relative } else { format!("{}/{}", dir, relative) };
+            let blame = blame_file(repo, &path, rev, settings, &ignored)?;
+            
+            let mut parent = path.as_str();
+            loop {
+                parent = parent.rfind('/').map_or("", |idx| &parent[..idx]);
+                report.directories.entry(parent.to_string()).or_default().merge(&blame.ownership);
+                if parent.is_empty() || parent == dir {
+                    break;
+                }
+            }
+            report.files.insert(path, blame.ownership);
+        }
+        
+        for ownership in report.directories.values_mut() {
+            ownership.finish();
+        }
+        
+        Ok(report)
+    }
+    
+    /// Check out a dedicated linked worktree for one operation. An unlocked
+    /// worktree named `name` at `path` is reused after being reset to `branch`
+    /// (or its own `HEAD`) with local changes discarded; stale ones are pruned
+    /// first. The worktree is locked until `release_worktree` is called.
+    ///
+    /// Queued operations do not use worktrees: `RepoStore` already gives each
+    /// of them exclusive use of a clone. Worktrees are for callers that want
+    /// several checkouts of one clone at the same time.
+    pub async fn acquire_worktree(
+        &self,
+        repo: &Repository,
+        name: &str,
+        path: &Path,
+        branch: Option<&str>,
+    ) -> Result<Repository, AgentError> {
+        let lock_reason = format!("in use by agent operation {}", name);
+        
+        let branch_ref = match branch {
+            Some(branch) => Some(match repo.find_branch(branch, BranchType::Local) {
+                Ok(existing) => existing,
+                Err(_) => {
+                    let head = repo.head()
+                        .and_then(|h| h.peel_to_commit())
+                        .map_err(|e| AgentError::GitError(e.to_string()))?;
+                    repo.branch(branch, &head, false)
+                        .map_err(|e| AgentError::GitError(e.to_string()))?
+                }
+            }.into_reference()),
+            None => None,
+        };
+        
+        if let Ok(worktree) = repo.find_worktree(name) {
+            if worktree.validate().is_ok() && worktree.path() == path {
+                if let WorktreeLockStatus::Locked(reason) = worktree.is_locked()
+                    .map_err(|e| AgentError::GitError(e.to_string()))?
+                {
+                    return Err(AgentError::GitError(format!(
+                        "worktree '{}' is locked: {}",
+                        name,
+                        reason.unwrap_or_default()
+                    )));
+                }
+                
+                // Whatever the previous operation left behind must not leak into this one
+                let checkout = Repository::open_from_worktree(&worktree)
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                if let Some(name) = branch_ref.as_ref().and_then(|reference| reference.name()) {
+                    checkout.set_head(name)
+                        .map_err(|e| AgentError::GitError(e.to_string()))?;
+                }
+                checkout.checkout_head(Some(CheckoutBuilder::new().force().remove_untracked(true)))
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                checkout.cleanup_state()
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                
+                worktree.lock(Some(&lock_reason))
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                return Ok(checkout);
+            }
+            
+            let mut prune = WorktreePruneOptions::new();
+            prune.valid(true).working_tree(true);
+            worktree.prune(Some(&mut prune))
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+        }
+        
+        let mut options = WorktreeAddOptions::new();
+        options.reference(branch_ref.as_ref());
+        let worktree = repo.worktree(name, path, Some(&options))
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        worktree.lock(Some(&lock_reason))
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        Repository::open_from_worktree(&worktree)
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// Unlock a worktree taken with `acquire_worktree` so it can be reused
+    pub async fn release_worktree(&self, repo: &Repository, name: &str) -> Result<(), AgentError> {
+        let worktree = repo.find_worktree(name)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        worktree.unlock()
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// List the linked worktrees of `repo`
+    pub async fn list_worktrees(&self, repo: &Repository) -> Result<Vec<WorktreeInfo>, AgentError> {
+        let names = repo.worktrees()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut result = Vec::new();
+        for name in names.iter().flatten() {
+            let worktree = repo.find_worktree(name)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            let locked = match worktree.is_locked()
+                .map_err(|e| AgentError::GitError(e.to_string()))?
+            {
+                WorktreeLockStatus::Locked(reason) => Some(reason.unwrap_or_default()),
+                WorktreeLockStatus::Unlocked => None,
+            };
+            result.push(WorktreeInfo {
+                name: name.to_string(),
+                path: worktree.path().to_path_buf(),
+                locked,
+                valid: worktree.validate().is_ok(),
+            });
+        }
+        
+        Ok(result)
+    }
+    
+    /// Delete a worktree and its working directory, even if it is locked
+    pub async fn remove_worktree(&self, repo: &Repository, name: &str) -> Result<(), AgentError> {
+        let worktree = repo.find_worktree(name)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut prune = WorktreePruneOptions::new();
+        prune.valid(true).locked(true).working_tree(true);
+        worktree.prune(Some(&mut prune))
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// Drop metadata of unlocked worktrees whose directories no longer exist
+    pub async fn prune_worktrees(&self, repo: &Repository) -> Result<Vec<String>, AgentError> {
+        let names = repo.worktrees()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut pruned = Vec::new();
+        for name in names.iter().flatten() {
+            let worktree = repo.find_worktree(name)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            if worktree.is_prunable(None).map_err(|e| AgentError::GitError(e.to_string()))? {
+                worktree.prune(None)
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                pruned.push(name.to_string());
+            }
+        }
+        
+        Ok(pruned)
+    }
+    
+    /// Stash local changes and return the stash commit id
+    pub async fn stash_save(
+        &self,
+        repo: &mut Repository,
+        message: Option<&str>,
+        include_untracked: bool,
+    ) -> Result<String, AgentError> {
+        let (_, stasher) = self.commit_signatures(repo, &CommitOptions::default())?;
+        let flags = if include_untracked {
+            StashFlags::INCLUDE_UNTRACKED
+        } else {
+            StashFlags::DEFAULT
+        };
+        
+        let stash_id = repo.stash_save2(&stasher, message, Some(flags))
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        Ok(stash_id.to_string())
+    }
+    
+    /// List stashes, most recent first
+    pub async fn stash_list(&self, repo: &mut Repository) -> Result<Vec<StashEntry>, AgentError> {
+        let mut entries = Vec::new();
+        repo.stash_foreach(|index, message, commit| {
+            entries.push(StashEntry {
+                index,
+                message: message.to_string(),
+                commit: commit.to_string(),
+            });
+            true
+        }).map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        Ok(entries)
+    }
+    
+    /// Apply stash `index` and keep it, restoring staged changes as staged
+    pub async fn stash_apply(&self, repo: &mut Repository, index: usize) -> Result<(), AgentError> {
+        let mut options = StashApplyOptions::new();
+        options.reinstantiate_index();
+        
+        repo.stash_apply(index, Some(&mut options))
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// Apply stash `index` and drop it if it applied cleanly
+    pub async fn stash_pop(&self, repo: &mut Repository, index: usize) -> Result<(), AgentError> {
+        let mut options = StashApplyOptions::new();
+        options.reinstantiate_index();
+        
+        repo.stash_pop(index, Some(&mut options))
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// Delete stash `index` without applying it
+    pub async fn stash_drop(&self, repo: &mut Repository, index: usize) -> Result<(), AgentError> {
+        repo.stash_drop(index)
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// Report every submodule, recursing into checked-out ones
+    pub async fn submodule_status(&self, repo: &Repository) -> Result<Vec<SubmoduleInfo>, AgentError> {
+        let mut result = Vec::new();
+        collect_submodule_status(repo, "", &mut result)?;
+        Ok(result)
+    }
+    
+    /// Copy submodule URLs from `.gitmodules` into `.git/config`. Existing
+    /// entries are kept unless `overwrite` is set. Returns the names initialized.
+    pub async fn submodule_init(&self, repo: &Repository, overwrite: bool) -> Result<Vec<String>, AgentError> {
+        let submodules = repo.submodules()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut initialized = Vec::new();
+        for mut submodule in submodules {
+            submodule.init(overwrite)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            initialized.push(submodule.name().unwrap_or_default().to_string());
+        }
+        
+        Ok(initialized)
+    }
+    
+    /// Initialize, clone and check out submodules at their recorded commits
+    pub async fn submodule_update(&self, repo: &Repository, recursive: bool) -> Result<(), AgentError> {
+        self.prepare_credentials().await?;
+        self.update_submodules(repo, recursive)
+    }
+    
+    /// Propagate URL changes in `.gitmodules` to `.git/config` and to each
+    /// submodule's `origin` remote
+    pub async fn submodule_sync(&self, repo: &Repository, recursive: bool) -> Result<(), AgentError> {
+        sync_submodules(repo, recursive)
+    }
+    
+    /// Files of the `HEAD` tree stored as LFS pointers
+    pub async fn lfs_files(&self, repo: &Repository) -> Result<Vec<LfsFile>, AgentError> {
+        let tree = match repo.head() {
+            Ok(head) => head.peel_to_tree()
+                .map_err(|e| AgentError::GitError(e.to_string()))?,
+            Err(_) => return Ok(Vec::new()),
+        };
+        let odb = repo.odb()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut files = Vec::new();
+        let mut walk_error = None;
+        tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() != Some(ObjectType::Blob) {
+                return TreeWalkResult::Ok;
+            }
+            // Only pointer-sized blobs are loaded
+            let small = odb.read_header(entry.id())
+                .map(|(size, _)| (size as u64) < LFS_POINTER_MAX_SIZE)
+                .unwrap_or(false);
+            if !small {
+                return TreeWalkResult::Ok;
+            }
+            match repo.find_blob(entry.id()) {
+                Ok(blob) => {
+                    if let Some(pointer) = LfsPointer::parse(blob.content()) {
+                        files.push(LfsFile {
+                            path: format!("{}{}", dir, entry.name().unwrap_or_default()),
+                            pointer,
+                        });
+                    }
+                    TreeWalkResult::Ok
+                }
+                Err(e) => {
+                    walk_error = Some(e);
+                    TreeWalkResult::Abort
+                }
+            }
+        }).map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        if let Some(e) = walk_error {
+            return Err(AgentError::GitError(e.to_string()));
+        }
+        Ok(files)
+    }
+    
+    /// Replace LFS pointer files in the working tree with their content,
+    /// downloading missing objects into `.git/lfs/objects`. `status` keeps
+    /// listing replaced files as unchanged until their content is edited.
+    pub async fn fetch_lfs_objects(&self, repo: &Repository) -> Result<LfsFetchReport, AgentError> {
+        let workdir = repo.workdir()
+            .ok_or_else(|| AgentError::GitError("repository has no working tree".to_string()))?
+            .to_path_buf();
+        
+        // Files already smudged, or outside a sparse checkout, are left alone
+        let pending: Vec<LfsFile> = self.lfs_files(repo).await?
+            .into_iter()
+            .filter(|file| LfsPointer::read(&workdir.join(&file.path)).is_some())
+            .collect();
+        
+        let mut report = LfsFetchReport::default();
+        if pending.is_empty() {
+            return Ok(report);
+        }
+        
+        let mut missing: Vec<LfsPointer> = pending.iter()
+            .map(|file| file.pointer.clone())
+            .filter(|pointer| !pointer.object_path(repo.path()).exists())
+            .collect();
+        missing.sort_by(|a, b| a.oid.cmp(&b.oid));
+        missing.dedup();
+        
+        let mut download_errors = HashMap::new();
+        if !missing.is_empty() {
+            let client = LfsClient::new(self.lfs_endpoint(repo)?)?;
+            for (pointer, content) in client.download(&missing).await? {
+                match content {
+                    Ok(content) => {
+                        let object_path = pointer.object_path(repo.path());
+                        if let Some(parent) = object_path.parent() {
+                            fs::create_dir_all(parent).await
+                                .map_err(|e| AgentError::InternalError(e.to_string()))?;
+                        }
+                        fs::write(&object_path, &content).await
+                            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+                        report.bytes_downloaded += content.len() as u64;
+                    }
+                    Err(error) => {
+                        download_errors.insert(pointer.oid, error);
+                    }
+                }
+            }
+        }
+        
+        for file in pending {
+            if let Some(error) = download_errors.get(&file.pointer.oid) {
+                report.failed.insert(file.path, error.clone());
+                continue;
+            }
+            
+            match fs::copy(file.pointer.object_path(repo.path()), workdir.join(&file.path)).await {
+                Ok(_) => report.fetched.push(file.path),
+                Err(e) => {
+                    report.failed.insert(file.path, e.to_string());
+                }
+            }
+        }
+        
+        Ok(report)
+    }
+    
+    /// Write a bundle of `refs` to `output`. With `basis`, objects reachable
+    /// from it are left out and the boundary commits become prerequisites.
+    pub async fn create_bundle(
+        &self,
+        repo: &Repository,
+        output: &Path,
+        refs: &[&str],
+        basis: Option<&str>,
+    ) -> Result<BundleInfo, AgentError> {
+        let mut bundle_refs = Vec::new();
+        let mut tag_objects = Vec::new();
+        for name in refs {
+            let reference = if *name == "HEAD" {
+                repo.head()
+            } else {
+                repo.resolve_reference_from_short_name(name)
+            }.map_err(|e| AgentError::GitError(e.to_string()))?;
+            let full_name = if *name == "HEAD" {
+                "HEAD".to_string()
+            } else {
+                reference.name().unwrap_or_default().to_string()
+            };
+            let target = reference.resolve()
+                .ok()
+                .and_then(|resolved| resolved.target())
+                .ok_or_else(|| AgentError::GitError(format!("{} does not point at an object", name)))?;
+            if repo.find_tag(target).is_ok() {
+                tag_objects.push(target);
+            }
+            bundle_refs.push(BundleRef {
+                name: full_name,
+                commit: target.to_string(),
+            });
+        }
+        
+        let basis = match basis {
+            Some(basis) => Some(repo.revparse_single(basis)
+                .and_then(|obj| obj.peel_to_commit())
+                .map_err(|e| AgentError::GitError(e.to_string()))?
+                .id()),
+            None => None,
+        };
+        let walk_refs = || -> Result<Revwalk<'_>, git2::Error> {
+            let mut walk = repo.revwalk()?;
+            for bundle_ref in &bundle_refs {
+                let commit = repo.find_object(Oid::from_str(&bundle_ref.commit)?, None)?
+                    .peel_to_commit()?;
+                walk.push(commit.id())?;
+            }
+            if let Some(basis) = basis {
+                walk.hide(basis)?;
+            }
+            Ok(walk)
+        };
+        
+        // Parents outside the bundled history are what the receiver must have
+        let included: HashSet<Oid> = walk_refs()
+            .and_then(|walk| walk.collect::<Result<_, _>>())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let mut prerequisites = Vec::new();
+        let mut seen = HashSet::new();
+        for oid in &included {
+            let commit = repo.find_commit(*oid)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            for parent in commit.parents() {
+                if !included.contains(&parent.id()) && seen.insert(parent.id()) {
+                    prerequisites.push((parent.id(), parent.summary().unwrap_or_default().to_string()));
+                }
+            }
+        }
+        prerequisites.sort();
+        
+        let mut builder = repo.packbuilder()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let mut walk = walk_refs()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        builder.insert_walk(&mut walk)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        for tag in tag_objects {
+            builder.insert_object(tag, None)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+        }
+        if builder.object_count() == 0 {
+            return Err(AgentError::GitError("refusing to create an empty bundle".to_string()));
+        }
+        let mut pack = git2::Buf::new();
+        builder.write_buf(&mut pack)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut content = format!("{}\n", BUNDLE_SIGNATURE_V2).into_bytes();
+        for (oid, summary) in &prerequisites {
+            content.extend_from_slice(format!("-{} {}\n", oid, summary).as_bytes());
+        }
+        for bundle_ref in &bundle_refs {
+            content.extend_from_slice(format!("{} {}\n", bundle_ref.commit, bundle_ref.name).as_bytes());
+        }
+        content.push(b'\n');
+        content.extend_from_slice(&pack);
+        
+        fs::write(output, &content).await
+            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        
+        Ok(BundleInfo {
+            refs: bundle_refs,
+            prerequisites: prerequisites.into_iter().map(|(oid, _)| oid.to_string()).collect(),
+            object_count: builder.object_count() as u32,
+            size_bytes: content.len() as u64,
+        })
+    }
+    
+    /// Check that `bundle` is well formed and that `repo` has its prerequisites
+    pub async fn verify_bundle(&self, repo: &Repository, bundle: &Path) -> Result<BundleInfo, AgentError> {
+        let content = fs::read(bundle).await
+            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        let (info, _) = parse_bundle(&content)?;
+        check_bundle_prerequisites(repo, &info)?;
+        Ok(info)
+    }
+    
+    /// Import the objects of `bundle` and update local refs through `refspecs`
+    /// such as `refs/heads/*:refs/remotes/origin/*`. Refspecs without a leading
+    /// `+` only accept fast-forwards.
+    pub async fn fetch_bundle(
+        &self,
+        repo: &Repository,
+        bundle: &Path,
+        refspecs: &[&str],
+    ) -> Result<BundleFetchOutcome, AgentError> {
+        let content = fs::read(bundle).await
+            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        let (info, pack) = parse_bundle(&content)?;
+        check_bundle_prerequisites(repo, &info)?;
+        
+        let odb = repo.odb()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let mut writer = odb.packwriter()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        writer.write_all(pack)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        writer.commit()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut updated = Vec::new();
+        let mut rejected = Vec::new();
+        for bundle_ref in &info.refs {
+            let new = Oid::from_str(&bundle_ref.commit)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            for spec in refspecs {
+                let (destination, force) = match refspec_destination(spec, &bundle_ref.name) {
+                    Some(mapping) => mapping,
+                    None => continue,
+                };
+                
+                let old = repo.refname_to_id(&destination).ok();
+                if old == Some(new) {
+                    continue;
+                }
+                let fast_forward = match old {
+                    Some(old) => {
+                        let peel = |oid: Oid| repo.find_object(oid, None).and_then(|obj| obj.peel_to_commit());
+                        match (peel(old), peel(new)) {
+                            (Ok(old), Ok(new)) => repo.graph_descendant_of(new.id(), old.id())
+                                .map_err(|e| AgentError::GitError(e.to_string()))?,
+                            _ => false,
+                        }
+                    }
+                    None => true,
+                };
+                if !fast_forward && !force {
+                    rejected.push(destination);
+                    continue;
+                }
+                
+                repo.reference(&destination, new, true, &format!("fetch: bundle {}", bundle.display()))
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                updated.push(destination);
+            }
+        }
+        
+        Ok(BundleFetchOutcome {
+            bundle: info,
+            updated,
+            rejected,
+        })
+    }
+    
+    /// Fetch every remote (or import `options.bundle`), fast-forward local
+    /// branches that track an upstream and optionally mirror the primary
+    /// remote's branches and tags to `options.mirror_remote`
+    pub async fn sync(&self, repo: &Repository, options: &SyncOptions) -> Result<SyncReport, AgentError> {
+        self.prepare_credentials().await?;
+        let mut report = SyncReport::default();
+        
+        match &options.bundle {
+            Some(bundle) => {
+                let outcome = self.fetch_bundle(repo, bundle, BUNDLE_IMPORT_REFSPECS).await?;
+                report.fetched.push("bundle".to_string());
+                report.rejected = outcome.rejected.into_iter()
+                    .map(|name| RefSync {
+                        name,
+                        outcome: RefSyncOutcome::Rejected { reason: "not a fast-forward".to_string() },
+                    })
+                    .collect();
+            }
+            None => {
+                let remotes = repo.remotes()
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                for name in remotes.iter().flatten() {
+                    let mut remote = repo.find_remote(name)
+                        .map_err(|e| AgentError::GitError(e.to_string()))?;
+                    let mut fetch_options = FetchOptions::new();
+                    fetch_options.remote_callbacks(self.remote_callbacks());
+                    fetch_options.prune(FetchPrune::On);
+                    fetch_options.download_tags(AutotagOption::All);
+                    // One unreachable remote should not keep the others from syncing
+                    match remote.fetch(&[] as &[&str], Some(&mut fetch_options), None) {
+                        Ok(()) => report.fetched.push(name.to_string()),
+                        Err(e) => {
+                            report.fetch_failed.insert(name.to_string(), e.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        
+        let branches = repo.branches(Some(BranchType::Local))
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        for branch in branches {
+            let (branch, _) = branch.map_err(|e| AgentError::GitError(e.to_string()))?;
+            let has_upstream = repo.branch_upstream_name(branch.get().name().unwrap_or_default()).is_ok();
+            if !has_upstream {
+                continue;
+            }
+            let name = branch.name().ok().flatten().unwrap_or_default().to_string();
+            let outcome = self.fast_forward_branch(repo, branch)?;
+            report.branches.push(RefSync { name, outcome });
+        }
+        
+        // An unreachable mirror should not discard what was fetched above
+        if let Some(mirror) = &options.mirror_remote {
+            match self.mirror_push(repo, mirror) {
+                Ok(mirrored) => report.mirrored = mirrored,
+                Err(e) => report.mirror_failed = Some(e.to_string()),
+            }
+        }
+        
+        Ok(report)
+    }
+    
+    /// Detach `HEAD` at a tag fetched by a single-tag clone
+    fn checkout_tag(
+        &self,
+        repo: &Repository,
+        tag: &str,
+        checkout: Option<CheckoutBuilder<'_>>,
+    ) -> Result<(), AgentError> {
+        let commit = repo.revparse_single(&format!("refs/tags/{}", tag))
+            .and_then(|obj| obj.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        if let Some(mut checkout) = checkout {
+            checkout.force();
+            repo.checkout_tree(commit.as_object(), Some(&mut checkout))
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+        }
+        
+        repo.set_head_detached(commit.id())
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// Mark everything outside `paths` as skip-worktree and record the sparse
+    /// patterns so the git CLI keeps honoring them
+    async fn apply_sparse_checkout(
+        &self,
+        repo: &Repository,
+        paths: &[String],
+    ) -> Result<(), AgentError> {
+        let head_tree = repo.head()
+            .and_then(|h| h.peel_to_tree())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut index = repo.index()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        index.read_tree(&head_tree)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let entries: Vec<_> = index.iter().collect();
+        for mut entry in entries {
+            let path = String::from_utf8_lossy(&entry.path).into_owned();
+            if !paths.iter().any(|p| path == *p || path.starts_with(&format!("{}/", p))) {
+                entry.flags |= INDEX_ENTRY_EXTENDED;
+                entry.flags_extended |= INDEX_ENTRY_SKIP_WORKTREE;
+                index.add(&entry)
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+            }
+        }
+        index.write()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut config = repo.config()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        config.set_bool("core.sparseCheckout", true)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let info_dir = repo.path().join("info");
+        fs::create_dir_all(&info_dir).await
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let patterns: String = paths.iter().map(|p| format!("/{}\n", p)).collect();
+        fs::write(info_dir.join("sparse-checkout"), patterns).await
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        Ok(())
+    }
+    
+    /// Initialize and update every submodule, descending into nested ones when `recursive`
+    fn update_submodules(&self, repo: &Repository, recursive: bool) -> Result<(), AgentError> {
+        let submodules = repo.submodules()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        for mut submodule in submodules {
+            let mut fetch_options = FetchOptions::new();
+            fetch_options.remote_callbacks(self.remote_callbacks());
+            let mut update_options = SubmoduleUpdateOptions::new();
+            update_options.fetch(fetch_options);
+            
+            submodule.update(true, Some(&mut update_options))
+                .map_err(|e| AgentError::from_remote(e, AgentError::GitError))?;
+            
+            if recursive {
+                let nested = submodule.open()
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                self.update_submodules(&nested, true)?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Resolve author and committer: per-call override, then `AgentConfig`,
+    /// then the repository's `user.name`/`user.email`
+    fn commit_signatures(
+        &self,
+        repo: &Repository,
+        options: &CommitOptions,
+    ) -> Result<(Signature<'static>, Signature<'static>), AgentError> {
+        let resolve = |explicit: Option<&CommitIdentity>, configured: Option<&CommitIdentity>| {
+            match explicit.or(configured) {
+                Some(identity) => identity.signature(),
+                None => repo.signature().map_err(|e| AgentError::GitError(format!(
+                    "no commit identity configured; set user.name and user.email: {}",
+                    e
+                ))),
+            }
+        };
+        
+        let author = resolve(options.author.as_ref(), self.config.commit_author.as_ref())?;
+        let committer = resolve(options.committer.as_ref(), self.config.commit_committer.as_ref())?;
+        Ok((author, committer))
+    }
+    
+    /// Write a commit object, signing it when a signer is configured, and
+    /// move `update_ref` to it
+    #[allow(clippy::too_many_arguments)]
+    fn write_commit(
+        &self,
+        repo: &Repository,
+        update_ref: Option<&str>,
+        author: &Signature<'_>,
+        committer: &Signature<'_>,
+        message: &str,
+        tree: &Tree<'_>,
+        parents: &[&Commit<'_>],
+    ) -> Result<Oid, AgentError> {
+        let signer = match &self.signer {
+            Some(signer) => signer,
+            None => {
+                return repo.commit(update_ref, author, committer, message, tree, parents)
+                    .map_err(|e| AgentError::GitError(e.to_string()));
+            }
+        };
+        
+        let buffer = repo.commit_create_buffer(author, committer, message, tree, parents)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let content = buffer.as_str()
+            .ok_or_else(|| AgentError::GitError("commit buffer is not valid UTF-8".to_string()))?;
+        
+        let signature = signer.sign(content)?;
+        let commit_id = repo.commit_signed(content, &signature, None)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let summary = message.lines().next().unwrap_or_default();
+        let log_message = format!("commit: {}", summary);
+        match update_ref {
+            Some("HEAD") => advance_head(repo, commit_id, &log_message)?,
+            Some(name) => {
+                repo.reference(name, commit_id, true, &log_message)
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+            }
+            None => {}
+        }
+        
+        Ok(commit_id)
+    }
+    
+    /// Merge an annotated commit into `HEAD`, fast-forwarding when allowed
+    fn merge_annotated(
+        &self,
+        repo: &Repository,
+        incoming: &AnnotatedCommit<'_>,
+        allow_fast_forward: bool,
+        message: &str,
+    ) -> Result<MergeOutcome, AgentError> {
+        let (analysis, _) = repo.merge_analysis(&[incoming])
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        if analysis.is_up_to_date() {
+            return Ok(MergeOutcome::UpToDate);
+        }
+        
+        if analysis.is_unborn() || (allow_fast_forward && analysis.is_fast_forward()) {
+            let target = repo.find_object(incoming.id(), None)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            repo.checkout_tree(&target, Some(CheckoutBuilder::new().safe()))
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            advance_head(repo, incoming.id(), &format!("merge: fast-forward to {}", incoming.id()))?;
+            return Ok(MergeOutcome::FastForward { commit: incoming.id().to_string() });
+        }
+        
+        repo.merge(&[incoming], None, Some(CheckoutBuilder::new().safe()))
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        // Prefer our message over the one libgit2 derives for MERGE_MSG
+        std::fs::write(repo.path().join("MERGE_MSG"), format!("{}\n", message))
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        self.commit_in_progress(repo)
+    }
+    
+    /// Commit the staged result of an in-progress merge, cherry-pick or revert,
+    /// or report its conflicts
+    fn commit_in_progress(&self, repo: &Repository) -> Result<MergeOutcome, AgentError> {
+        let mut index = repo.index()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        if index.has_conflicts() {
+            return Ok(MergeOutcome::Conflicted { conflicts: conflict_entries(&index)? });
+        }
+        
+        let tree_id = index.write_tree()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let tree = repo.find_tree(tree_id)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let head_commit = repo.head()
+            .and_then(|h| h.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let mut parents = vec![head_commit];
+        let (mut author, committer) = self.commit_signatures(repo, &CommitOptions::default())?;
+        
+        match repo.state() {
+            RepositoryState::Merge => {
+                // `mergehead_foreach` needs `&mut Repository`, so read MERGE_HEAD directly
+                let merge_heads = std::fs::read_to_string(repo.path().join("MERGE_HEAD"))
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                for line in merge_heads.lines().filter(|l| !l.trim().is_empty()) {
+                    let commit = Oid::from_str(line.trim())
+                        .and_then(|oid| repo.find_commit(oid))
+                        .map_err(|e| AgentError::GitError(e.to_string()))?;
+                    parents.push(commit);
+                }
+            }
+            RepositoryState::CherryPick => {
+                let picked = repo.revparse_single("CHERRY_PICK_HEAD")
+                    .and_then(|obj| obj.peel_to_commit())
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                author = picked.author().to_owned();
+            }
+            _ => {}
+        }
+        
+        let message = repo.message()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let parent_refs: Vec<&Commit> = parents.iter().collect();
+        let commit_id = self.write_commit(
+            repo,
+            Some("HEAD"),
+            &author,
+            &committer,
+            &message,
+            &tree,
+            &parent_refs,
+        )?;
+        
+        repo.cleanup_state()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        Ok(MergeOutcome::Committed { commit: commit_id.to_string() })
+    }
+    
+    /// Drive an on-disk rebase to completion, committing `pending` first when
+    /// resuming after conflicts
+    fn run_rebase(
+        &self,
+        repo: &Repository,
+        rebase: &mut Rebase<'_>,
+        pending: Option<Oid>,
+    ) -> Result<MergeOutcome, AgentError> {
+        if let Some(original) = pending {
+            if let Some(conflicts) = self.commit_rebase_step(repo, original)? {
+                return Ok(MergeOutcome::Conflicted { conflicts });
+            }
+        }
+        
+        for operation in rebase.by_ref() {
+            let operation = operation.map_err(|e| AgentError::GitError(e.to_string()))?;
+            if let Some(conflicts) = self.commit_rebase_step(repo, operation.id())? {
+                return Ok(MergeOutcome::Conflicted { conflicts });
+            }
+        }
+        
+        rebase.finish(None)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        let head = repo.head()
+            .and_then(|h| h.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        Ok(MergeOutcome::Committed { commit: head.id().to_string() })
+    }
+    
+    /// Commit the applied rebase step through `write_commit` so rewritten
+    /// commits are signed like any other; already-applied changes are dropped
+    fn commit_rebase_step(
+        &self,
+        repo: &Repository,
+        original: Oid,
+    ) -> Result<Option<Vec<ConflictEntry>>, AgentError> {
+        let mut index = repo.index()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        if index.has_conflicts() {
+            return Ok(Some(conflict_entries(&index)?));
+        }
+        
+        let tree_id = index.write_tree()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let head_commit = repo.head()
+            .and_then(|h| h.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        if head_commit.tree_id() == tree_id {
+            return Ok(None);
+        }
+        
+        let tree = repo.find_tree(tree_id)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let original = repo.find_commit(original)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let (_, committer) = self.commit_signatures(repo, &CommitOptions::default())?;
+        
+        self.write_commit(
+            repo,
+            Some("HEAD"),
+            &original.author(),
+            &committer,
+            original.message().unwrap_or_default(),
+            &tree,
+            &[&head_commit],
+        )?;
+        
+        Ok(None)
+    }
+    
+    /// LFS endpoint: `AgentConfig`, then `lfs.url` from git config or
+    /// `.lfsconfig`, then derived from the `origin` URL
+    fn lfs_endpoint(&self, repo: &Repository) -> Result<String, AgentError> {
+        if let Some(endpoint) = &self.config.lfs_endpoint {
+            return Ok(endpoint.clone());
+        }
+        if let Ok(url) = repo.config().and_then(|config| config.get_string("lfs.url")) {
+            return Ok(url);
+        }
+        if let Some(workdir) = repo.workdir() {
+            let lfsconfig = workdir.join(".lfsconfig");
+            if let Ok(url) = git2::Config::open(&lfsconfig).and_then(|config| config.get_string("lfs.url")) {
+                return Ok(url);
+            }
+        }
+        
+        repo.find_remote("origin")
+            .ok()
+            .and_then(|remote| remote.url().and_then(lfs_endpoint_for_remote))
+            .ok_or_else(|| AgentError::GitError("no LFS endpoint configured".to_string()))
+    }
+    
+    /// Move `branch` to its upstream when that is a fast-forward
+    fn fast_forward_branch(&self, repo: &Repository, branch: Branch<'_>) -> Result<RefSyncOutcome, AgentError> {
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            Err(_) => return Ok(RefSyncOutcome::UpstreamGone),
+        };
+        let (local, remote) = match (branch.get().target(), upstream.get().target()) {
+            (Some(local), Some(remote)) => (local, remote),
+            _ => return Ok(RefSyncOutcome::UpstreamGone),
+        };
+        if local == remote {
+            return Ok(RefSyncOutcome::UpToDate);
+        }
+        
+        let (ahead, behind) = repo.graph_ahead_behind(local, remote)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        match (ahead, behind) {
+            (0, _) => {}
+            (ahead, 0) => return Ok(RefSyncOutcome::Ahead { ahead }),
+            (ahead, behind) => return Ok(RefSyncOutcome::Diverged { ahead, behind }),
+        }
+        
+        if branch.is_head() {
+            let target = repo.find_commit(remote)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            if let Err(e) = repo.checkout_tree(target.as_object(), Some(CheckoutBuilder::new().safe())) {
+                return Ok(RefSyncOutcome::Skipped { reason: e.message().to_string() });
+            }
+        }
+        
+        let mut reference = branch.into_reference();
+        reference.set_target(remote, "sync: fast-forward")
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        Ok(RefSyncOutcome::FastForwarded {
+            from: local.to_string(),
+            to: remote.to_string(),
+        })
+    }
+    
+    /// Force-push the primary remote's branches and all tags to `mirror`,
+    /// a configured remote name or a URL
+    fn mirror_push(&self, repo: &Repository, mirror: &str) -> Result<Vec<RefSync>, AgentError> {
+        let remotes = repo.remotes()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let primary = if remotes.iter().flatten().any(|name| name == "origin") {
+            "origin".to_string()
+        } else {
+            remotes.iter().flatten()
+                .find(|name| *name != mirror)
+                .ok_or_else(|| AgentError::GitError("no remote to mirror".to_string()))?
+                .to_string()
+        };
+        
+        let tracking_prefix = format!("refs/remotes/{}/", primary);
+        let references = repo.references()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let mut refspecs = Vec::new();
+        for reference in references {
+            let reference = reference.map_err(|e| AgentError::GitError(e.to_string()))?;
+            if reference.symbolic_target().is_some() {
+                continue;
+            }
+            let name = reference.name().unwrap_or_default();
+            if let Some(branch) = name.strip_prefix(&tracking_prefix) {
+                refspecs.push(format!("+{}:refs/heads/{}", name, branch));
+            } else if name.starts_with("refs/tags/") {
+                refspecs.push(format!("+{0}:{0}", name));
+            }
+        }
+        
+        let mut remote = match repo.find_remote(mirror) {
+            Ok(remote) => remote,
+            Err(_) => repo.remote_anonymous(mirror)
+                .map_err(|e| AgentError::GitError(e.to_string()))?,
+        };
+        
+        let rejected = RefCell::new(HashMap::new());
+        let mut callbacks = self.remote_callbacks();
+        callbacks.push_update_reference(|refname, status| {
+            if let Some(reason) = status {
+                rejected.borrow_mut().insert(refname.to_string(), reason.to_string());
+            }
+            Ok(())
+        });
+        let mut push_options = PushOptions::new();
+        push_options.remote_callbacks(callbacks);
+        
+        remote.push(&refspecs, Some(&mut push_options))
+            .map_err(|e| AgentError::from_remote(e, |message| {
+                AgentError::GitError(format!("mirroring to {}: {}", mirror, message))
+            }))?;
+        
+        let mut rejected = rejected.take();
+        Ok(refspecs.iter()
+            .filter_map(|spec| spec.split_once(':').map(|(_, destination)| destination.to_string()))
+            .map(|name| {
+                let outcome = match rejected.remove(&name) {
+                    Some(reason) => RefSyncOutcome::MirrorRejected { reason },
+                    None => RefSyncOutcome::Mirrored,
+                };
+                RefSync { name, outcome }
+            })
+            .collect())
+    }
+    
+    /// Give the credential provider a chance to resolve credentials ahead of a remote operation
+    async fn prepare_credentials(&self) -> Result<(), AgentError> {
+        match &self.credentials {
+            Some(provider) => provider.prepare().await,
+            None => Ok(()),
+        }
+    }
+    
+    /// Build remote callbacks wired to the configured credential provider
+    fn remote_callbacks(&self) -> RemoteCallbacks<'_> {
+        let mut callbacks = RemoteCallbacks::new();
+        
+        if let Some(provider) = &self.credentials {
+            let attempts = Cell::new(0);
+            callbacks.credentials(move |url, username_from_url, allowed| {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() > MAX_CREDENTIAL_ATTEMPTS {
+                    return Err(git2::Error::from_str("authentication failed: credentials rejected"));
+                }
+                provider.credentials(url, username_from_url, allowed)
+            });
+        }
+        
+        callbacks
+    }
+}
+
+/// Point `HEAD` (or the branch it refers to, even if unborn) at `commit_id`
+fn advance_head(repo: &Repository, commit_id: Oid, log_message: &str) -> Result<(), AgentError> {
+    match repo.head() {
+        Ok(mut head) => {
+            head.set_target(commit_id, log_message)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+        }
+        Err(_) => {
+            let head = repo.find_reference("HEAD")
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            let branch = head.symbolic_target()
+                .ok_or_else(|| AgentError::GitError("HEAD is not a symbolic reference".to_string()))?;
+            repo.reference(branch, commit_id, false, log_message)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Describe every conflicted path in `index` with its base, ours and theirs blobs
+fn conflict_entries(index: &git2::Index) -> Result<Vec<ConflictEntry>, AgentError> {
+    let conflicts = index.conflicts()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    
+    let mut entries = Vec::new();
+    for conflict in conflicts {
+        let conflict = conflict.map_err(|e| AgentError::GitError(e.to_string()))?;
+        let path = conflict.our.as_ref()
+            .or(conflict.their.as_ref())
+            .or(conflict.ancestor.as_ref())
+            .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+            .unwrap_or_default();
+        entries.push(ConflictEntry {
+            path,
+            ancestor: conflict.ancestor.map(|entry| entry.id.to_string()),
+            ours: conflict.our.map(|entry| entry.id.to_string()),
+            theirs: conflict.their.map(|entry| entry.id.to_string()),
+        });
+    }
+    Ok(entries)
+}
+
+/// Discard an in-progress merge, cherry-pick or revert. Only the paths the
+/// operation changes are restored to `HEAD`; libgit2 refuses to start one over
+/// local changes to those paths, so other uncommitted work is left alone.
+fn abort_merge(repo: &Repository) -> Result<(), AgentError> {
+    let head = repo.head()
+        .and_then(|h| h.peel_to_commit())
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    let head_tree = head.tree()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    let workdir = repo.workdir()
+        .ok_or_else(|| AgentError::GitError("cannot abort in a bare repository".to_string()))?;
+    
+    let mut index = repo.index()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    let mut paths = operation_paths(repo, &head)?;
+    paths.extend(conflict_entries(&index)?.into_iter().map(|conflict| conflict.path));
+    
+    let mut restored = Vec::new();
+    for path in &paths {
+        // Drops the staged entry and any conflict stages; `HEAD`'s is put back below
+        let _ = index.remove_path(Path::new(path));
+        if head_tree.get_path(Path::new(path)).is_ok() {
+            restored.push(path.as_str());
+            continue;
+        }
+        
+        // Added by the operation
+        let file = workdir.join(path);
+        if file.is_file() {
+            std::fs::remove_file(&file)
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+        }
+    }
+    index.write()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    
+    if !restored.is_empty() {
+        repo.reset_default(Some(head.as_object()), restored.iter())
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force();
+        for path in &restored {
+            checkout.path(*path);
+        }
+        repo.checkout_head(Some(&mut checkout))
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+    }
+    
+    repo.cleanup_state()
+        .map_err(|e| AgentError::GitError(e.to_string()))
+}
+
+/// Paths changed by the commits an in-progress merge, cherry-pick or revert applies
+fn operation_paths(repo: &Repository, head: &Commit<'_>) -> Result<HashSet<String>, AgentError> {
+    let commit_at = |name: &str| {
+        repo.revparse_single(name)
+            .and_then(|obj| obj.peel_to_commit())
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    };
+    fn parent_tree<'r>(commit: &Commit<'r>) -> Option<Tree<'r>> {
+        commit.parent(0).and_then(|parent| parent.tree()).ok()
+    }
+    
+    let mut changes: Vec<(Option<Tree<'_>>, Commit<'_>)> = Vec::new();
+    match repo.state() {
+        RepositoryState::Merge => {
+            let merge_heads = std::fs::read_to_string(repo.path().join("MERGE_HEAD"))
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            for line in merge_heads.lines().filter(|l| !l.trim().is_empty()) {
+                let theirs = Oid::from_str(line.trim())
+                    .and_then(|oid| repo.find_commit(oid))
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                let base = repo.merge_base(head.id(), theirs.id())
+                    .and_then(|base| repo.find_commit(base))
+                    .and_then(|base| base.tree())
+                    .ok();
+                changes.push((base, theirs));
+            }
+        }
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+            let picked = commit_at("CHERRY_PICK_HEAD")?;
+            changes.push((parent_tree(&picked), picked));
+        }
+        RepositoryState::Revert | RepositoryState::RevertSequence => {
+            let reverted = commit_at("REVERT_HEAD")?;
+            changes.push((parent_tree(&reverted), reverted));
+        }
+        _ => {}
+    }
+    
+    let mut paths = HashSet::new();
+    for (base, commit) in &changes {
+        let tree = commit.tree()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let diff = repo.diff_tree_to_tree(base.as_ref(), Some(&tree), None)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        for delta in diff.deltas() {
+            paths.extend(delta_path(delta.old_file().path()));
+            paths.extend(delta_path(delta.new_file().path()));
+        }
+    }
+    Ok(paths)
+}
+
+fn staged_change_kind(status: Status) -> Option<FileChangeKind> {
+    if status.is_index_renamed() {
+        Some(FileChangeKind::Renamed)
+    } else if status.is_index_new() {
+        Some(FileChangeKind::Added)
+    } else if status.is_index_deleted() {
+        Some(FileChangeKind::Deleted)
+    } else if status.is_index_typechange() {
+        Some(FileChangeKind::TypeChange)
+    } else if status.is_index_modified() {
+        Some(FileChangeKind::Modified)
+    } else {
+        None
+    }
+}
+
+fn unstaged_change_kind(status: Status) -> Option<FileChangeKind> {
+    if status.is_wt_renamed() {
+        Some(FileChangeKind::Renamed)
+    } else if status.is_wt_deleted() {
+        Some(FileChangeKind::Deleted)
+    } else if status.is_wt_typechange() {
+        Some(FileChangeKind::TypeChange)
+    } else if status.is_wt_modified() {
+        Some(FileChangeKind::Modified)
+    } else {
+        None
+    }
+}
+
+fn status_entry(delta: Option<DiffDelta<'_>>, path: String, kind: FileChangeKind) -> StatusEntry {
+    let (path, old_path) = match (kind, delta) {
+        (FileChangeKind::Renamed, Some(delta)) => (
+            delta_path(delta.new_file().path()).unwrap_or(path),
+            delta_path(delta.old_file().path()),
+        ),
+        _ => (path, None),
+    };
+    StatusEntry { path, old_path, kind }
+}
+
+fn is_skip_worktree(index: &git2::Index, path: &str) -> bool {
+    index.get_path(Path::new(path), 0)
+        .map(|entry| entry.flags_extended & INDEX_ENTRY_SKIP_WORKTREE != 0)
+        .unwrap_or(false)
+}
+
+/// Whether the index holds an LFS pointer for `path` and the working tree
+/// holds exactly the object it points at
+fn is_smudged_lfs(repo: &Repository, index: &git2::Index, path: &str) -> bool {
+    let (entry, workdir, odb) = match (index.get_path(Path::new(path), 0), repo.workdir(), repo.odb()) {
+        (Some(entry), Some(workdir), Ok(odb)) => (entry, workdir, odb),
+        _ => return false,
+    };
+    let small = odb.read_header(entry.id)
+        .map(|(size, _)| (size as u64) < LFS_POINTER_MAX_SIZE)
+        .unwrap_or(false);
+    let pointer = match repo.find_blob(entry.id).ok().filter(|_| small) {
+        Some(blob) => LfsPointer::parse(blob.content()),
+        None => None,
+    };
+    let pointer = match pointer {
+        Some(pointer) => pointer,
+        None => return false,
+    };
+    
+    let file = workdir.join(path);
+    let same_size = std::fs::metadata(&file).is_ok_and(|meta| meta.len() == pointer.size);
+    same_size && std::fs::read(&file).is_ok_and(|content| pointer.verify(&content).is_ok())
+}
+
+fn delta_path(path: Option<&Path>) -> Option<String> {
+    path.map(|p| p.to_string_lossy().replace('\\', "/"))
+}
+
+fn delta_change_kind(delta: &DiffDelta<'_>) -> FileChangeKind {
+    match delta.status() {
+        Delta::Added | Delta::Untracked => FileChangeKind::Added,
+        Delta::Deleted => FileChangeKind::Deleted,
+        Delta::Renamed => FileChangeKind::Renamed,
+        Delta::Typechange => FileChangeKind::TypeChange,
+        _ => FileChangeKind::Modified,
+    }
+}
+
+/// Convert a libgit2 diff into per-file hunks with line numbers and counts
+fn diff_report(diff: &Diff<'_>) -> Result<DiffReport, AgentError> {
+    let mut report = DiffReport::default();
+    
+    for idx in 0..diff.deltas().len() {
+        let patch = Patch::from_diff(diff, idx)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        let delta = match diff.get_delta(idx) {
+            Some(delta) => delta,
+            None => continue,
+        };
+        
+        let kind = delta_change_kind(&delta);
+        let new_path = delta_path(delta.new_file().path());
+        let old_path = delta_path(delta.old_file().path());
+        let path = match kind {
+            FileChangeKind::Deleted => old_path.clone(),
+            _ => new_path.or_else(|| old_path.clone()),
+        }.unwrap_or_default();
+        
+        let mut file = FileDiff {
+            path,
+            old_path: if kind == FileChangeKind::Renamed { old_path } else { None },
+            kind,
+            binary: delta.flags().is_binary(),
+            insertions: 0,
+            deletions: 0,
+            hunks: Vec::new(),
+        };
+        
+        if let Some(patch) = patch {
+            for hunk_idx in 0..patch.num_hunks() {
+                let (hunk, line_count) = patch.hunk(hunk_idx)
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                
+                let mut lines = Vec::with_capacity(line_count);
+                for line_idx in 0..line_count {
+                    let line = patch.line_in_hunk(hunk_idx, line_idx)
+                        .map_err(|e| AgentError::GitError(e.to_string()))?;
+                    let kind = match line.origin() {
+                        '+' => DiffLineKind::Addition,
+                        '-' => DiffLineKind::Deletion,
+                        ' ' => DiffLineKind::Context,
+                        _ => continue,
+                    };
+                    match kind {
+                        DiffLineKind::Addition => file.insertions += 1,
+                        DiffLineKind::Deletion => file.deletions += 1,
+                        DiffLineKind::Context => {}
+                    }
+                    lines.push(DiffLine {
+                        kind,
+                        old_lineno: line.old_lineno(),
+                        new_lineno: line.new_lineno(),
+                        content: String::from_utf8_lossy(line.content()).into_owned(),
+                    });
+                }
+                
+                file.hunks.push(DiffHunk {
+                    header: String::from_utf8_lossy(hunk.header()).trim_end().to_string(),
+                    old_start: hunk.old_start(),
+                    old_lines: hunk.old_lines(),
+                    new_start: hunk.new_start(),
+                    new_lines: hunk.new_lines(),
+                    lines,
+                });
+            }
+        }
+        
+        report.insertions += file.insertions;
+        report.deletions += file.deletions;
+        report.files.push(file);
+    }
+    
+    Ok(report)
+}
+
+fn branch_info(repo: &Repository, branch: &Branch<'_>, kind: BranchKind) -> Result<BranchInfo, AgentError> {
+    let name = branch.name()
+        .map_err(|e| AgentError::GitError(e.to_string()))?
+        .unwrap_or_default()
+        .to_string();
+    let commit = branch.get().peel_to_commit()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    
+    let mut info = BranchInfo {
+        name,
+        kind,
+        commit: commit.id().to_string(),
+        is_head: branch.is_head(),
+        upstream: None,
+        ahead: 0,
+        behind: 0,
+    };
+    
+    if kind == BranchKind::Local {
+        if let Ok(upstream) = branch.upstream() {
+            let upstream_commit = upstream.get().peel_to_commit()
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            let (ahead, behind) = repo.graph_ahead_behind(commit.id(), upstream_commit.id())
+                .map_err(|e| AgentError::GitError(e.to_string()))?;
+            info.upstream = upstream.name().ok().flatten().map(String::from);
+            info.ahead = ahead;
+            info.behind = behind;
+        }
+    }
+    
+    Ok(info)
+}
+
+fn tag_info(repo: &Repository, name: &str, reference: &Reference<'_>) -> Result<TagInfo, AgentError> {
+    let commit = reference.peel_to_commit()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    let tag = reference.target().and_then(|oid| repo.find_tag(oid).ok());
+    
+    Ok(TagInfo {
+        name: name.to_string(),
+        commit: commit.id().to_string(),
+        annotated: tag.is_some(),
+        message: tag.as_ref().and_then(|t| t.message()).map(String::from),
+        tagger: tag.as_ref().and_then(|t| t.tagger()).map(|sig| CommitIdentity::new(
+            sig.name().unwrap_or_default(),
+            sig.email().unwrap_or_default(),
+        )),
+    })
+}
+
+fn git_time(time: git2::Time) -> DateTime<Utc> {
+    Utc.timestamp_opt(time.seconds(), 0).single().unwrap_or_default()
+}
+
+/// Diff a commit against its first parent, or the empty tree for a root commit
+fn first_parent_diff<'r>(repo: &'r Repository, commit: &Commit<'_>) -> Result<Diff<'r>, AgentError> {
+    let tree = commit.tree()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree().map_err(|e| AgentError::GitError(e.to_string()))?),
+        Err(_) => None,
+    };
+    repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)
+        .map_err(|e| AgentError::GitError(e.to_string()))
+}
+
+fn commit_info(repo: &Repository, commit: &Commit<'_>) -> Result<CommitInfo, AgentError> {
+    let stats = first_parent_diff(repo, commit)?
+        .stats()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    
+    let message = commit.message().unwrap_or_default().to_string();
+    let trailers = git2::message_trailers_strs(&message)
+        .map(|trailers| {
+            trailers.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    
+    let author = commit.author();
+    let committer = commit.committer();
+    
+    Ok(CommitInfo {
+        id: commit.id().to_string(),
+        parents: commit.parent_ids().map(|id| id.to_string()).collect(),
+        author: CommitIdentity::new(
+            author.name().unwrap_or_default(),
+            author.email().unwrap_or_default(),
+        ),
+        committer: CommitIdentity::new(
+            committer.name().unwrap_or_default(),
+            committer.email().unwrap_or_default(),
+        ),
+        authored_at: git_time(author.when()),
+        committed_at: git_time(committer.when()),
+        summary: commit.summary().unwrap_or_default().to_string(),
+        message,
+        trailers,
+        files_changed: stats.files_changed(),
+        insertions: stats.insertions(),
+        deletions: stats.deletions(),
+    })
+}
+
+/// Collect the commits named in `settings` and in the ignore-revs file
+fn ignored_revisions(repo: &Repository, settings: &BlameSettings) -> Result<HashSet<Oid>, AgentError> {
+    let mut revisions = settings.ignore_revs.clone();
+    
+    let file = match &settings.ignore_revs_file {
+        Some(file) => Some(file.clone()),
+        None => repo.config()
+            .and_then(|config| config.get_path("blame.ignoreRevsFile"))
+            .ok(),
+    };
+    if let Some(file) = file {
+        let path = match repo.workdir() {
+            Some(workdir) if file.is_relative() => workdir.join(&file),
+            _ => file,
+        };
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| AgentError::GitError(format!("cannot read {}: {}", path.display(), e)))?;
+        revisions.extend(
+            contents.lines()
+                .map(|line| line.split('#').next().unwrap_or_default().trim())
+                .filter(|line| !line.is_empty())
+                .map(String::from),
+        );
+    }
+    
+    revisions.iter()
+        .map(|rev| {
+            repo.revparse_single(rev)
+                .and_then(|obj| obj.peel_to_commit())
+                .map(|commit| commit.id())
+                .map_err(|e| AgentError::GitError(format!("invalid ignored revision {}: {}", rev, e)))
+        })
+        .collect()
+}
+
+fn blame_at<'r>(
+    repo: &'r Repository,
+    path: &str,
+    commit: Oid,
+    settings: &BlameSettings,
+) -> Result<Blame<'r>, AgentError> {
+    let mut options = BlameOptions::new();
+    options
+        .newest_commit(commit)
+        .ignore_whitespace(settings.ignore_whitespace)
+        .use_mailmap(true);
+    repo.blame_file(Path::new(path), Some(&mut options))
+        .map_err(|e| AgentError::GitError(e.to_string()))
+}
+
+fn blame_file(
+    repo: &Repository,
+    path: &str,
+    rev: &str,
+    settings: &BlameSettings,
+    ignored: &HashSet<Oid>,
+) -> Result<FileBlame, AgentError> {
+    let commit = repo.revparse_single(rev)
+        .and_then(|obj| obj.peel_to_commit())
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    let blob = commit.tree()
+        .and_then(|tree| tree.get_path(Path::new(path)))
+        .and_then(|entry| repo.find_blob(entry.id()))
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    let content = String::from_utf8_lossy(blob.content()).into_owned();
+    
+    let blame = blame_at(repo, path, commit.id(), settings)?;
+    let mut parent_blames: HashMap<Oid, Blame<'_>> = HashMap::new();
+    
+    let mut lines = Vec::new();
+    for (idx, text) in content.lines().enumerate() {
+        let line = idx + 1;
+        let hunk = match blame.get_line(line) {
+            Some(hunk) => hunk,
+            None => continue,
+        };
+        
+        let mut owner = hunk.final_commit_id();
+        let mut signature = hunk.final_signature().to_owned();
+        
+        if ignored.contains(&owner) {
+            let mut origin_line = hunk.orig_start_line() + (line - hunk.final_start_line());
+            // Follow the line into the parent of each ignored commit until a
+            // commit that is not ignored owns it
+            while ignored.contains(&owner) {
+                let (parent, parent_line) = match line_in_parent(repo, owner, path, origin_line)? {
+                    Some(found) => found,
+                    None => break,
+                };
+                if let Entry::Vacant(entry) = parent_blames.entry(parent) {
+                    entry.insert(blame_at(repo, path, parent, settings)?);
+                }
+                let parent_hunk = match parent_blames[&parent].get_line(parent_line) {
+                    Some(parent_hunk) => parent_hunk,
+                    None => break,
+                };
+                owner = parent_hunk.final_commit_id();
+                signature = parent_hunk.final_signature().to_owned();
+                origin_line = parent_hunk.orig_start_line() + (parent_line - parent_hunk.final_start_line());
+            }
+        }
+        
+        lines.push(BlameLine {
+            line,
+            commit: owner.to_string(),
+            author: CommitIdentity::new(
+                signature.name().unwrap_or_default(),
+                signature.email().unwrap_or_default(),
+            ),
+            timestamp: git_time(signature.when()),
+            content: text.to_string(),
+        });
+    }
+    
+    Ok(FileBlame {
+        path: path.to_string(),
+        revision: commit.id().to_string(),
+        ownership: Ownership::from_lines(&lines),
+        lines,
+    })
+}
+
+/// Map `line` of `path` as of `commit` to the matching line in its first parent.
+/// Lines inside a changed hunk map to the same offset in the old side of the
+/// hunk; lines that were purely added have no counterpart.
+fn line_in_parent(
+    repo: &Repository,
+    commit: Oid,
+    path: &str,
+    line: usize,
+) -> Result<Option<(Oid, usize)>, AgentError> {
+    let commit = repo.find_commit(commit)
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    let parent = match commit.parent(0) {
+        Ok(parent) => parent,
+        Err(_) => return Ok(None),
+    };
+    let parent_tree = parent.tree()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    if parent_tree.get_path(Path::new(path)).is_err() {
+        return Ok(None);
+    }
+    let tree = commit.tree()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    
+    let mut options = DiffOptions::new();
+    options.pathspec(path).context_lines(0);
+    let diff = repo.diff_tree_to_tree(Some(&parent_tree), Some(&tree), Some(&mut options))
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    
+    let mut offset: i64 = 0;
+    if diff.deltas().len() > 0 {
+        if let Some(patch) = Patch::from_diff(&diff, 0).map_err(|e| AgentError::GitError(e.to_string()))? {
+            for idx in 0..patch.num_hunks() {
+                let (hunk, _) = patch.hunk(idx)
+                    .map_err(|e| AgentError::GitError(e.to_string()))?;
+                let (old_start, old_lines) = (hunk.old_start() as usize, hunk.old_lines() as usize);
+                let (new_start, new_lines) = (hunk.new_start() as usize, hunk.new_lines() as usize);
+                
+                // A pure deletion hunk sits after `new_start`
+                if line < new_start || (new_lines == 0 && line <= new_start) {
+                    break;
+                }
+                if line < new_start + new_lines {
+                    if old_lines == 0 {
+                        return Ok(None);
+                    }
+                    let mapped = old_start + (line - new_start).min(old_lines - 1);
+                    return Ok(Some((parent.id(), mapped)));
+                }
+                offset = (old_start + old_lines) as i64 - (new_start + new_lines) as i64;
+            }
+        }
+    }
+    
+    Ok(Some((parent.id(), (line as i64 + offset) as usize)))
+}
+
+/// Append the status of `repo`'s submodules, and theirs, to `result`
+fn collect_submodule_status(
+    repo: &Repository,
+    prefix: &str,
+    result: &mut Vec<SubmoduleInfo>,
+) -> Result<(), AgentError> {
+    let submodules = repo.submodules()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    let config = repo.config()
+        .and_then(|mut config| config.snapshot())
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    
+    for submodule in submodules {
+        let name = submodule.name().unwrap_or_default().to_string();
+        let status = repo.submodule_status(&name, SubmoduleIgnore::None)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        // `IN_CONFIG` only means listed in `.gitmodules`; init writes the URL to `.git/config`
+        let initialized = config.get_string(&format!("submodule.{}.url", name)).is_ok();
+        let state = if !initialized {
+            SubmoduleState::Uninitialized
+        } else if status.contains(SubmoduleStatus::WD_UNINITIALIZED) {
+            SubmoduleState::NotCheckedOut
+        } else if status.contains(SubmoduleStatus::WD_MODIFIED) {
+            SubmoduleState::OutOfDate
+        } else if status.intersects(
+            SubmoduleStatus::WD_INDEX_MODIFIED
+                | SubmoduleStatus::WD_WD_MODIFIED
+                | SubmoduleStatus::WD_UNTRACKED,
+        ) {
+            SubmoduleState::Dirty
+        } else {
+            SubmoduleState::UpToDate
+        };
+        
+        let path = format!("{}{}", prefix, submodule.path().to_string_lossy());
+        result.push(SubmoduleInfo {
+            name,
+            path: path.clone(),
+            url: submodule.url().map(String::from),
+            recorded_commit: submodule.index_id().map(|id| id.to_string()),
+            checked_out_commit: submodule.workdir_id().map(|id| id.to_string()),
+            state,
+        });
+        
+        let checked_out = !matches!(state, SubmoduleState::Uninitialized | SubmoduleState::NotCheckedOut);
+        if checked_out {
+            if let Ok(nested) = submodule.open() {
+                collect_submodule_status(&nested, &format!("{}/", path), result)?;
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Apply `.gitmodules` URLs to the config and remotes of `repo`'s submodules
+fn sync_submodules(repo: &Repository, recursive: bool) -> Result<(), AgentError> {
+    let submodules = repo.submodules()
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    
+    for mut submodule in submodules {
+        submodule.sync()
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+        
+        if recursive {
+            if let Ok(nested) = submodule.open() {
+                sync_submodules(&nested, true)?;
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Split a bundle file into its header and packfile, checking the pack checksum
+fn parse_bundle(content: &[u8]) -> Result<(BundleInfo, &[u8]), AgentError> {
+    let invalid = |reason: &str| AgentError::GitError(format!("invalid bundle: {}", reason));
+    
+    let header_end = content.windows(2)
+        .position(|window| window == b"\n\n")
+        .ok_or_else(|| invalid("missing header terminator"))?;
+    let header = std::str::from_utf8(&content[..header_end])
+        .map_err(|_| invalid("header is not UTF-8"))?;
+    let pack = &content[header_end + 2..];
+    
+    let mut lines = header.lines();
+    match lines.next() {
+        Some(BUNDLE_SIGNATURE_V2) | Some(BUNDLE_SIGNATURE_V3) => {}
+        _ => return Err(invalid("unsupported signature")),
+    }
+    
+    let mut refs = Vec::new();
+    let mut prerequisites = Vec::new();
+    for line in lines {
+        if let Some(capability) = line.strip_prefix('@') {
+            if capability != "object-format=sha1" {
+                return Err(invalid(&format!("unsupported capability {}", capability)));
+            }
+        } else if let Some(prerequisite) = line.strip_prefix('-') {
+            let oid = prerequisite.split(' ').next().unwrap_or_default();
+            Oid::from_str(oid).map_err(|_| invalid("bad prerequisite"))?;
+            prerequisites.push(oid.to_string());
+        } else {
+            let (oid, name) = line.split_once(' ').ok_or_else(|| invalid("bad ref line"))?;
+            Oid::from_str(oid).map_err(|_| invalid("bad ref line"))?;
+            refs.push(BundleRef {
+                name: name.to_string(),
+                commit: oid.to_string(),
+            });
+        }
+    }
+    
+    if pack.len() < 32 || &pack[..4] != b"PACK" {
+        return Err(invalid("missing packfile"));
+    }
+    let (body, checksum) = pack.split_at(pack.len() - 20);
+    if digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, body).as_ref() != checksum {
+        return Err(invalid("packfile checksum mismatch"));
+    }
+    let object_count = u32::from_be_bytes([pack[8], pack[9], pack[10], pack[11]]);
+    
+    Ok((
+        BundleInfo {
+            refs,
+            prerequisites,
+            object_count,
+            size_bytes: content.len() as u64,
+        },
+        pack,
+    ))
+}
+
+fn check_bundle_prerequisites(repo: &Repository, info: &BundleInfo) -> Result<(), AgentError> {
+    let missing: Vec<&str> = info.prerequisites.iter()
+        .filter(|oid| {
+            Oid::from_str(oid)
+                .and_then(|oid| repo.find_commit(oid))
+                .is_err()
+        })
+        .map(String::as_str)
+        .collect();
+    
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(AgentError::GitError(format!(
+            "repository lacks prerequisite commits: {}",
+            missing.join(", ")
+        )))
+    }
+}
+
+/// Map `name` through a `[+]src:dst` refspec with at most one `*`;
+/// returns the destination and whether non-fast-forward updates are allowed
+fn refspec_destination(spec: &str, name: &str) -> Option<(String, bool)> {
+    let (force, spec) = match spec.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => (false, spec),
+    };
+    let (source, destination) = spec.split_once(':')?;
+    
+    match source.split_once('*') {
+        Some((prefix, suffix)) => {
+            let matched = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            Some((destination.replacen('*', matched, 1), force))
+        }
+        None if source == name => Some((destination.to_string(), force)),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    
+    /// A bare remote with one commit on `main` and two clones of it
+    struct Fixture {
+        dir: TempDir,
+        engine: GitEngine,
+        remote: Repository,
+        alice: Repository,
+        bob: Repository,
+    }
+    
+    fn with_identity(repo: Repository) -> Repository {
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        drop(config);
+        repo
+    }
+    
+    async fn fixture() -> Fixture {
+        let dir = TempDir::new().unwrap();
+        let remote_path = dir.path().join("remote.git");
+        let mut init = git2::RepositoryInitOptions::new();
+        init.bare(true).initial_head("main");
+        let remote = Repository::init_opts(&remote_path, &init).unwrap();
+        
+        let engine = GitEngine::new(&AgentConfig::default()).unwrap();
+        let alice = with_identity(Repository::clone(remote_path.to_str().unwrap(), dir.path().join("alice")).unwrap());
+        alice.set_head("refs/heads/main").unwrap();
+        commit_file(&engine, &alice, "README.md", "hello\n").await;
+        engine.push(&alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        
+        let bob = with_identity(Repository::clone(remote_path.to_str().unwrap(), dir.path().join("bob")).unwrap());
+        Fixture { dir, engine, remote, alice, bob }
+    }
+    
+    async fn commit_file(engine: &GitEngine, repo: &Repository, path: &str, contents: &str) -> String {
+        std::fs::write(repo.workdir().unwrap().join(path), contents).unwrap();
+        engine.create_commit(repo, &format!("Update {}", path), &[path]).await.unwrap()
+    }
+    
+    fn read(repo: &Repository, path: &str) -> String {
+        std::fs::read_to_string(repo.workdir().unwrap().join(path)).unwrap()
+    }
+    
+    fn head_id(repo: &Repository) -> String {
+        repo.head().unwrap().target().unwrap().to_string()
+    }
+    
+    fn remote_url(fx: &Fixture) -> String {
+        format!("file://{}", fx.remote.path().display())
+    }
+    
+    /// Serve the fixture remote over smart HTTP through `git http-backend`;
+    /// libgit2 only honours `depth` on stateless transports. `None` where git
+    /// is not installed.
+    fn serve_http(fx: &Fixture) -> Option<String> {
+        let exec_path = std::process::Command::new("git").arg("--exec-path").output().ok()?;
+        let backend = PathBuf::from(String::from_utf8(exec_path.stdout).ok()?.trim()).join("git-http-backend");
+        if !backend.exists() {
+            return None;
+        }
+        
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").ok()?;
+        let url = format!("http://{}/remote.git", listener.local_addr().ok()?);
+        let root = fx.dir.path().to_path_buf();
+        // git2 blocks the test's runtime while cloning, so the server gets its own thread
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = run_http_backend(&backend, &root, stream);
+            }
+        });
+        Some(url)
+    }
+    
+    /// Answer one request by running `backend` as a CGI script
+    fn run_http_backend(backend: &Path, root: &Path, stream: std::net::TcpStream) -> std::io::Result<()> {
+        use std::io::{BufRead, BufReader, Read};
+        
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        let mut parts = request.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let target = parts.next().unwrap_or_default().to_string();
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+        
+        let mut content_type = String::new();
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                match name.to_ascii_lowercase().as_str() {
+                    "content-type" => content_type = value.trim().to_string(),
+                    "content-length" => content_length = value.trim().parse().unwrap_or(0),
+                    _ => {}
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        
+        let mut child = std::process::Command::new(backend)
+            .env("GIT_PROJECT_ROOT", root)
+            .env("GIT_HTTP_EXPORT_ALL", "1")
+            .env("REQUEST_METHOD", method)
+            .env("PATH_INFO", path)
+            .env("QUERY_STRING", query)
+            .env("CONTENT_TYPE", content_type)
+            .env("CONTENT_LENGTH", content_length.to_string())
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .spawn()?;
+        child.stdin.take().map(|mut stdin| stdin.write_all(&body)).transpose()?;
+        let output = child.wait_with_output()?;
+        
+        let split = output.stdout.windows(4).position(|window| window == b"\r\n\r\n").unwrap_or(0);
+        let headers = String::from_utf8_lossy(&output.stdout[..split]);
+        let content = output.stdout.get(split + 4..).unwrap_or_default();
+        let status = headers.lines()
+            .find_map(|line| line.strip_prefix("Status:"))
+            .unwrap_or("200 OK")
+            .trim();
+        let mut response = format!("HTTP/1.1 {}\r\n", status);
+        for line in headers.lines().filter(|line| !line.starts_with("Status:")) {
+            response.push_str(line);
+            response.push_str("\r\n");
+        }
+        response.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", content.len()));
+        
+        let mut stream = stream;
+        stream.write_all(response.as_bytes())?;
+        stream.write_all(content)
+    }
+    
+    #[tokio::test]
+    async fn shallow_clone_fetches_only_recent_history() {
+        let fx = fixture().await;
+        commit_file(&fx.engine, &fx.alice, "a.txt", "a\n").await;
+        let latest = commit_file(&fx.engine, &fx.alice, "b.txt", "b\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        let Some(url) = serve_http(&fx) else {
+            return;
+        };
+        
+        let path = fx.dir.path().join("shallow");
+        let clone = fx.engine.clone_repo_with(&url, &path, &CloneOptions::new().depth(1)).await.unwrap();
+        
+        assert!(clone.is_shallow());
+        assert_eq!(head_id(&clone), latest);
+        let mut walk = clone.revwalk().unwrap();
+        walk.push_head().unwrap();
+        assert_eq!(walk.count(), 1);
+    }
+    
+    #[tokio::test]
+    async fn single_branch_clone_checks_out_only_that_branch() {
+        let fx = fixture().await;
+        let main = head_id(&fx.alice);
+        let feature = fx.alice.branch("feature", &fx.alice.find_commit(Oid::from_str(&main).unwrap()).unwrap(), false).unwrap();
+        drop(feature);
+        fx.alice.set_head("refs/heads/feature").unwrap();
+        let tip = commit_file(&fx.engine, &fx.alice, "feature.txt", "feature\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/feature:refs/heads/feature"]).await.unwrap();
+        
+        let path = fx.dir.path().join("single");
+        let clone = fx.engine.clone_repo_with(&remote_url(&fx), &path, &CloneOptions::new().branch("feature")).await.unwrap();
+        
+        assert_eq!(clone.head().unwrap().shorthand(), Some("feature"));
+        assert_eq!(head_id(&clone), tip);
+        assert_eq!(read(&clone, "feature.txt"), "feature\n");
+        assert!(clone.find_reference("refs/remotes/origin/feature").is_ok());
+        assert!(clone.find_reference("refs/remotes/origin/main").is_err());
+    }
+    
+    #[tokio::test]
+    async fn bare_clone_has_no_working_tree() {
+        let fx = fixture().await;
+        
+        let path = fx.dir.path().join("bare.git");
+        let clone = fx.engine.clone_repo_with(&remote_url(&fx), &path, &CloneOptions::new().bare(true)).await.unwrap();
+        
+        assert!(clone.is_bare());
+        assert!(clone.workdir().is_none());
+        assert_eq!(head_id(&clone), head_id(&fx.alice));
+    }
+    
+    #[tokio::test]
+    async fn sparse_clone_checks_out_only_the_requested_paths() {
+        let fx = fixture().await;
+        std::fs::create_dir_all(fx.alice.workdir().unwrap().join("docs")).unwrap();
+        std::fs::create_dir_all(fx.alice.workdir().unwrap().join("src")).unwrap();
+        commit_file(&fx.engine, &fx.alice, "docs/guide.md", "guide\n").await;
+        commit_file(&fx.engine, &fx.alice, "src/lib.rs", "lib\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        
+        let path = fx.dir.path().join("sparse");
+        let options = CloneOptions::new().sparse_paths(["docs/"]);
+        let clone = fx.engine.clone_repo_with(&remote_url(&fx), &path, &options).await.unwrap();
+        
+        assert_eq!(read(&clone, "docs/guide.md"), "guide\n");
+        assert!(!path.join("src/lib.rs").exists());
+        assert!(!path.join("README.md").exists());
+        
+        let index = clone.index().unwrap();
+        for skipped in ["src/lib.rs", "README.md"] {
+            let entry = index.get_path(Path::new(skipped), 0).unwrap();
+            assert_ne!(entry.flags_extended & INDEX_ENTRY_SKIP_WORKTREE, 0, "{}", skipped);
+        }
+        let kept = index.get_path(Path::new("docs/guide.md"), 0).unwrap();
+        assert_eq!(kept.flags_extended & INDEX_ENTRY_SKIP_WORKTREE, 0);
+        assert!(clone.config().unwrap().get_bool("core.sparseCheckout").unwrap());
+        let patterns = std::fs::read_to_string(clone.path().join("info/sparse-checkout")).unwrap();
+        assert_eq!(patterns, "/docs\n");
+    }
+    
+    #[tokio::test]
+    async fn clone_progress_does_not_report_empty_resolving_stages() {
+        let fx = fixture().await;
+        let reports = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let options = CloneOptions::new().on_progress(move |progress| sink.lock().push(progress.clone()));
+        
+        fx.engine.clone_repo_with(&remote_url(&fx), &fx.dir.path().join("progress"), &options).await.unwrap();
+        
+        let reports = reports.lock();
+        assert!(!reports.is_empty());
+        assert!(reports.iter().all(|progress| progress.stage != CloneStage::Resolving || progress.total > 0));
+    }
+    
+    #[tokio::test]
+    async fn fetch_updates_remote_tracking_branch() {
+        let fx = fixture().await;
+        let commit = commit_file(&fx.engine, &fx.alice, "a.txt", "a\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        
+        fx.engine.fetch(&fx.bob, "origin", &[]).await.unwrap();
+        
+        let tracking = fx.bob.find_reference("refs/remotes/origin/main").unwrap();
+        assert_eq!(tracking.target().unwrap().to_string(), commit);
+        assert!(!fx.bob.workdir().unwrap().join("a.txt").exists());
+    }
+    
+    #[tokio::test]
+    async fn pull_fast_forwards() {
+        let fx = fixture().await;
+        let commit = commit_file(&fx.engine, &fx.alice, "a.txt", "a\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        
+        let outcome = fx.engine.pull(&fx.bob, "origin", "main", PullMode::FastForwardOnly).await.unwrap();
+        
+        assert_eq!(outcome, PullOutcome::FastForward { commit: commit.clone() });
+        assert_eq!(head_id(&fx.bob), commit);
+        assert_eq!(read(&fx.bob, "a.txt"), "a\n");
+        
+        let again = fx.engine.pull(&fx.bob, "origin", "main", PullMode::FastForwardOnly).await.unwrap();
+        assert_eq!(again, PullOutcome::UpToDate);
+    }
+    
+    #[tokio::test]
+    async fn fast_forward_only_pull_refuses_diverged_branches() {
+        let fx = fixture().await;
+        commit_file(&fx.engine, &fx.alice, "a.txt", "a\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        let local = commit_file(&fx.engine, &fx.bob, "b.txt", "b\n").await;
+        
+        let err = fx.engine.pull(&fx.bob, "origin", "main", PullMode::FastForwardOnly).await.unwrap_err();
+        
+        assert!(err.to_string().contains("diverged"), "{}", err);
+        assert_eq!(head_id(&fx.bob), local);
+        assert_eq!(fx.bob.state(), RepositoryState::Clean);
+    }
+    
+    #[tokio::test]
+    async fn merge_pull_commits_diverged_branches() {
+        let fx = fixture().await;
+        let theirs = commit_file(&fx.engine, &fx.alice, "a.txt", "a\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        let ours = commit_file(&fx.engine, &fx.bob, "b.txt", "b\n").await;
+        
+        let outcome = fx.engine.pull(&fx.bob, "origin", "main", PullMode::Merge).await.unwrap();
+        
+        let commit = match outcome {
+            PullOutcome::Merged { commit } => commit,
+            other => panic!("expected a merge commit, got {:?}", other),
+        };
+        let merge = fx.bob.find_commit(Oid::from_str(&commit).unwrap()).unwrap();
+        let parents: Vec<_> = merge.parent_ids().map(|id| id.to_string()).collect();
+        assert_eq!(parents, vec![ours, theirs]);
+        assert_eq!(read(&fx.bob, "a.txt"), "a\n");
+        assert_eq!(read(&fx.bob, "b.txt"), "b\n");
+        assert_eq!(fx.bob.state(), RepositoryState::Clean);
+    }
+    
+    #[tokio::test]
+    async fn conflicting_pull_keeps_unrelated_changes() {
+        let fx = fixture().await;
+        commit_file(&fx.engine, &fx.alice, "README.md", "theirs\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        commit_file(&fx.engine, &fx.bob, "README.md", "ours\n").await;
+        let ours = commit_file(&fx.engine, &fx.bob, "notes.txt", "committed\n").await;
+        std::fs::write(fx.bob.workdir().unwrap().join("notes.txt"), "work in progress\n").unwrap();
+        std::fs::write(fx.bob.workdir().unwrap().join("scratch.txt"), "untracked\n").unwrap();
+        
+        let err = fx.engine.pull(&fx.bob, "origin", "main", PullMode::Merge).await.unwrap_err();
+        
+        assert!(err.to_string().contains("README.md"), "{}", err);
+        assert_eq!(fx.bob.state(), RepositoryState::Clean);
+        assert_eq!(head_id(&fx.bob), ours);
+        assert_eq!(read(&fx.bob, "README.md"), "ours\n");
+        assert_eq!(read(&fx.bob, "notes.txt"), "work in progress\n");
+        assert_eq!(read(&fx.bob, "scratch.txt"), "untracked\n");
+        assert!(!fx.bob.index().unwrap().has_conflicts());
+    }
+    
+    #[tokio::test]
+    async fn sync_records_unreachable_remotes_and_continues() {
+        let fx = fixture().await;
+        let commit = commit_file(&fx.engine, &fx.alice, "a.txt", "a\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        let missing = fx.dir.path().join("missing.git");
+        fx.bob.remote("backup", missing.to_str().unwrap()).unwrap();
+        
+        let report = fx.engine.sync(&fx.bob, &SyncOptions::default()).await.unwrap();
+        
+        assert_eq!(report.fetched, vec!["origin".to_string()]);
+        assert!(report.fetch_failed.contains_key("backup"));
+        assert_eq!(report.branches.len(), 1);
+        assert_eq!(head_id(&fx.bob), commit);
+    }
+    
+    #[tokio::test]
+    async fn sync_keeps_its_report_when_the_mirror_is_unreachable() {
+        let fx = fixture().await;
+        let commit = commit_file(&fx.engine, &fx.alice, "a.txt", "a\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        let missing = fx.dir.path().join("missing.git");
+        let options = SyncOptions {
+            mirror_remote: Some(missing.to_str().unwrap().to_string()),
+            ..SyncOptions::default()
+        };
+        
+        let report = fx.engine.sync(&fx.bob, &options).await.unwrap();
+        
+        assert_eq!(report.fetched, vec!["origin".to_string()]);
+        assert!(matches!(report.branches[0].outcome, RefSyncOutcome::FastForwarded { .. }));
+        assert!(report.mirrored.is_empty());
+        assert!(report.mirror_failed.is_some());
+        assert_eq!(head_id(&fx.bob), commit);
+    }
+    
+    #[tokio::test]
+    async fn aborted_cherry_pick_keeps_unrelated_changes() {
+        let fx = fixture().await;
+        let theirs = commit_file(&fx.engine, &fx.alice, "README.md", "theirs\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        fx.engine.fetch(&fx.bob, "origin", &[]).await.unwrap();
+        commit_file(&fx.engine, &fx.bob, "README.md", "ours\n").await;
+        let ours = commit_file(&fx.engine, &fx.bob, "notes.txt", "committed\n").await;
+        std::fs::write(fx.bob.workdir().unwrap().join("notes.txt"), "work in progress\n").unwrap();
+        
+        let outcome = fx.engine.cherry_pick(&fx.bob, &theirs).await.unwrap();
+        assert!(matches!(outcome, MergeOutcome::Conflicted { .. }), "{:?}", outcome);
+        fx.engine.abort_operation(&fx.bob).await.unwrap();
+        
+        assert_eq!(fx.bob.state(), RepositoryState::Clean);
+        assert_eq!(head_id(&fx.bob), ours);
+        assert_eq!(read(&fx.bob, "README.md"), "ours\n");
+        assert_eq!(read(&fx.bob, "notes.txt"), "work in progress\n");
+    }
+    
+    #[tokio::test]
+    async fn reused_worktree_is_reset_to_requested_branch() {
+        let fx = fixture().await;
+        let path = fx.dir.path().join("worktree");
+        
+        let first = fx.engine.acquire_worktree(&fx.bob, "job", &path, Some("first")).await.unwrap();
+        std::fs::write(path.join("README.md"), "leftover\n").unwrap();
+        std::fs::write(path.join("scratch.txt"), "untracked\n").unwrap();
+        drop(first);
+        assert!(fx.engine.acquire_worktree(&fx.bob, "job", &path, Some("second")).await.is_err());
+        fx.engine.release_worktree(&fx.bob, "job").await.unwrap();
+        
+        let second = fx.engine.acquire_worktree(&fx.bob, "job", &path, Some("second")).await.unwrap();
+        
+        assert_eq!(second.head().unwrap().name(), Some("refs/heads/second"));
+        assert_eq!(read(&second, "README.md"), "hello\n");
+        assert!(!path.join("scratch.txt").exists());
+    }
+    
+    #[tokio::test]
+    async fn push_reports_non_fast_forward_rejection() {
+        let fx = fixture().await;
+        let theirs = commit_file(&fx.engine, &fx.alice, "a.txt", "a\n").await;
+        fx.engine.push(&fx.alice, "origin", &["refs/heads/main:refs/heads/main"]).await.unwrap();
+        commit_file(&fx.engine, &fx.bob, "b.txt", "b\n").await;
+        
+        let result = fx.engine.push(&fx.bob, "origin", &["refs/heads/main:refs/heads/main"]).await;
+        
+        assert!(result.is_err());
+        let remote_main = fx.remote.find_reference("refs/heads/main").unwrap();
+        assert_eq!(remote_main.target().unwrap().to_string(), theirs);
+    }
+    
+    /// Commit `README.md` differently on `main` and on a new `topic` branch,
+    /// leaving `main` checked out
+    async fn diverge_readme(engine: &GitEngine, repo: &Repository) -> (String, String) {
+        let base = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("topic", &base, false).unwrap();
+        let ours = commit_file(engine, repo, "README.md", "ours\n").await;
+        engine.checkout_branch(repo, "topic").await.unwrap();
+        let theirs = commit_file(engine, repo, "README.md", "theirs\n").await;
+        engine.checkout_branch(repo, "main").await.unwrap();
+        (ours, theirs)
+    }
+    
+    fn stage(repo: &Repository, add: &[&str], remove: &[&str]) {
+        let mut index = repo.index().unwrap();
+        for path in add {
+            index.add_path(Path::new(path)).unwrap();
+        }
+        for path in remove {
+            index.remove_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+    }
+    
+    #[tokio::test]
+    async fn status_separates_staged_unstaged_untracked_and_renamed_paths() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        let workdir = repo.workdir().unwrap().to_path_buf();
+        commit_file(&fx.engine, repo, "staged.txt", "one\n").await;
+        commit_file(&fx.engine, repo, "unstaged.txt", "one\n").await;
+        commit_file(&fx.engine, repo, "old.txt", "a file long enough to be recognized after a move\n").await;
+        
+        std::fs::write(workdir.join("staged.txt"), "two\n").unwrap();
+        std::fs::write(workdir.join("unstaged.txt"), "two\n").unwrap();
+        std::fs::write(workdir.join("untracked.txt"), "new\n").unwrap();
+        std::fs::rename(workdir.join("old.txt"), workdir.join("new.txt")).unwrap();
+        stage(repo, &["staged.txt", "new.txt"], &["old.txt"]);
+        
+        let status = fx.engine.status(repo).await.unwrap();
+        
+        let staged: Vec<_> = status.staged.iter()
+            .map(|entry| (entry.path.as_str(), entry.old_path.as_deref(), entry.kind))
+            .collect();
+        assert_eq!(staged, vec![
+            ("new.txt", Some("old.txt"), FileChangeKind::Renamed),
+            ("staged.txt", None, FileChangeKind::Modified),
+        ]);
+        assert_eq!(status.unstaged, vec![StatusEntry {
+            path: "unstaged.txt".to_string(),
+            old_path: None,
+            kind: FileChangeKind::Modified,
+        }]);
+        assert_eq!(status.untracked, vec!["untracked.txt".to_string()]);
+        assert!(status.conflicted.is_empty());
+        assert!(!status.is_clean());
+    }
+    
+    #[tokio::test]
+    async fn status_lists_conflicted_paths() {
+        let fx = fixture().await;
+        diverge_readme(&fx.engine, &fx.alice).await;
+        
+        fx.engine.merge(&fx.alice, "topic", true).await.unwrap();
+        let status = fx.engine.status(&fx.alice).await.unwrap();
+        
+        assert_eq!(status.conflicted, vec!["README.md".to_string()]);
+        assert!(status.staged.is_empty());
+        assert!(status.unstaged.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn diff_reports_hunk_line_numbers() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        commit_file(&fx.engine, repo, "lines.txt", "1\n2\n3\n4\n5\n6\n7\n8\n9\n").await;
+        std::fs::write(repo.workdir().unwrap().join("lines.txt"), "1\n2\n3\n4\nfive\n6\n7\n8\n9\n10\n").unwrap();
+        
+        let unstaged = fx.engine.diff(repo, &DiffTarget::WorkdirToIndex).await.unwrap();
+        
+        assert_eq!((unstaged.insertions, unstaged.deletions), (2, 1));
+        let file = &unstaged.files[0];
+        assert_eq!((file.path.as_str(), file.kind), ("lines.txt", FileChangeKind::Modified));
+        assert_eq!(file.hunks.len(), 1);
+        let hunk = &file.hunks[0];
+        assert_eq!((hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines), (2, 8, 2, 9));
+        assert_eq!(hunk.header, "@@ -2,8 +2,9 @@");
+        
+        let changed: Vec<_> = hunk.lines.iter()
+            .filter(|line| line.kind != DiffLineKind::Context)
+            .map(|line| (line.kind, line.old_lineno, line.new_lineno, line.content.as_str()))
+            .collect();
+        assert_eq!(changed, vec![
+            (DiffLineKind::Deletion, Some(5), None, "5\n"),
+            (DiffLineKind::Addition, None, Some(5), "five\n"),
+            (DiffLineKind::Addition, None, Some(10), "10\n"),
+        ]);
+        let context = hunk.lines.iter().find(|line| line.kind == DiffLineKind::Context).unwrap();
+        assert_eq!((context.old_lineno, context.new_lineno), (Some(2), Some(2)));
+        
+        stage(repo, &["lines.txt"], &[]);
+        let staged = fx.engine.diff(repo, &DiffTarget::IndexToHead).await.unwrap();
+        assert_eq!(staged.files_changed(), vec!["lines.txt".to_string()]);
+        assert!(fx.engine.diff(repo, &DiffTarget::WorkdirToIndex).await.unwrap().files.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn diff_between_revisions() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        let base = head_id(repo);
+        commit_file(&fx.engine, repo, "added.txt", "new\n").await;
+        commit_file(&fx.engine, repo, "README.md", "hello\nworld\n").await;
+        
+        let report = fx.engine.diff(repo, &DiffTarget::Revisions {
+            from: base.clone(),
+            to: "HEAD".to_string(),
+        }).await.unwrap();
+        
+        let files: Vec<_> = report.files.iter().map(|file| (file.path.as_str(), file.kind)).collect();
+        assert_eq!(files, vec![("README.md", FileChangeKind::Modified), ("added.txt", FileChangeKind::Added)]);
+        assert_eq!((report.insertions, report.deletions), (2, 0));
+        
+        let reverse = fx.engine.diff(repo, &DiffTarget::Revisions {
+            from: "HEAD".to_string(),
+            to: base,
+        }).await.unwrap();
+        assert_eq!(reverse.files[1].kind, FileChangeKind::Deleted);
+        assert_eq!((reverse.insertions, reverse.deletions), (0, 2));
+    }
+    
+    /// Pointer file text for `content` and the pointer it parses to
+    fn lfs_pointer(content: &[u8]) -> (LfsPointer, String) {
+        let oid: String = digest::digest(&digest::SHA256, content).as_ref()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let text = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize {}\n",
+            oid,
+            content.len()
+        );
+        (LfsPointer::parse(text.as_bytes()).unwrap(), text)
+    }
+    
+    /// Commit a pointer to `content` at `path` and store the object locally
+    async fn commit_lfs_file(engine: &GitEngine, repo: &Repository, path: &str, content: &[u8]) -> LfsPointer {
+        let (pointer, text) = lfs_pointer(content);
+        commit_file(engine, repo, path, &text).await;
+        let object = pointer.object_path(repo.path());
+        std::fs::create_dir_all(object.parent().unwrap()).unwrap();
+        std::fs::write(&object, content).unwrap();
+        pointer
+    }
+    
+    #[tokio::test]
+    async fn fetched_lfs_files_stay_clean_until_edited() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        commit_lfs_file(&fx.engine, repo, "asset.bin", b"large binary content\n").await;
+        
+        let report = fx.engine.fetch_lfs_objects(repo).await.unwrap();
+        
+        assert_eq!(report.fetched, vec!["asset.bin".to_string()]);
+        assert!(report.failed.is_empty());
+        assert_eq!(read(repo, "asset.bin"), "large binary content\n");
+        assert!(fx.engine.status(repo).await.unwrap().is_clean());
+        
+        std::fs::write(repo.workdir().unwrap().join("asset.bin"), "edited\n").unwrap();
+        let status = fx.engine.status(repo).await.unwrap();
+        let unstaged: Vec<_> = status.unstaged.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(unstaged, vec!["asset.bin"]);
+        
+        let commit = fx.engine.create_commit(repo, "Edit asset", &["asset.bin"]).await.unwrap();
+        let tree = repo.find_commit(Oid::from_str(&commit).unwrap()).unwrap().tree().unwrap();
+        let blob = repo.find_blob(tree.get_name("asset.bin").unwrap().id()).unwrap();
+        assert_eq!(blob.content(), b"edited\n");
+    }
+    
+    #[tokio::test]
+    async fn failed_lfs_copies_are_reported_per_file() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        commit_lfs_file(&fx.engine, repo, "good.bin", b"good content\n").await;
+        let broken = commit_lfs_file(&fx.engine, repo, "broken.bin", b"broken content\n").await;
+        // An object path that cannot be copied from
+        let object = broken.object_path(repo.path());
+        std::fs::remove_file(&object).unwrap();
+        std::fs::create_dir(&object).unwrap();
+        
+        let report = fx.engine.fetch_lfs_objects(repo).await.unwrap();
+        
+        assert_eq!(report.fetched, vec!["good.bin".to_string()]);
+        assert_eq!(report.failed.keys().collect::<Vec<_>>(), vec!["broken.bin"]);
+        assert_eq!(read(repo, "good.bin"), "good content\n");
+        assert!(LfsPointer::read(&repo.workdir().unwrap().join("broken.bin")).is_some());
+    }
+    
+    fn local_branches(branches: &[BranchInfo]) -> Vec<&str> {
+        branches.iter()
+            .filter(|branch| branch.kind == BranchKind::Local)
+            .map(|branch| branch.name.as_str())
+            .collect()
+    }
+    
+    #[tokio::test]
+    async fn branches_can_be_created_renamed_and_deleted() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        
+        let created = fx.engine.create_branch(repo, "feature", None).await.unwrap();
+        assert_eq!(created.commit, head_id(repo));
+        assert_eq!(created.kind, BranchKind::Local);
+        assert!(!created.is_head);
+        
+        let renamed = fx.engine.rename_branch(repo, "feature", "feature-2", false).await.unwrap();
+        assert_eq!(renamed.name, "feature-2");
+        let branches = fx.engine.list_branches(repo, Some(BranchKind::Local)).await.unwrap();
+        assert_eq!(local_branches(&branches), vec!["feature-2", "main"]);
+        
+        fx.engine.delete_branch(repo, "feature-2", false).await.unwrap();
+        let branches = fx.engine.list_branches(repo, Some(BranchKind::Local)).await.unwrap();
+        assert_eq!(local_branches(&branches), vec!["main"]);
+    }
+    
+    #[tokio::test]
+    async fn unmerged_branches_are_only_deleted_when_forced() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        diverge_readme(&fx.engine, repo).await;
+        
+        assert!(fx.engine.delete_branch(repo, "topic", false).await.is_err());
+        assert!(repo.find_branch("topic", BranchType::Local).is_ok());
+        fx.engine.delete_branch(repo, "topic", true).await.unwrap();
+        assert!(repo.find_branch("topic", BranchType::Local).is_err());
+    }
+    
+    #[tokio::test]
+    async fn the_checked_out_branch_is_never_deleted() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        
+        assert!(fx.engine.delete_branch(repo, "main", false).await.is_err());
+        assert!(fx.engine.delete_branch(repo, "main", true).await.is_err());
+        assert!(repo.find_branch("main", BranchType::Local).is_ok());
+    }
+    
+    #[tokio::test]
+    async fn upstream_tracking_reports_ahead_and_behind() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        fx.engine.create_branch(repo, "feature", None).await.unwrap();
+        fx.engine.set_upstream(repo, "feature", Some("origin/main")).await.unwrap();
+        fx.engine.checkout_branch(repo, "feature").await.unwrap();
+        commit_file(&fx.engine, repo, "feature.txt", "feature\n").await;
+        
+        let branches = fx.engine.list_branches(repo, Some(BranchKind::Local)).await.unwrap();
+        let feature = branches.iter().find(|branch| branch.name == "feature").unwrap();
+        assert!(feature.is_head);
+        assert_eq!(feature.upstream.as_deref(), Some("origin/main"));
+        assert_eq!((feature.ahead, feature.behind), (1, 0));
+        
+        fx.engine.set_upstream(repo, "feature", None).await.unwrap();
+        let branches = fx.engine.list_branches(repo, Some(BranchKind::Local)).await.unwrap();
+        let feature = branches.iter().find(|branch| branch.name == "feature").unwrap();
+        assert_eq!(feature.upstream, None);
+        assert_eq!((feature.ahead, feature.behind), (0, 0));
+    }
+    
+    #[tokio::test]
+    async fn lightweight_and_annotated_tags() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        let head = head_id(repo);
+        
+        let light = fx.engine.create_tag(repo, "v1.0", "HEAD", None).await.unwrap();
+        assert_eq!(light.commit, head);
+        assert!(!light.annotated);
+        assert_eq!(light.message, None);
+        assert!(light.tagger.is_none());
+        
+        let annotated = fx.engine.create_tag(repo, "v1.1", "main", Some("Release 1.1")).await.unwrap();
+        assert_eq!(annotated.commit, head);
+        assert!(annotated.annotated);
+        assert_eq!(annotated.message.as_deref().map(str::trim), Some("Release 1.1"));
+        assert_eq!(annotated.tagger.unwrap().name, "Test");
+        
+        fx.engine.create_tag(repo, "nightly", "HEAD", None).await.unwrap();
+        let names = |tags: Vec<TagInfo>| tags.into_iter().map(|tag| tag.name).collect::<Vec<_>>();
+        assert_eq!(names(fx.engine.list_tags(repo, Some("v1.*")).await.unwrap()), vec!["v1.0", "v1.1"]);
+        
+        fx.engine.delete_tag(repo, "v1.0").await.unwrap();
+        fx.engine.delete_tag(repo, "v1.1").await.unwrap();
+        assert_eq!(names(fx.engine.list_tags(repo, None).await.unwrap()), vec!["nightly"]);
+    }
+    
+    fn blob_id(content: &str) -> Option<String> {
+        Some(Oid::hash_object(ObjectType::Blob, content.as_bytes()).unwrap().to_string())
+    }
+    
+    fn expect_conflicts(outcome: MergeOutcome) -> Vec<ConflictEntry> {
+        match outcome {
+            MergeOutcome::Conflicted { conflicts } => conflicts,
+            other => panic!("expected conflicts, got {:?}", other),
+        }
+    }
+    
+    fn commit_id(outcome: MergeOutcome) -> String {
+        match outcome {
+            MergeOutcome::Committed { commit } => commit,
+            other => panic!("expected a commit, got {:?}", other),
+        }
+    }
+    
+    #[tokio::test]
+    async fn conflicting_merge_reports_each_side_and_continues_once_resolved() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        let (ours, theirs) = diverge_readme(&fx.engine, repo).await;
+        
+        let conflicts = expect_conflicts(fx.engine.merge(repo, "topic", true).await.unwrap());
+        assert_eq!(conflicts, vec![ConflictEntry {
+            path: "README.md".to_string(),
+            ancestor: blob_id("hello\n"),
+            ours: blob_id("ours\n"),
+            theirs: blob_id("theirs\n"),
+        }]);
+        assert_eq!(repo.state(), RepositoryState::Merge);
+        
+        std::fs::write(repo.workdir().unwrap().join("README.md"), "merged\n").unwrap();
+        stage(repo, &["README.md"], &[]);
+        let merge = commit_id(fx.engine.continue_operation(repo).await.unwrap());
+        
+        assert_eq!(repo.state(), RepositoryState::Clean);
+        assert_eq!(head_id(repo), merge);
+        let parents: Vec<String> = repo.find_commit(Oid::from_str(&merge).unwrap()).unwrap()
+            .parent_ids()
+            .map(|id| id.to_string())
+            .collect();
+        assert_eq!(parents, vec![ours, theirs]);
+        assert_eq!(read(repo, "README.md"), "merged\n");
+    }
+    
+    /// `topic` from `diverge_readme` plus a commit adding `notes.txt`, checked out
+    async fn topic_with_notes(engine: &GitEngine, repo: &Repository) -> (String, String) {
+        let (ours, _) = diverge_readme(engine, repo).await;
+        engine.checkout_branch(repo, "topic").await.unwrap();
+        let tip = commit_file(engine, repo, "notes.txt", "notes\n").await;
+        (ours, tip)
+    }
+    
+    #[tokio::test]
+    async fn rebase_stops_on_a_conflict_and_continues_after_resolution() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        let (main, _) = topic_with_notes(&fx.engine, repo).await;
+        
+        let conflicts = expect_conflicts(fx.engine.rebase(repo, "main").await.unwrap());
+        assert_eq!(conflicts.iter().map(|c| c.path.as_str()).collect::<Vec<_>>(), vec!["README.md"]);
+        assert_eq!(repo.state(), RepositoryState::RebaseMerge);
+        
+        std::fs::write(repo.workdir().unwrap().join("README.md"), "resolved\n").unwrap();
+        stage(repo, &["README.md"], &[]);
+        let rebased = commit_id(fx.engine.continue_operation(repo).await.unwrap());
+        
+        assert_eq!(repo.state(), RepositoryState::Clean);
+        assert_eq!(repo.head().unwrap().name(), Some("refs/heads/topic"));
+        assert_eq!(head_id(repo), rebased);
+        let tip = repo.find_commit(Oid::from_str(&rebased).unwrap()).unwrap();
+        let resolved = tip.parent(0).unwrap();
+        assert_eq!(resolved.parent_id(0).unwrap().to_string(), main);
+        assert_eq!(read(repo, "README.md"), "resolved\n");
+        assert_eq!(read(repo, "notes.txt"), "notes\n");
+    }
+    
+    #[tokio::test]
+    async fn aborted_rebase_restores_the_branch() {
+        let fx = fixture().await;
+        let repo = &fx.alice;
+        let (_, tip) = topic_with_notes(&fx.engine, repo).await;
+        
+        expect_conflicts(fx.engine.rebase(repo, "main").await.unwrap());
+        fx.engine.abort_operation(repo).await.unwrap();
+        
+        assert_eq!(repo.state(), RepositoryState::Clean);
+        assert_eq!(repo.head().unwrap().name(), Some("refs/heads/topic"));
+        assert_eq!(head_id(repo), tip);
+        assert_eq!(read(repo, "README.md"), "theirs\n");
+        assert_eq!(read(repo, "notes.txt"), "notes\n");
+    }
+    
+    /// A history with fixed authors and timestamps, independent of the fixture clock
+    struct History {
+        _dir: TempDir,
+        engine: GitEngine,
+        repo: Repository,
+    }
+    
+    fn history() -> History {
+        let dir = TempDir::new().unwrap();
+        let mut init = git2::RepositoryInitOptions::new();
+        init.initial_head("main");
+        let repo = Repository::init_opts(dir.path().join("history"), &init).unwrap();
+        History {
+            _dir: dir,
+            engine: GitEngine::new(&AgentConfig::default()).unwrap(),
+            repo,
+        }
+    }
+    
+    /// Commit `path` with `contents` on top of `parents` (the first updates
+    /// `HEAD`'s branch), authored and committed by `name` at `seconds`
+    fn commit_at(
+        repo: &Repository,
+        parents: &[Oid],
+        path: &str,
+        contents: &str,
+        name: &str,
+        seconds: i64,
+        message: &str,
+    ) -> Oid {
+        let file = repo.workdir().unwrap().join(path);
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(&file, contents).unwrap();
+        let mut index = repo.index().unwrap();
+        match parents.first() {
+            Some(parent) => index.read_tree(&repo.find_commit(*parent).unwrap().tree().unwrap()).unwrap(),
+            None => index.clear().unwrap(),
+        }
+        index.add_path(Path::new(path)).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::new(
+            name,
+            &format!("{}@example.com", name.to_lowercase()),
+            &git2::Time::new(seconds, 0),
+        ).unwrap();
+        let parents: Vec<Commit<'_>> = parents.iter().map(|id| repo.find_commit(*id).unwrap()).collect();
+        let parents: Vec<&Commit<'_>> = parents.iter().collect();
+        // Only a commit on top of the current tip moves the branch
+        let head = repo.head().ok().and_then(|head| head.target());
+        let update = (parents.first().map(|parent| parent.id()) == head).then_some("HEAD");
+        repo.commit(update, &signature, &signature, message, &tree, &parents).unwrap()
+    }
+    
+    async fn log_ids(engine: &GitEngine, repo: &Repository, filter: LogFilter) -> Vec<Oid> {
+        engine.log(repo, &filter).await.unwrap()
+            .map(|commit| Oid::from_str(&commit.unwrap().id).unwrap())
+            .collect()
+    }
+    
+    fn at(seconds: i64) -> Option<DateTime<Utc>> {
+        Utc.timestamp_opt(seconds, 0).single()
+    }
+    
+    #[tokio::test]
+    async fn log_filters_by_author_path_and_date() {
+        let h = history();
+        let first = commit_at(&h.repo, &[], "a.txt", "a\n", "Alice", 1_000, "Add a");
+        let second = commit_at(&h.repo, &[first], "b.txt", "b\n", "Bob", 2_000, "Add b");
+        let third = commit_at(&h.repo, &[second], "b.txt", "b2\n", "Alice", 3_000, "Edit b");
+        
+        let log = |filter| log_ids(&h.engine, &h.repo, filter);
+        assert_eq!(log(LogFilter::default()).await, vec![third, second, first]);
+        assert_eq!(log(LogFilter { author: Some("Bob".into()), ..LogFilter::default() }).await, vec![second]);
+        assert_eq!(log(LogFilter { author: Some("alice@".into()), ..LogFilter::default() }).await, vec![third, first]);
+        assert_eq!(log(LogFilter { paths: vec!["b.txt".into()], ..LogFilter::default() }).await, vec![third, second]);
+        assert_eq!(
+            log(LogFilter { author: Some("Alice".into()), paths: vec!["b.txt".into()], ..LogFilter::default() }).await,
+            vec![third]
+        );
+        assert_eq!(
+            log(LogFilter { since: at(1_500), until: at(2_500), ..LogFilter::default() }).await,
+            vec![second]
+        );
+        assert_eq!(log(LogFilter { since: at(2_000), ..LogFilter::default() }).await, vec![third, second]);
+    }
+    
+    #[tokio::test]
+    async fn log_ranges_exclude_the_left_side() {
+        let h = history();
+        let first = commit_at(&h.repo, &[], "a.txt", "a\n", "Alice", 1_000, "Add a");
+        let second = commit_at(&h.repo, &[first], "a.txt", "a2\n", "Alice", 2_000, "Edit a");
+        let third = commit_at(&h.repo, &[second], "a.txt", "a3\n", "Alice", 3_000, "Edit a again");
+        
+        let range = |range: String| LogFilter { range: Some(range), ..LogFilter::default() };
+        assert_eq!(log_ids(&h.engine, &h.repo, range(format!("{}..{}", first, third))).await, vec![third, second]);
+        assert_eq!(log_ids(&h.engine, &h.repo, range(format!("{}..HEAD", second))).await, vec![third]);
+        assert_eq!(log_ids(&h.engine, &h.repo, range(second.to_string())).await, vec![second, first]);
+    }
+    
+    #[tokio::test]
+    async fn log_parses_trailers_in_order() {
+        let h = history();
+        commit_at(
+            &h.repo,
+            &[],
+            "a.txt",
+            "a\n",
+            "Alice",
+            1_000,
+            "Fix the parser\n\nIt dropped the last line.\n\nSigned-off-by: Alice <alice@example.com>\nCo-authored-by: Bob <bob@example.com>\n",
+        );
+        
+        let commit = h.engine.log(&h.repo, &LogFilter::default()).await.unwrap().next().unwrap().unwrap();
+        
+        assert_eq!(commit.summary, "Fix the parser");
+        assert_eq!(commit.trailers, vec![
+            ("Signed-off-by".to_string(), "Alice <alice@example.com>".to_string()),
+            ("Co-authored-by".to_string(), "Bob <bob@example.com>".to_string()),
+        ]);
+    }
+    
+    #[tokio::test]
+    async fn topo_order_lists_children_before_parents_despite_clock_skew() {
+        let h = history();
+        let base = commit_at(&h.repo, &[], "a.txt", "a\n", "Alice", 1_000, "Base");
+        let parent = commit_at(&h.repo, &[base], "a.txt", "a2\n", "Alice", 2_000, "Parent");
+        // Committed on a machine whose clock is behind
+        let child = commit_at(&h.repo, &[parent], "a.txt", "a3\n", "Alice", 1_500, "Child");
+        let side = commit_at(&h.repo, &[base], "b.txt", "b\n", "Bob", 5_000, "Side");
+        let merge = commit_at(&h.repo, &[child, side], "b.txt", "b\n", "Alice", 6_000, "Merge");
+        
+        let by_time = log_ids(&h.engine, &h.repo, LogFilter::default()).await;
+        assert_eq!(by_time, vec![merge, side, parent, child, base]);
+        
+        let topo = log_ids(&h.engine, &h.repo, LogFilter { topo_order: true, ..LogFilter::default() }).await;
+        let position = |id: Oid| topo.iter().position(|listed| *listed == id).unwrap();
+        assert_eq!(topo.len(), 5);
+        assert_eq!(topo[0], merge);
+        assert!(position(child) < position(parent));
+        assert!(position(parent) < position(base) && position(side) < position(base));
+    }
+    
+    fn line_owners(blame: &FileBlame) -> Vec<(&str, &str)> {
+        blame.lines.iter()
+            .map(|line| (line.content.as_str(), line.author.name.as_str()))
+            .collect()
+    }
+    
+    #[tokio::test]
+    async fn blame_looks_through_ignored_revisions() {
+        let h = history();
+        let written = commit_at(&h.repo, &[], "lib.rs", "a\nb\nc\n", "Alice", 1_000, "Write lib");
+        let reformat = commit_at(&h.repo, &[written], "lib.rs", "A\nB\nC\n", "Formatter", 2_000, "Reformat");
+        commit_at(&h.repo, &[reformat], "lib.rs", "A\nB\nC\nd\n", "Bob", 3_000, "Append");
+        
+        let plain = h.engine.blame(&h.repo, "lib.rs", "HEAD", &BlameSettings::default()).await.unwrap();
+        assert_eq!(line_owners(&plain), vec![("A", "Formatter"), ("B", "Formatter"), ("C", "Formatter"), ("d", "Bob")]);
+        
+        let settings = BlameSettings {
+            ignore_revs: vec![reformat.to_string()],
+            ..BlameSettings::default()
+        };
+        let ignored = h.engine.blame(&h.repo, "lib.rs", "HEAD", &settings).await.unwrap();
+        assert_eq!(line_owners(&ignored), vec![("A", "Alice"), ("B", "Alice"), ("C", "Alice"), ("d", "Bob")]);
+        assert!(ignored.lines[..3].iter().all(|line| line.commit == written.to_string()));
+        
+        let ignore_file = h.repo.workdir().unwrap().join(".git-blame-ignore-revs");
+        std::fs::write(&ignore_file, format!("# bulk reformat\n{}\n", reformat)).unwrap();
+        let settings = BlameSettings {
+            ignore_revs_file: Some(PathBuf::from(".git-blame-ignore-revs")),
+            ..BlameSettings::default()
+        };
+        let from_file = h.engine.blame(&h.repo, "lib.rs", "HEAD", &settings).await.unwrap();
+        assert_eq!(line_owners(&from_file), line_owners(&ignored));
+    }
+    
+    #[test]
+    fn lines_map_into_the_first_parent_across_hunks() {
+        let h = history();
+        let parent = commit_at(&h.repo, &[], "notes.txt", "a\nb\nc\nd\n", "Alice", 1_000, "Notes");
+        // Drops `b`, rewrites `c` and appends `e`
+        let child = commit_at(&h.repo, &[parent], "notes.txt", "a\nC\nd\ne\n", "Bob", 2_000, "Edit notes");
+        let added = commit_at(&h.repo, &[child], "other.txt", "x\n", "Bob", 3_000, "Other");
+        
+        let map = |commit, path, line| line_in_parent(&h.repo, commit, path, line).unwrap();
+        assert_eq!(map(child, "notes.txt", 1), Some((parent, 1)));
+        assert_eq!(map(child, "notes.txt", 2), Some((parent, 2)));
+        assert_eq!(map(child, "notes.txt", 3), Some((parent, 4)));
+        assert_eq!(map(child, "notes.txt", 4), None);
+        // Unchanged by `added`, so lines map straight through
+        assert_eq!(map(added, "notes.txt", 3), Some((child, 3)));
+        assert_eq!(map(added, "other.txt", 1), None);
+        assert_eq!(map(parent, "notes.txt", 1), None);
+    }
+    
+    fn shares(ownership: &Ownership) -> Vec<(&str, usize, f32)> {
+        ownership.authors.iter()
+            .map(|share| (share.author.name.as_str(), share.lines, share.share))
+            .collect()
+    }
+    
+    #[tokio::test]
+    async fn ownership_shares_add_up_per_file_and_directory() {
+        let h = history();
+        let first = commit_at(&h.repo, &[], "src/a.rs", "1\n2\n3\n", "Alice", 1_000, "Add a");
+        let second = commit_at(&h.repo, &[first], "src/b.rs", "1\n", "Bob", 2_000, "Add b");
+        commit_at(&h.repo, &[second], "README.md", "1\n2\n3\n4\n", "Carol", 3_000, "Add readme");
+        
+        let src = h.engine.ownership(&h.repo, "src", "HEAD", &BlameSettings::default()).await.unwrap();
+        assert_eq!(src.files.keys().collect::<Vec<_>>(), vec!["src/a.rs", "src/b.rs"]);
+        assert_eq!(shares(&src.files["src/a.rs"]), vec![("Alice", 3, 1.0)]);
+        assert_eq!(src.directories.keys().collect::<Vec<_>>(), vec!["src"]);
+        assert_eq!(shares(&src.directories["src"]), vec![("Alice", 3, 0.75), ("Bob", 1, 0.25)]);
+        assert_eq!(src.directories["src"].bus_factor(), 1);
+        
+        let all = h.engine.ownership(&h.repo, "", "HEAD", &BlameSettings::default()).await.unwrap();
+        let root = &all.directories[""];
+        assert_eq!(root.total_lines, 8);
+        assert_eq!(shares(root), vec![("Carol", 4, 0.5), ("Alice", 3, 0.375), ("Bob", 1, 0.125)]);
+        assert_eq!(root.bus_factor(), 2);
+        assert_eq!(root.authors[0].last_change, at(3_000).unwrap());
+    }
+    
+    const BUNDLE_REFSPEC: &str = "refs/heads/*:refs/remotes/origin/*";
+    
+    fn remote_main(repo: &Repository) -> String {
+        repo.refname_to_id("refs/remotes/origin/main").unwrap().to_string()
+    }
+    
+    #[tokio::test]
+    async fn bundles_round_trip_full_and_incremental_history() {
+        let fx = fixture().await;
+        commit_file(&fx.engine, &fx.alice, "a.txt", "a\n").await;
+        let first = commit_file(&fx.engine, &fx.alice, "b.txt", "b\n").await;
+        let full = fx.dir.path().join("full.bundle");
+        
+        let info = fx.engine.create_bundle(&fx.alice, &full, &["main"], None).await.unwrap();
+        assert!(info.prerequisites.is_empty());
+        assert_eq!(info.refs.len(), 1);
+        assert_eq!((info.refs[0].name.as_str(), info.refs[0].commit.as_str()), ("refs/heads/main", first.as_str()));
+        assert_eq!(info.size_bytes, std::fs::metadata(&full).unwrap().len());
+        
+        let fetched = fx.engine.fetch_bundle(&fx.bob, &full, &[BUNDLE_REFSPEC]).await.unwrap();
+        assert_eq!(fetched.updated, vec!["refs/remotes/origin/main".to_string()]);
+        assert!(fetched.rejected.is_empty());
+        assert_eq!(remote_main(&fx.bob), first);
+        
+        let second = commit_file(&fx.engine, &fx.alice, "c.txt", "c\n").await;
+        let incremental = fx.dir.path().join("incremental.bundle");
+        let info = fx.engine.create_bundle(&fx.alice, &incremental, &["main"], Some(&first)).await.unwrap();
+        assert_eq!(info.prerequisites, vec![first.clone()]);
+        // The new commit, its tree and the added blob
+        assert_eq!(info.object_count, 3);
+        
+        fx.engine.fetch_bundle(&fx.bob, &incremental, &[BUNDLE_REFSPEC]).await.unwrap();
+        assert_eq!(remote_main(&fx.bob), second);
+        let tree = fx.bob.find_commit(Oid::from_str(&second).unwrap()).unwrap().tree().unwrap();
+        assert!(tree.get_name("c.txt").is_some());
+        
+        // Going back to the older bundle is not a fast-forward
+        let stale = fx.engine.fetch_bundle(&fx.bob, &full, &[BUNDLE_REFSPEC]).await.unwrap();
+        assert_eq!(stale.rejected, vec!["refs/remotes/origin/main".to_string()]);
+        assert_eq!(remote_main(&fx.bob), second);
+        let forced = format!("+{}", BUNDLE_REFSPEC);
+        fx.engine.fetch_bundle(&fx.bob, &full, &[&forced]).await.unwrap();
+        assert_eq!(remote_main(&fx.bob), first);
+    }
+    
+    #[tokio::test]
+    async fn verify_reports_missing_prerequisites() {
+        let fx = fixture().await;
+        let unpublished = commit_file(&fx.engine, &fx.alice, "a.txt", "a\n").await;
+        commit_file(&fx.engine, &fx.alice, "b.txt", "b\n").await;
+        let path = fx.dir.path().join("incremental.bundle");
+        fx.engine.create_bundle(&fx.alice, &path, &["main"], Some(&unpublished)).await.unwrap();
+        
+        let info = fx.engine.verify_bundle(&fx.alice, &path).await.unwrap();
+        assert_eq!(info.prerequisites, vec![unpublished.clone()]);
+        
+        match fx.engine.verify_bundle(&fx.bob, &path).await {
+            Err(AgentError::GitError(message)) => assert!(message.contains(&unpublished), "{}", message),
+            other => panic!("expected missing prerequisites, got {:?}", other),
+        }
+        let before = remote_main(&fx.bob);
+        assert!(fx.engine.fetch_bundle(&fx.bob, &path, &[BUNDLE_REFSPEC]).await.is_err());
+        assert_eq!(remote_main(&fx.bob), before);
+    }
+}
@@ -1,8 +1,31 @@
 //! GitHub API client optimizado
 
-use crate::{AgentConfig, AgentError};
-use octocrab::Octocrab;
+use crate::cache::{cache_key, CachedResponse, ResponseCache};
+use crate::ratelimit::{retry_after, RateLimiter, SECONDARY_LIMIT_PAUSE_SECS};
+use crate::{AgentConfig, AgentError, Authenticator, EventBus, RateBudget};
+use chrono::{TimeZone, Utc};
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// REST API root of github.com
+const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// REST API version requested on every call
+const GITHUB_API_VERSION: &str = "2022-11-28";
+
+/// Results per search page; the API maximum
+const SEARCH_PAGE_SIZE: u8 = 100;
+
+/// Search results beyond 10 pages of 100 are not served by the API
+const SEARCH_MAX_PAGES: u32 = 10;
+
+/// Times a request is resent after hitting a secondary rate limit
+const SECONDARY_LIMIT_RETRIES: u32 = 2;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RepoInfo {
@@ -13,37 +36,443 @@ pub struct RepoInfo {
     pub forks: u32,
 }
 
+/// Repository fields read from the REST API
+#[derive(Deserialize)]
+pub(crate) struct RepoResponse {
+    name: String,
+    full_name: String,
+    language: Option<String>,
+    stargazers_count: u32,
+    forks_count: u32,
+}
+
+impl From<RepoResponse> for RepoInfo {
+    fn from(repo: RepoResponse) -> Self {
+        Self {
+            name: repo.name,
+            full_name: repo.full_name,
+            language: repo.language,
+            stars: repo.stargazers_count,
+            forks: repo.forks_count,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    items: Vec<SearchItem>,
+}
+
+#[derive(Deserialize)]
+struct SearchItem {
+    full_name: String,
+}
+
+#[derive(Deserialize)]
+struct GraphQlResponse {
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlError {
+    message: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RateLimitResponse {
+    resources: HashMap<String, RateLimitResource>,
+}
+
+#[derive(Deserialize)]
+struct RateLimitResource {
+    limit: u64,
+    remaining: u64,
+    used: u64,
+    reset: i64,
+}
+
+/// Roots of the GitHub services the agent talks to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitHubEndpoints {
+    /// REST API, e.g. `https://api.github.com` or `https://ghe.example.com/api/v3`
+    pub api: String,
+    /// Release asset uploads
+    pub uploads: String,
+    /// GraphQL endpoint
+    pub graphql: String,
+    /// Web root serving the OAuth endpoints and repository clones
+    pub web: String,
+}
+
+impl GitHubEndpoints {
+    /// Endpoints from `config`. Unset upload and GraphQL URLs follow the API URL:
+    /// github.com conventions for github.com, `/api/uploads` and `/api/graphql`
+    /// next to an Enterprise Server `/api/v3`, and the API root itself otherwise.
+    pub fn from_config(config: &AgentConfig) -> Self {
+        let api = config.github_api_url.as_deref()
+            .unwrap_or(GITHUB_API_URL)
+            .trim_end_matches('/')
+            .to_string();
+        
+        let (uploads, graphql, web) = if api == GITHUB_API_URL {
+            (
+                "https://uploads.github.com".to_string(),
+                format!("{}/graphql", api),
+                "https://github.com".to_string(),
+            )
+        } else if let Some(root) = api.strip_suffix("/api/v3") {
+            (
+                format!("{}/api/uploads", root),
+                format!("{}/api/graphql", root),
+                root.to_string(),
+            )
+        } else {
+            (api.clone(), format!("{}/graphql", api), api.clone())
+        };
+        
+        Self {
+            uploads: config.github_upload_url.as_deref()
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or(uploads),
+            graphql: config.github_graphql_url.clone().unwrap_or(graphql),
+            api,
+            web,
+        }
+    }
+}
+
+/// HTTP client honoring the configured proxy and extra CA certificates
+pub(crate) fn http_client(config: &AgentConfig) -> Result<reqwest::Client, AgentError> {
+    let mut builder = reqwest::Client::builder().user_agent("github-agent-core");
+    
+    if let Some(proxy) = &config.http_proxy {
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|e| AgentError::InternalError(format!("invalid proxy {}: {}", proxy, e)))?;
+        builder = builder.proxy(proxy);
+    }
+    for path in &config.ca_certificates {
+        let pem = std::fs::read(path)
+            .map_err(|e| AgentError::InternalError(format!("cannot read {}: {}", path.display(), e)))?;
+        let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+            .map_err(|e| AgentError::InternalError(format!("invalid certificate {}: {}", path.display(), e)))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    
+    builder.build().map_err(|e| AgentError::InternalError(e.to_string()))
+}
+
+/// REST and GraphQL client. It sends requests through the same `reqwest`
+/// client as `Authenticator`, built by `http_client`, rather than through
+/// octocrab: octocrab sets up its own hyper connector, so custom CA roots and
+/// proxies would have to be configured twice, and the rate-limit, ETag and
+/// `Link` handling here needs the raw response headers anyway.
 pub struct GitHubClient {
-    client: Octocrab,
-    config: AgentConfig,
+    http: reqwest::Client,
+    endpoints: GitHubEndpoints,
+    auth: Arc<Authenticator>,
+    limits: RateLimiter,
+    cache: ResponseCache,
 }
 
 impl GitHubClient {
     pub async fn new(config: &AgentConfig) -> Result<Self, AgentError> {
-        let client = Octocrab::builder()
-            .base_uri("https://api.github.com")?
-            .build()?;
-            
         Ok(Self {
-            client,
-            config: config.clone(),
+            http: http_client(config)?,
+            endpoints: GitHubEndpoints::from_config(config),
+            auth: Arc::new(Authenticator::new(config)?),
+            limits: RateLimiter::new(config.rate_limit_buffer),
+            cache: ResponseCache::open(
+                config.cache_size_mb as u64 * 1024 * 1024,
+                config.http_cache_dir.as_deref(),
+            )?,
         })
     }
     
+    /// Publish authentication prompts, such as device flow codes, on `events`
+    pub fn with_events(self, events: Arc<EventBus>) -> Self {
+        self.auth.set_events(events);
+        self
+    }
+    
+    /// Token source shared with git remote operations
+    pub fn authenticator(&self) -> Arc<Authenticator> {
+        Arc::clone(&self.auth)
+    }
+    
+    pub fn endpoints(&self) -> &GitHubEndpoints {
+        &self.endpoints
+    }
+    
+    /// Last known budget of `resource`, e.g. `core`, `search` or `graphql`
+    pub fn rate_limit(&self, resource: &str) -> Option<RateBudget> {
+        self.limits.budget(resource)
+    }
+    
+    /// Last known budgets of every resource seen so far
+    pub fn rate_limits(&self) -> HashMap<String, RateBudget> {
+        self.limits.budgets()
+    }
+    
+    /// Fetch the budgets of all resources; this call does not count against them
+    pub async fn refresh_rate_limits(&self) -> Result<HashMap<String, RateBudget>, AgentError> {
+        let response: RateLimitResponse = self.get("/rate_limit", &[]).await?;
+        for (resource, limit) in response.resources {
+            let reset = Utc.timestamp_opt(limit.reset, 0).single()
+                .ok_or_else(|| AgentError::GitHubError(format!("invalid reset time {}", limit.reset)))?;
+            self.limits.set_budget(&resource, RateBudget {
+                limit: limit.limit,
+                remaining: limit.remaining,
+                used: limit.used,
+                reset,
+            });
+        }
+        Ok(self.limits.budgets())
+    }
+    
     pub async fn get_repo_info(&self, owner: &str, repo: &str) -> Result<RepoInfo, AgentError> {
-        let repo = self.client
-            .repos(owner, repo)
-            .get()
-            .await
-            .map_err(|e| AgentError::GitHubError(e.to_string()))?;
+        let repo: RepoResponse = self.get(&format!("/repos/{}/{}", owner, repo), &[]).await?;
+        Ok(repo.into())
+    }
+    
+    /// Full names of repositories matching a search query such as `org:acme topic:rust`.
+    /// The search API returns at most 1000 results.
+    pub async fn search_repo_names(&self, query: &str) -> Result<Vec<String>, AgentError> {
+        let mut names = Vec::new();
+        for page in 1..=SEARCH_MAX_PAGES {
+            let results: SearchResponse = self.get("/search/repositories", &[
+                ("q", query.to_string()),
+                ("per_page", SEARCH_PAGE_SIZE.to_string()),
+                ("page", page.to_string()),
+            ]).await?;
             
-        Ok(RepoInfo {
-            name: repo.name,
-            full_name: repo.full_name.unwrap_or_default(),
-            language: repo.language,
-            stars: repo.stargazers_count.unwrap_or(0),
-            forks: repo.forks_count.unwrap_or(0),
-        })
+            let count = results.items.len();
+            names.extend(results.items.into_iter().map(|repo| repo.full_name));
+            if count < SEARCH_PAGE_SIZE as usize {
+                break;
+            }
+        }
+        
+        Ok(names)
+    }
+    
+    /// Run a GraphQL query and return its `data`
+    pub async fn graphql(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<serde_json::Value, AgentError> {
+        let request = self.request(Method::POST, &self.endpoints.graphql).await?
+            .json(&serde_json::json!({ "query": query, "variables": variables }));
+        let response: GraphQlResponse = self.send(request, "graphql").await?;
+        
+        if response.errors.iter().any(|e| e.kind.as_deref() == Some("RATE_LIMITED")) {
+            return Err(AgentError::RateLimitError);
+        }
+        if !response.errors.is_empty() {
+            let messages: Vec<String> = response.errors.into_iter().map(|e| e.message).collect();
+            return Err(AgentError::GitHubError(messages.join("; ")));
+        }
+        response.data.ok_or_else(|| AgentError::GitHubError("GraphQL response has no data".to_string()))
+    }
+    
+    /// GET `path` under the REST API root
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T, AgentError> {
+        let url = format!("{}{}", self.endpoints.api, path);
+        let resource = if path.starts_with("/search/") { "search" } else { "core" };
+        self.send(self.request(Method::GET, &url).await?.query(query), resource).await
+    }
+    
+    /// GET a page from the REST API, returning the URL of the next page if there is one
+    pub(crate) async fn get_page<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, String)],
+    ) -> Result<(T, Option<String>), AgentError> {
+        let request = self.request(Method::GET, url).await?.query(query);
+        let (page, link) = self.send_with_link(request, "core").await?;
+        Ok((page, link.as_deref().and_then(next_page_url)))
+    }
+    
+    /// Request to `url` carrying the API headers and the current token
+    async fn request(&self, method: Method, url: &str) -> Result<RequestBuilder, AgentError> {
+        let mut request = self.http
+            .request(method, url)
+            .header(header::ACCEPT, "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", GITHUB_API_VERSION);
+        if let Some(token) = self.auth.token().await? {
+            request = request.bearer_auth(token);
+        }
+        Ok(request)
     }
+    
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder, resource: &str) -> Result<T, AgentError> {
+        self.send_with_link(request, resource).await.map(|(parsed, _)| parsed)
+    }
+    
+    /// Send `request` within the budget of `resource`, returning the parsed
+    /// body and its `Link` header. Secondary limits pause all requests and are
+    /// retried; an exhausted budget is a `RateLimitError`. GETs are revalidated
+    /// against cached responses, since a 304 does not count against the rate limit.
+    async fn send_with_link<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        resource: &str,
+    ) -> Result<(T, Option<String>), AgentError> {
+        let mut attempt = 0;
+        loop {
+            let mut pending = request.try_clone()
+                .ok_or_else(|| AgentError::InternalError("request body cannot be resent".to_string()))?
+                .build()
+                .map_err(|e| AgentError::GitHubError(e.to_string()))?;
+            // Without a known identity the response is simply not cached
+            let key = if pending.method() == Method::GET {
+                self.auth.identity().await.ok()
+                    .map(|identity| cache_key(pending.url().as_str(), &identity))
+            } else {
+                None
+            };
+            let cached = key.as_deref().and_then(|key| self.cache.get(key));
+            if let Some(cached) = &cached {
+                let validators = [
+                    (header::IF_NONE_MATCH, &cached.etag),
+                    (header::IF_MODIFIED_SINCE, &cached.last_modified),
+                ];
+                for (name, value) in validators {
+                    if let Some(value) = value.as_deref().and_then(|value| HeaderValue::from_str(value).ok()) {
+                        pending.headers_mut().insert(name, value);
+                    }
+                }
+            }
+            
+            self.limits.acquire(resource).await?;
+            let response = self.http.execute(pending).await
+                .map_err(|e| AgentError::from_request(e, AgentError::GitHubError))?;
+            self.limits.record(resource, response.headers());
+            
+            let status = response.status();
+            if let (StatusCode::NOT_MODIFIED, Some(cached)) = (status, cached) {
+                let parsed = serde_json::from_str(&cached.body)
+                    .map_err(|e| AgentError::GitHubError(e.to_string()))?;
+                return Ok((parsed, cached.link));
+            }
+            if status.is_success() {
+                let etag = header_string(response.headers(), header::ETAG);
+                let last_modified = header_string(response.headers(), header::LAST_MODIFIED);
+                let link = header_string(response.headers(), header::LINK);
+                let body = response.text().await
+                    .map_err(|e| AgentError::from_request(e, AgentError::GitHubError))?;
+                let parsed = serde_json::from_str(&body)
+                    .map_err(|e| AgentError::GitHubError(e.to_string()))?;
+                
+                if let Some(key) = key.filter(|_| etag.is_some() || last_modified.is_some()) {
+                    self.cache.insert(key, CachedResponse {
+                        etag,
+                        last_modified,
+                        link: link.clone(),
+                        body,
+                    }).await;
+                }
+                return Ok((parsed, link));
+            }
+            
+            let exhausted = response.headers().get("x-ratelimit-remaining")
+                .is_some_and(|remaining| remaining == "0");
+            let retry_after = retry_after(response.headers());
+            let body = response.text().await.unwrap_or_default();
+            let limited = matches!(status, StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS);
+            let secondary = retry_after.is_some()
+                || status == StatusCode::TOO_MANY_REQUESTS
+                || body.contains("secondary rate limit");
+            
+            if limited && exhausted {
+                return Err(AgentError::RateLimitError);
+            }
+            if limited && secondary {
+                self.limits.pause(retry_after.unwrap_or(Duration::from_secs(SECONDARY_LIMIT_PAUSE_SECS)));
+                attempt += 1;
+                if attempt > SECONDARY_LIMIT_RETRIES {
+                    return Err(AgentError::RateLimitError);
+                }
+                continue;
+            }
+            return Err(match status {
+                StatusCode::UNAUTHORIZED => AgentError::AuthError(body),
+                _ if status.is_server_error() => AgentError::NetworkError(format!("{}: {}", status, body)),
+                _ => AgentError::GitHubError(format!("{}: {}", status, body)),
+            });
+        }
+    }
+}
+
+fn header_string(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers.get(name)?.to_str().ok().map(str::to_string)
 }
 
+/// Target of `rel="next"` in a `Link` header
+fn next_page_url(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let (url, params) = part.split_once(';')?;
+        params.split(';')
+            .any(|param| param.trim() == "rel=\"next\"")
+            .then(|| url.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn endpoints(api: Option<&str>) -> GitHubEndpoints {
+        GitHubEndpoints::from_config(&AgentConfig {
+            github_api_url: api.map(str::to_string),
+            ..AgentConfig::default()
+        })
+    }
+    
+    #[test]
+    fn derives_endpoints_from_the_api_url() {
+        let public = endpoints(None);
+        assert_eq!(public.uploads, "https://uploads.github.com");
+        assert_eq!(public.graphql, "https://api.github.com/graphql");
+        assert_eq!(public.web, "https://github.com");
+        
+        let enterprise = endpoints(Some("https://ghe.example.com/api/v3/"));
+        assert_eq!(enterprise.api, "https://ghe.example.com/api/v3");
+        assert_eq!(enterprise.uploads, "https://ghe.example.com/api/uploads");
+        assert_eq!(enterprise.graphql, "https://ghe.example.com/api/graphql");
+        assert_eq!(enterprise.web, "https://ghe.example.com");
+        
+        let mock = endpoints(Some("http://127.0.0.1:8080"));
+        assert_eq!(mock.uploads, "http://127.0.0.1:8080");
+        assert_eq!(mock.web, "http://127.0.0.1:8080");
+    }
+    
+    #[test]
+    fn finds_the_next_page_among_several_links() {
+        let link = concat!(
+            "<https://api.github.com/orgs/o/repos?per_page=100&page=1>; rel=\"prev\", ",
+            "<https://api.github.com/orgs/o/repos?per_page=100&page=3>; rel=\"next\", ",
+            "<https://api.github.com/orgs/o/repos?per_page=100&page=9>; rel=\"last\", ",
+            "<https://api.github.com/orgs/o/repos?per_page=100&page=1>; rel=\"first\"",
+        );
+        assert_eq!(
+            next_page_url(link).as_deref(),
+            Some("https://api.github.com/orgs/o/repos?per_page=100&page=3")
+        );
+        
+        let last_page = concat!(
+            "<https://api.github.com/orgs/o/repos?page=8>; rel=\"prev\", ",
+            "<https://api.github.com/orgs/o/repos?page=1>; rel=\"first\"",
+        );
+        assert_eq!(next_page_url(last_page), None);
+        assert_eq!(next_page_url(""), None);
+        // `rel="next"` must be a parameter of its own, not part of another value
+        assert_eq!(next_page_url("<https://example.com/?q=rel=\"next\">; rel=\"last\""), None);
+    }
+}
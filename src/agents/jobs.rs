@@ -0,0 +1,422 @@
+//! Job tracking for operations submitted to the agent
+
+use crate::journal::{JournalEntry, QueueJournal};
+use crate::scheduler::QueuedJob;
+use crate::{AgentError, BatchReport, ContributionResult, Operation, Priority, RepoHealth, SyncReport};
+use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+
+/// Identifier returned by `GitHubAgent::submit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct JobId(pub Uuid);
+
+impl JobId {
+    pub(crate) fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Lifecycle of a submitted operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobState::Completed | JobState::Failed | JobState::Cancelled)
+    }
+}
+
+/// Snapshot returned by `GitHubAgent::status`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub id: JobId,
+    /// Operation kind, e.g. `smart_commit`
+    pub operation: String,
+    pub repo: String,
+    pub priority: Priority,
+    /// Key given to `GitHubAgent::submit_idempotent`
+    pub idempotency_key: Option<String>,
+    pub state: JobState,
+    /// Attempts started so far, retries included
+    pub attempts: u32,
+    pub submitted_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+/// Output of a finished operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OperationResult {
+    SmartCommit(ContributionResult),
+    AnalyzeRepo(RepoHealth),
+    SyncRepo(SyncReport),
+    HealthCheck(RepoHealth),
+    Batch(BatchReport),
+}
+
+struct JobEntry {
+    status: JobStatus,
+    result: Option<Result<OperationResult, AgentError>>,
+    abort: Option<AbortHandle>,
+    done: watch::Sender<bool>,
+}
+
+/// Jobs by id. Finished jobs are kept until `capacity` newer ones have finished.
+pub(crate) struct JobStore {
+    capacity: usize,
+    jobs: DashMap<JobId, JobEntry>,
+    finished: Mutex<VecDeque<JobId>>,
+    /// Idempotency keys of known jobs
+    keys: DashMap<String, JobId>,
+    journal: Option<QueueJournal>,
+}
+
+impl JobStore {
+    pub(crate) fn new(capacity: usize, journal: Option<QueueJournal>) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            jobs: DashMap::new(),
+            finished: Mutex::new(VecDeque::new()),
+            keys: DashMap::new(),
+            journal,
+        }
+    }
+
+    /// Register `operation` as queued, waiting for the journal to sync it when
+    /// persistence is on. Returns the existing job and `false` when
+    /// `idempotency_key` is already known.
+    pub(crate) async fn insert(
+        &self,
+        operation: &Operation,
+        priority: Priority,
+        idempotency_key: Option<String>,
+    ) -> Result<(JobId, bool), AgentError> {
+        let entry = match self.reserve(operation, priority, idempotency_key) {
+            Ok(entry) => entry,
+            Err(existing) => return Ok((existing, false)),
+        };
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.append_queued(&entry).await {
+                self.forget(entry.id);
+                return Err(e);
+            }
+        }
+        Ok((entry.id, true))
+    }
+
+    /// Register `operation` as queued without waiting for the journal write
+    pub(crate) fn try_insert(&self, operation: &Operation, priority: Priority) -> Result<JobId, AgentError> {
+        let entry = match self.reserve(operation, priority, None) {
+            Ok(entry) => entry,
+            Err(existing) => return Ok(existing),
+        };
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.queue(&entry) {
+                self.forget(entry.id);
+                return Err(e);
+            }
+        }
+        Ok(entry.id)
+    }
+
+    /// Register a new job, or return the one already holding `idempotency_key`
+    fn reserve(
+        &self,
+        operation: &Operation,
+        priority: Priority,
+        idempotency_key: Option<String>,
+    ) -> Result<JournalEntry, JobId> {
+        let id = JobId::new();
+        if let Some(key) = &idempotency_key {
+            match self.keys.entry(key.clone()) {
+                Entry::Occupied(existing) => return Err(*existing.get()),
+                Entry::Vacant(slot) => {
+                    slot.insert(id);
+                }
+            }
+        }
+
+        let entry = JournalEntry {
+            id,
+            operation: operation.clone(),
+            priority,
+            idempotency_key,
+            submitted_at: Utc::now(),
+        };
+        self.register(&entry);
+        Ok(entry)
+    }
+
+    /// Register a job replayed from the journal
+    pub(crate) fn restore(&self, entry: JournalEntry) -> QueuedJob {
+        if let Some(key) = &entry.idempotency_key {
+            self.keys.insert(key.clone(), entry.id);
+        }
+        self.register(&entry);
+        QueuedJob {
+            id: entry.id,
+            operation: entry.operation,
+            priority: entry.priority,
+        }
+    }
+
+    fn register(&self, entry: &JournalEntry) {
+        let (done, _) = watch::channel(false);
+        self.jobs.insert(entry.id, JobEntry {
+            status: JobStatus {
+                id: entry.id,
+                operation: entry.operation.kind().to_string(),
+                repo: entry.operation.repo().to_string(),
+                priority: entry.priority,
+                idempotency_key: entry.idempotency_key.clone(),
+                state: JobState::Queued,
+                attempts: 0,
+                submitted_at: entry.submitted_at,
+                started_at: None,
+                finished_at: None,
+                error: None,
+            },
+            result: None,
+            abort: None,
+            done,
+        });
+    }
+
+    /// Forget a job that never reached the queue
+    pub(crate) fn discard(&self, id: JobId) {
+        self.forget(id);
+        if let Some(journal) = &self.journal {
+            let _ = journal.append_finished(id);
+        }
+    }
+
+    fn forget(&self, id: JobId) {
+        if let Some((_, entry)) = self.jobs.remove(&id) {
+            if let Some(key) = entry.status.idempotency_key {
+                self.keys.remove_if(&key, |_, owner| *owner == id);
+            }
+        }
+    }
+
+    pub(crate) fn status(&self, id: JobId) -> Option<JobStatus> {
+        self.jobs.get(&id).map(|entry| entry.status.clone())
+    }
+
+    /// Mark a queued job as running; false when it was cancelled meanwhile
+    pub(crate) fn start(&self, id: JobId, abort: AbortHandle) -> bool {
+        match self.jobs.get_mut(&id) {
+            Some(mut entry) if entry.status.state == JobState::Queued => {
+                entry.status.state = JobState::Running;
+                entry.status.started_at = Some(Utc::now());
+                entry.abort = Some(abort);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Note that attempt number `attempt` of `id` is starting
+    pub(crate) fn record_attempt(&self, id: JobId, attempt: u32) {
+        if let Some(mut entry) = self.jobs.get_mut(&id) {
+            entry.status.attempts = attempt;
+        }
+    }
+
+    /// Whether `id` can still run
+    pub(crate) fn is_queued(&self, id: JobId) -> bool {
+        self.jobs.get(&id)
+            .map(|entry| entry.status.state == JobState::Queued)
+            .unwrap_or(false)
+    }
+
+    /// Record the outcome of `id`; ignored if it already finished
+    pub(crate) fn finish(&self, id: JobId, result: Result<OperationResult, AgentError>) {
+        {
+            let mut entry = match self.jobs.get_mut(&id) {
+                Some(entry) if !entry.status.state.is_finished() => entry,
+                _ => return,
+            };
+            entry.status.state = match &result {
+                Ok(_) => JobState::Completed,
+                Err(AgentError::Cancelled) => JobState::Cancelled,
+                Err(_) => JobState::Failed,
+            };
+            entry.status.error = result.as_ref().err().map(|e| e.to_string());
+            entry.status.finished_at = Some(Utc::now());
+            entry.result = Some(result);
+            entry.abort = None;
+            entry.done.send_replace(true);
+        }
+
+        // A lost finished record only means the job runs again after a restart
+        if let Some(journal) = &self.journal {
+            let _ = journal.append_finished(id);
+        }
+
+        let mut finished = self.finished.lock();
+        finished.push_back(id);
+        while finished.len() > self.capacity {
+            if let Some(expired) = finished.pop_front() {
+                self.forget(expired);
+            }
+        }
+    }
+
+    /// Cancel a queued or running job; false if it is unknown or already finished
+    pub(crate) fn cancel(&self, id: JobId) -> bool {
+        let abort = match self.jobs.get(&id) {
+            Some(entry) if !entry.status.state.is_finished() => entry.abort.clone(),
+            _ => return false,
+        };
+        if let Some(abort) = abort {
+            abort.abort();
+        }
+        self.finish(id, Err(AgentError::Cancelled));
+        true
+    }
+
+    /// Wait until `id` finishes and return its result
+    pub(crate) async fn wait(&self, id: JobId) -> Result<OperationResult, AgentError> {
+        let mut done = self.jobs.get(&id)
+            .map(|entry| entry.done.subscribe())
+            .ok_or_else(|| AgentError::InternalError(format!("unknown job {}", id)))?;
+        done.wait_for(|finished| *finished).await
+            .map_err(|_| AgentError::InternalError(format!("job {} was discarded", id)))?;
+
+        self.jobs.get(&id)
+            .and_then(|entry| entry.result.clone())
+            .unwrap_or_else(|| Err(AgentError::InternalError(format!("result of job {} has expired", id))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn operation(repo: &str) -> Operation {
+        Operation::HealthCheck {
+            repo: repo.to_string(),
+            session_id: Uuid::nil(),
+        }
+    }
+
+    fn state(store: &JobStore, id: JobId) -> JobState {
+        store.status(id).unwrap().state
+    }
+
+    #[tokio::test]
+    async fn jobs_move_from_queued_to_completed() {
+        let store = JobStore::new(10, None);
+        let (id, created) = store.insert(&operation("o/r"), Priority::Normal, None).await.unwrap();
+        assert!(created);
+        assert_eq!(state(&store, id), JobState::Queued);
+        assert!(store.is_queued(id));
+
+        let task = tokio::spawn(std::future::pending::<()>());
+        assert!(store.start(id, task.abort_handle()));
+        store.record_attempt(id, 1);
+        let status = store.status(id).unwrap();
+        assert_eq!(status.state, JobState::Running);
+        assert!(status.started_at.is_some());
+        assert_eq!(status.attempts, 1);
+
+        store.finish(id, Ok(OperationResult::SyncRepo(SyncReport::default())));
+        assert!(matches!(store.wait(id).await, Ok(OperationResult::SyncRepo(_))));
+        let status = store.status(id).unwrap();
+        assert_eq!(status.state, JobState::Completed);
+        assert!(status.finished_at.is_some());
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn waiting_resolves_when_the_job_finishes() {
+        let store = Arc::new(JobStore::new(10, None));
+        let (id, _) = store.insert(&operation("o/r"), Priority::Normal, None).await.unwrap();
+        let waiter = tokio::spawn({
+            let store = Arc::clone(&store);
+            async move { store.wait(id).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        store.finish(id, Err(AgentError::GitError("boom".to_string())));
+        assert!(matches!(waiter.await.unwrap(), Err(AgentError::GitError(_))));
+        assert_eq!(store.status(id).unwrap().error.as_deref(), Some("Git operation failed: boom"));
+    }
+
+    #[tokio::test]
+    async fn cancelled_queued_jobs_never_start() {
+        let store = JobStore::new(10, None);
+        let (id, _) = store.insert(&operation("o/r"), Priority::Normal, None).await.unwrap();
+
+        assert!(store.cancel(id));
+        assert_eq!(state(&store, id), JobState::Cancelled);
+        assert!(matches!(store.wait(id).await, Err(AgentError::Cancelled)));
+        assert!(!store.is_queued(id));
+        let task = tokio::spawn(std::future::pending::<()>());
+        assert!(!store.start(id, task.abort_handle()));
+        assert!(!store.cancel(id));
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_running_job_aborts_its_task() {
+        let store = JobStore::new(10, None);
+        let (id, _) = store.insert(&operation("o/r"), Priority::Normal, None).await.unwrap();
+        let task = tokio::spawn(std::future::pending::<()>());
+        assert!(store.start(id, task.abort_handle()));
+
+        assert!(store.cancel(id));
+
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert_eq!(state(&store, id), JobState::Cancelled);
+        // The scheduler's own report of the abort does not overwrite the outcome
+        store.finish(id, Err(AgentError::InternalError("aborted".to_string())));
+        assert_eq!(state(&store, id), JobState::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn a_known_idempotency_key_returns_the_existing_job() {
+        let store = JobStore::new(1, None);
+        let key = Some("nightly:o/r".to_string());
+        let (first, created) = store.insert(&operation("o/r"), Priority::Normal, key.clone()).await.unwrap();
+        assert!(created);
+
+        let (again, created) = store.insert(&operation("o/r"), Priority::Interactive, key.clone()).await.unwrap();
+        assert_eq!(again, first);
+        assert!(!created);
+        assert_eq!(store.status(first).unwrap().priority, Priority::Normal);
+
+        // Finished jobs keep their key until their result expires
+        store.finish(first, Ok(OperationResult::SyncRepo(SyncReport::default())));
+        assert_eq!(store.insert(&operation("o/r"), Priority::Normal, key.clone()).await.unwrap().0, first);
+        let (other, _) = store.insert(&operation("o/s"), Priority::Normal, None).await.unwrap();
+        store.finish(other, Ok(OperationResult::SyncRepo(SyncReport::default())));
+        assert!(store.status(first).is_none());
+        let (fresh, created) = store.insert(&operation("o/r"), Priority::Normal, key).await.unwrap();
+        assert_ne!(fresh, first);
+        assert!(created);
+    }
+}
@@ -0,0 +1,330 @@
+//! Append-only journal that lets queued operations survive restarts
+
+use crate::{AgentError, JobId, Operation, Priority};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::{mpsc, oneshot};
+
+/// Rewrite the journal once it holds this many more records than pending jobs
+const JOURNAL_COMPACT_SLACK: usize = 1000;
+
+/// A submission as recorded in the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JournalEntry {
+    pub(crate) id: JobId,
+    pub(crate) operation: Operation,
+    pub(crate) priority: Priority,
+    pub(crate) idempotency_key: Option<String>,
+    pub(crate) submitted_at: DateTime<Utc>,
+}
+
+/// One line of the journal
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum JournalRecord {
+    Queued(JournalEntry),
+    Finished { id: JobId },
+}
+
+struct JournalState {
+    path: PathBuf,
+    file: File,
+    pending: HashMap<JobId, JournalEntry>,
+    records: usize,
+}
+
+enum JournalCommand {
+    Queued {
+        entry: JournalEntry,
+        written: Option<oneshot::Sender<Result<(), AgentError>>>,
+    },
+    Finished(JobId),
+}
+
+/// JSON-lines journal of queued and finished jobs, anything without a
+/// finished record is replayed. Records are written in order by a single
+/// writer task that does its file IO on the blocking pool; queued records
+/// are synced before `append_queued` returns.
+pub(crate) struct QueueJournal {
+    commands: mpsc::UnboundedSender<JournalCommand>,
+}
+
+impl QueueJournal {
+    /// Open or create the journal at `path`, returning the jobs that were queued
+    /// or running when it was last written, in submission order. A job whose
+    /// idempotency key an earlier pending job already holds is dropped.
+    /// Must be called within a Tokio runtime, which runs the writer.
+    pub(crate) fn open(path: &Path) -> Result<(Self, Vec<JournalEntry>), AgentError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(journal_error)?;
+        }
+        
+        let mut order = Vec::new();
+        let mut pending = HashMap::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line.map_err(journal_error)?;
+                    // A line torn by a crash mid-write is skipped
+                    let record = match serde_json::from_str(&line) {
+                        Ok(record) => record,
+                        Err(_) => continue,
+                    };
+                    match record {
+                        JournalRecord::Queued(entry) => {
+                            order.push(entry.id);
+                            pending.insert(entry.id, entry);
+                        }
+                        JournalRecord::Finished { id } => {
+                            pending.remove(&id);
+                        }
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(journal_error(e)),
+        }
+        
+        let mut keys = HashSet::new();
+        let replay: Vec<JournalEntry> = order.iter()
+            .filter_map(|id| pending.remove(id))
+            .filter(|entry| match &entry.idempotency_key {
+                Some(key) => keys.insert(key.clone()),
+                None => true,
+            })
+            .collect();
+        let file = write_compacted(path, &replay)?;
+        
+        let state = JournalState {
+            path: path.to_path_buf(),
+            file,
+            records: replay.len(),
+            pending: replay.iter().map(|entry| (entry.id, entry.clone())).collect(),
+        };
+        let (commands, queue) = mpsc::unbounded_channel();
+        tokio::spawn(run_writer(state, queue));
+        Ok((Self { commands }, replay))
+    }
+    
+    /// Durably record a new submission
+    pub(crate) async fn append_queued(&self, entry: &JournalEntry) -> Result<(), AgentError> {
+        let (written, done) = oneshot::channel();
+        self.send(JournalCommand::Queued {
+            entry: entry.clone(),
+            written: Some(written),
+        })?;
+        done.await.map_err(|_| writer_stopped())?
+    }
+    
+    /// Record a new submission without waiting for it to reach the disk
+    pub(crate) fn queue(&self, entry: &JournalEntry) -> Result<(), AgentError> {
+        self.send(JournalCommand::Queued {
+            entry: entry.clone(),
+            written: None,
+        })
+    }
+    
+    /// Record that `id` finished. Not synced: losing this record only means
+    /// the job runs again after a restart.
+    pub(crate) fn append_finished(&self, id: JobId) -> Result<(), AgentError> {
+        self.send(JournalCommand::Finished(id))
+    }
+    
+    fn send(&self, command: JournalCommand) -> Result<(), AgentError> {
+        self.commands.send(command).map_err(|_| writer_stopped())
+    }
+}
+
+/// Apply commands in arrival order, batching whatever queued up meanwhile
+/// into one blocking task and one sync
+async fn run_writer(mut state: JournalState, mut queue: mpsc::UnboundedReceiver<JournalCommand>) {
+    while let Some(first) = queue.recv().await {
+        let mut batch = vec![first];
+        while let Ok(next) = queue.try_recv() {
+            batch.push(next);
+        }
+        
+        let written = tokio::task::spawn_blocking(move || {
+            let result = state.apply(&mut batch);
+            (state, batch, result)
+        }).await;
+        let (returned, batch, result) = match written {
+            Ok(written) => written,
+            Err(e) => {
+                tracing::error!("queue journal writer failed: {}", e);
+                return;
+            }
+        };
+        state = returned;
+        if let Err(e) = &result {
+            tracing::warn!("queue journal: {}", e);
+        }
+        for command in batch {
+            if let JournalCommand::Queued { written: Some(written), .. } = command {
+                let _ = written.send(result.clone());
+            }
+        }
+    }
+}
+
+impl JournalState {
+    fn apply(&mut self, batch: &mut [JournalCommand]) -> Result<(), AgentError> {
+        let mut queued = false;
+        for command in batch.iter() {
+            match command {
+                JournalCommand::Queued { entry, .. } => {
+                    append(&mut self.file, &JournalRecord::Queued(entry.clone()))?;
+                    self.pending.insert(entry.id, entry.clone());
+                    self.records += 1;
+                    queued = true;
+                }
+                JournalCommand::Finished(id) => {
+                    if self.pending.remove(id).is_some() {
+                        append(&mut self.file, &JournalRecord::Finished { id: *id })?;
+                        self.records += 1;
+                    }
+                }
+            }
+        }
+        if queued {
+            self.file.sync_data().map_err(journal_error)?;
+        }
+        
+        if self.records > self.pending.len() * 2 + JOURNAL_COMPACT_SLACK {
+            let mut entries: Vec<JournalEntry> = self.pending.values().cloned().collect();
+            entries.sort_by_key(|entry| entry.submitted_at);
+            self.file = write_compacted(&self.path, &entries)?;
+            self.records = entries.len();
+        }
+        Ok(())
+    }
+}
+
+/// Atomically replace the journal with queued records for `entries` and
+/// return it opened for appending
+fn write_compacted(path: &Path, entries: &[JournalEntry]) -> Result<File, AgentError> {
+    let staging = path.with_extension("compact");
+    {
+        let mut file = File::create(&staging).map_err(journal_error)?;
+        for entry in entries {
+            append(&mut file, &JournalRecord::Queued(entry.clone()))?;
+        }
+        file.sync_all().map_err(journal_error)?;
+    }
+    fs::rename(&staging, path).map_err(journal_error)?;
+    
+    OpenOptions::new()
+        .append(true)
+        .open(path)
+        .map_err(journal_error)
+}
+
+fn append(file: &mut File, record: &JournalRecord) -> Result<(), AgentError> {
+    let mut line = serde_json::to_string(record)
+        .map_err(|e| AgentError::InternalError(e.to_string()))?;
+    line.push('\n');
+    file.write_all(line.as_bytes()).map_err(journal_error)
+}
+
+fn writer_stopped() -> AgentError {
+    AgentError::InternalError("queue journal writer has stopped".to_string())
+}
+
+fn journal_error(e: std::io::Error) -> AgentError {
+    AgentError::InternalError(format!("queue journal: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use uuid::Uuid;
+    
+    fn entry(repo: &str, key: Option<&str>) -> JournalEntry {
+        JournalEntry {
+            id: JobId::new(),
+            operation: Operation::HealthCheck {
+                repo: repo.to_string(),
+                session_id: Uuid::nil(),
+            },
+            priority: Priority::Normal,
+            idempotency_key: key.map(str::to_string),
+            submitted_at: Utc::now(),
+        }
+    }
+    
+    fn ids(entries: &[JournalEntry]) -> Vec<JobId> {
+        entries.iter().map(|entry| entry.id).collect()
+    }
+    
+    #[tokio::test]
+    async fn unfinished_jobs_are_replayed_in_order_after_a_restart() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("queue.jsonl");
+        let (journal, replayed) = QueueJournal::open(&path).unwrap();
+        assert!(replayed.is_empty());
+        
+        let (a, b, c) = (entry("o/a", None), entry("o/b", None), entry("o/c", Some("nightly-c")));
+        for queued in [&a, &b, &c] {
+            journal.append_queued(queued).await.unwrap();
+        }
+        journal.append_finished(b.id).unwrap();
+        // Records are written in order, so this one lands after `b` finished
+        let d = entry("o/d", None);
+        journal.append_queued(&d).await.unwrap();
+        drop(journal);
+        
+        let (_, replayed) = QueueJournal::open(&path).unwrap();
+        assert_eq!(ids(&replayed), vec![a.id, c.id, d.id]);
+        assert_eq!(replayed[1].idempotency_key.as_deref(), Some("nightly-c"));
+    }
+    
+    #[tokio::test]
+    async fn finished_records_are_compacted_away() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("queue.jsonl");
+        let (journal, _) = QueueJournal::open(&path).unwrap();
+        
+        let total = JOURNAL_COMPACT_SLACK + 100;
+        for _ in 0..total {
+            let queued = entry("o/r", None);
+            journal.queue(&queued).unwrap();
+            journal.append_finished(queued.id).unwrap();
+        }
+        let last = entry("o/last", None);
+        journal.append_queued(&last).await.unwrap();
+        
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        assert!(lines < total, "journal still holds {} records", lines);
+        drop(journal);
+        
+        let (_, replayed) = QueueJournal::open(&path).unwrap();
+        assert_eq!(ids(&replayed), vec![last.id]);
+    }
+    
+    #[tokio::test]
+    async fn a_repeated_idempotency_key_is_replayed_once() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("queue.jsonl");
+        let first = entry("o/r", Some("nightly"));
+        let repeat = entry("o/r", Some("nightly"));
+        let other = entry("o/s", None);
+        let mut file = File::create(&path).unwrap();
+        for queued in [&first, &repeat, &other] {
+            append(&mut file, &JournalRecord::Queued(queued.clone())).unwrap();
+        }
+        drop(file);
+        
+        let (journal, replayed) = QueueJournal::open(&path).unwrap();
+        assert_eq!(ids(&replayed), vec![first.id, other.id]);
+        drop(journal);
+        
+        // The duplicate is gone from the rewritten journal too
+        let (_, replayed) = QueueJournal::open(&path).unwrap();
+        assert_eq!(ids(&replayed), vec![first.id, other.id]);
+    }
+}
@@ -0,0 +1,396 @@
+//! Git LFS pointer files and batch API downloads
+
+use crate::AgentError;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// First line of every LFS pointer file
+const LFS_SPEC_VERSION: &str = "version https://git-lfs.github.com/spec/v1";
+
+/// Pointer files are always smaller than this many bytes
+pub const LFS_POINTER_MAX_SIZE: u64 = 1024;
+
+const LFS_MEDIA_TYPE: &str = "application/vnd.git-lfs+json";
+
+/// Contents of an LFS pointer file
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LfsPointer {
+    /// Hex sha256 of the real content
+    pub oid: String,
+    pub size: u64,
+}
+
+impl LfsPointer {
+    /// Parse pointer file contents; `None` for anything that is not a pointer
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() as u64 >= LFS_POINTER_MAX_SIZE {
+            return None;
+        }
+        let text = std::str::from_utf8(data).ok()?;
+        let mut lines = text.lines();
+        if lines.next()? != LFS_SPEC_VERSION {
+            return None;
+        }
+
+        let mut oid = None;
+        let mut size = None;
+        for line in lines {
+            let (key, value) = line.split_once(' ')?;
+            match key {
+                "oid" => {
+                    oid = value.strip_prefix("sha256:")
+                        .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+                        .map(str::to_string);
+                }
+                "size" => size = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            oid: oid?,
+            size: size?,
+        })
+    }
+
+    /// Read `path` and parse it as a pointer file
+    pub fn read(path: &Path) -> Option<Self> {
+        let meta = std::fs::symlink_metadata(path).ok()?;
+        if !meta.is_file() || meta.len() >= LFS_POINTER_MAX_SIZE {
+            return None;
+        }
+        Self::parse(&std::fs::read(path).ok()?)
+    }
+
+    /// Where git-lfs keeps this object inside `git_dir`
+    pub fn object_path(&self, git_dir: &Path) -> PathBuf {
+        git_dir
+            .join("lfs")
+            .join("objects")
+            .join(&self.oid[0..2])
+            .join(&self.oid[2..4])
+            .join(&self.oid)
+    }
+
+    /// Check that `content` is the object this pointer refers to
+    pub fn verify(&self, content: &[u8]) -> Result<(), String> {
+        if content.len() as u64 != self.size {
+            return Err(format!("expected {} bytes, got {}", self.size, content.len()));
+        }
+        if hex(digest::digest(&digest::SHA256, content).as_ref()) != self.oid {
+            return Err("sha256 mismatch".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Whether `path` holds an LFS pointer rather than real content
+pub fn is_lfs_pointer(path: &Path) -> bool {
+    LfsPointer::read(path).is_some()
+}
+
+/// Client for the LFS batch API using the `basic` transfer adapter
+pub struct LfsClient {
+    endpoint: String,
+    http: reqwest::Client,
+}
+
+#[derive(Serialize)]
+struct BatchRequest<'a> {
+    operation: &'a str,
+    transfers: Vec<&'a str>,
+    objects: Vec<BatchObject<'a>>,
+}
+
+#[derive(Serialize)]
+struct BatchObject<'a> {
+    oid: &'a str,
+    size: u64,
+}
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    objects: Vec<BatchResponseObject>,
+}
+
+#[derive(Deserialize)]
+struct BatchResponseObject {
+    oid: String,
+    actions: Option<BatchActions>,
+    error: Option<BatchError>,
+}
+
+#[derive(Deserialize)]
+struct BatchActions {
+    download: Option<BatchAction>,
+}
+
+#[derive(Deserialize)]
+struct BatchAction {
+    href: String,
+    #[serde(default)]
+    header: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct BatchError {
+    code: u16,
+    message: String,
+}
+
+impl LfsClient {
+    /// Client for `endpoint`, e.g. `https://github.com/owner/repo.git/info/lfs`
+    pub fn new(endpoint: impl Into<String>) -> Result<Self, AgentError> {
+        let http = reqwest::Client::builder()
+            .user_agent("github-agent-core")
+            .build()
+            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+
+        Ok(Self {
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            http,
+        })
+    }
+
+    /// Download and verify `pointers`. The outer error is a failed batch
+    /// request; each object then succeeds or fails on its own.
+    pub async fn download(
+        &self,
+        pointers: &[LfsPointer],
+    ) -> Result<Vec<(LfsPointer, Result<Vec<u8>, String>)>, AgentError> {
+        if pointers.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let request = BatchRequest {
+            operation: "download",
+            transfers: vec!["basic"],
+            objects: pointers.iter()
+                .map(|pointer| BatchObject { oid: &pointer.oid, size: pointer.size })
+                .collect(),
+        };
+        let response = self.http
+            .post(format!("{}/objects/batch", self.endpoint))
+            .header(reqwest::header::ACCEPT, LFS_MEDIA_TYPE)
+            .header(reqwest::header::CONTENT_TYPE, LFS_MEDIA_TYPE)
+            .json(&request)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AgentError::from_request(e, |message| {
+                AgentError::GitError(format!("LFS batch request failed: {}", message))
+            }))?;
+        let batch: BatchResponse = response.json().await
+            .map_err(|e| AgentError::GitError(format!("invalid LFS batch response: {}", e)))?;
+
+        let mut actions: HashMap<String, Result<BatchAction, String>> = HashMap::new();
+        for object in batch.objects {
+            let action = match (object.error, object.actions.and_then(|a| a.download)) {
+                (Some(error), _) => Err(format!("{} ({})", error.message, error.code)),
+                (None, Some(download)) => Ok(download),
+                (None, None) => Err("server returned no download action".to_string()),
+            };
+            actions.insert(object.oid, action);
+        }
+
+        let mut results = Vec::with_capacity(pointers.len());
+        for pointer in pointers {
+            let content = match actions.remove(&pointer.oid) {
+                Some(Ok(action)) => self.fetch_object(pointer, &action).await,
+                Some(Err(error)) => Err(error),
+                None => Err("object missing from batch response".to_string()),
+            };
+            results.push((pointer.clone(), content));
+        }
+
+        Ok(results)
+    }
+
+    async fn fetch_object(&self, pointer: &LfsPointer, action: &BatchAction) -> Result<Vec<u8>, String> {
+        let mut request = self.http.get(&action.href);
+        for (name, value) in &action.header {
+            request = request.header(name.as_str(), value.as_str());
+        }
+
+        let content = request.send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .bytes()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        pointer.verify(&content)?;
+        Ok(content.to_vec())
+    }
+}
+
+/// Derive the default LFS endpoint from a remote URL the way git-lfs does
+pub fn lfs_endpoint_for_remote(url: &str) -> Option<String> {
+    let base = if let Some(rest) = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://")) {
+        let scheme = if url.starts_with("https://") { "https" } else { "http" };
+        format!("{}://{}", scheme, rest)
+    } else if let Some(rest) = url.strip_prefix("ssh://") {
+        let rest = rest.split_once('@').map(|(_, host)| host).unwrap_or(rest);
+        format!("https://{}", rest)
+    } else if let Some((user_host, path)) = url.split_once(':') {
+        // scp-like syntax: git@github.com:owner/repo.git
+        if user_host.contains('/') || path.starts_with("//") {
+            return None;
+        }
+        let host = user_host.split_once('@').map(|(_, host)| host).unwrap_or(user_host);
+        format!("https://{}/{}", host, path)
+    } else {
+        return None;
+    };
+
+    let base = base.trim_end_matches('/');
+    let repo = if base.ends_with(".git") {
+        base.to_string()
+    } else {
+        format!("{}.git", base)
+    };
+    Some(format!("{}/info/lfs", repo))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn pointer_for(content: &[u8]) -> LfsPointer {
+        LfsPointer {
+            oid: hex(digest::digest(&digest::SHA256, content).as_ref()),
+            size: content.len() as u64,
+        }
+    }
+
+    fn pointer_text(pointer: &LfsPointer) -> String {
+        format!("{}\noid sha256:{}\nsize {}\n", LFS_SPEC_VERSION, pointer.oid, pointer.size)
+    }
+
+    /// Read one request, returning its request line
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> String {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let read = stream.read(&mut buf).await.unwrap();
+            data.extend_from_slice(&buf[..read]);
+            let text = String::from_utf8_lossy(&data).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length: usize = head.lines()
+                    .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0);
+                if body.len() >= length || read == 0 {
+                    return head.lines().next().unwrap_or_default().to_string();
+                }
+            }
+        }
+    }
+
+    /// LFS server stand-in. The batch endpoint offers a download for each
+    /// object; `/objects/<oid>` serves `objects[oid]`, which may be corrupt.
+    async fn serve(listener: TcpListener, objects: HashMap<String, Vec<u8>>) {
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut stream).await;
+            let (content_type, body) = if request.starts_with("POST /repo.git/info/lfs/objects/batch ") {
+                let entries: Vec<String> = objects.keys()
+                    .map(|oid| format!(
+                        r#"{{"oid":"{0}","size":0,"actions":{{"download":{{"href":"{1}/objects/{0}","header":{{"x-test":"1"}}}}}}}}"#,
+                        oid, base
+                    ))
+                    .collect();
+                (LFS_MEDIA_TYPE, format!(r#"{{"objects":[{}]}}"#, entries.join(",")).into_bytes())
+            } else {
+                let oid = request.split_whitespace().nth(1).and_then(|path| path.strip_prefix("/objects/"));
+                match oid.and_then(|oid| objects.get(oid)) {
+                    Some(content) => ("application/octet-stream", content.clone()),
+                    None => {
+                        let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await;
+                        continue;
+                    }
+                }
+            };
+            let head = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                content_type, body.len()
+            );
+            let _ = stream.write_all(head.as_bytes()).await;
+            let _ = stream.write_all(&body).await;
+        }
+    }
+
+    #[test]
+    fn parses_pointer_files() {
+        let pointer = pointer_for(b"large file\n");
+        assert_eq!(LfsPointer::parse(pointer_text(&pointer).as_bytes()), Some(pointer.clone()));
+
+        let with_extension = format!(
+            "{}\next-0-foo sha256:{}\noid sha256:{}\nsize {}\n",
+            LFS_SPEC_VERSION, pointer.oid, pointer.oid, pointer.size
+        );
+        assert_eq!(LfsPointer::parse(with_extension.as_bytes()), Some(pointer.clone()));
+
+        assert_eq!(LfsPointer::parse(b"plain text\n"), None);
+        assert_eq!(LfsPointer::parse(format!("{}\nsize 3\n", LFS_SPEC_VERSION).as_bytes()), None);
+        assert_eq!(LfsPointer::parse(format!("{}\noid sha256:abc\nsize 3\n", LFS_SPEC_VERSION).as_bytes()), None);
+        let oversized = format!("{}{}", pointer_text(&pointer), " ".repeat(LFS_POINTER_MAX_SIZE as usize));
+        assert_eq!(LfsPointer::parse(oversized.as_bytes()), None);
+    }
+
+    #[test]
+    fn verifies_size_and_sha256() {
+        let pointer = pointer_for(b"content");
+        assert!(pointer.verify(b"content").is_ok());
+        assert!(pointer.verify(b"contents").is_err());
+        assert_eq!(pointer.verify(b"CONTENT"), Err("sha256 mismatch".to_string()));
+    }
+
+    #[test]
+    fn detects_pointer_files_on_disk() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let pointer_path = dir.path().join("model.bin");
+        let real_path = dir.path().join("README.md");
+        std::fs::write(&pointer_path, pointer_text(&pointer_for(b"weights"))).unwrap();
+        std::fs::write(&real_path, "# Project\n").unwrap();
+
+        assert!(is_lfs_pointer(&pointer_path));
+        assert!(!is_lfs_pointer(&real_path));
+        assert!(!is_lfs_pointer(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn downloads_and_verifies_objects() {
+        let good = pointer_for(b"real content");
+        let corrupt = pointer_for(b"expected content");
+        let unknown = pointer_for(b"never uploaded");
+        let objects = HashMap::from([
+            (good.oid.clone(), b"real content".to_vec()),
+            (corrupt.oid.clone(), b"tampered content".to_vec()),
+        ]);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/repo.git/info/lfs/", listener.local_addr().unwrap());
+        tokio::spawn(serve(listener, objects));
+
+        let client = LfsClient::new(endpoint).unwrap();
+        let results: HashMap<_, _> = client.download(&[good.clone(), corrupt.clone(), unknown.clone()]).await
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        assert_eq!(results[&good], Ok(b"real content".to_vec()));
+        assert_eq!(results[&corrupt], Err("sha256 mismatch".to_string()));
+        assert_eq!(results[&unknown], Err("object missing from batch response".to_string()));
+    }
+}
@@ -11,22 +11,40 @@ use mimalloc::MiMalloc;
 static GLOBAL: MiMalloc = MiMalloc;
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{broadcast, mpsc};
 use uuid::Uuid;
 
+use crate::journal::{JournalEntry, QueueJournal};
+use crate::scheduler::{run_scheduler, QueuedJob, OPERATION_QUEUE_CAPACITY};
+
 pub mod git;
 pub mod github;
 pub mod analyzer;
 pub mod automation;
 pub mod security;
 pub mod performance;
+pub mod signing;
+pub mod lfs;
+pub mod store;
+pub mod jobs;
+pub mod scheduler;
+pub mod journal;
+pub mod retry;
+pub mod events;
+pub mod recurring;
+pub mod batch;
+pub mod auth;
+pub mod ratelimit;
+pub mod cache;
+pub mod pagination;
 
 pub use git::*;
 pub use github::*;
@@ -34,9 +52,21 @@ pub use analyzer::*;
 pub use automation::*;
 pub use security::*;
 pub use performance::*;
+pub use signing::*;
+pub use lfs::*;
+pub use store::*;
+pub use jobs::*;
+pub use scheduler::*;
+pub use retry::*;
+pub use events::*;
+pub use recurring::*;
+pub use batch::*;
+pub use auth::*;
+pub use ratelimit::*;
+pub use pagination::*;
 
 /// Errors that can occur in the GitHub Agent
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone)]
 pub enum AgentError {
     #[error("Authentication failed: {0}")]
     AuthError(String),
@@ -53,11 +83,20 @@ pub enum AgentError {
     #[error("Rate limit exceeded")]
     RateLimitError,
     
+    #[error("Network error: {0}")]
+    NetworkError(String),
+    
     #[error("Permission denied: {0}")]
     PermissionError(String),
     
     #[error("Internal error: {0}")]
     InternalError(String),
+    
+    #[error("Operation cancelled")]
+    Cancelled,
+    
+    #[error("Operation queue is full")]
+    QueueFull,
 }
 
 /// Configuration for the GitHub Agent
@@ -66,10 +105,11 @@ pub struct AgentConfig {
     /// Maximum number of concurrent operations
     pub max_concurrent_operations: usize,
     
-    /// Buffer for rate limiting (requests per second)
+    /// GitHub API requests kept in reserve per rate-limit resource; requests
+    /// are throttled once the remaining budget falls under it
     pub rate_limit_buffer: u64,
     
-    /// Cache size in megabytes
+    /// Cache size in megabytes, applied to cached clones and cached API responses each
     pub cache_size_mb: usize,
     
     /// Path to AI models
@@ -80,6 +120,75 @@ pub struct AgentConfig {
     
     /// Enable telemetry collection
     pub telemetry_enabled: bool,
+    
+    /// Author of agent commits; the repository's `user.name`/`user.email` when unset
+    #[serde(default)]
+    pub commit_author: Option<CommitIdentity>,
+    
+    /// Committer of agent commits; resolved the same way as `commit_author`
+    #[serde(default)]
+    pub commit_committer: Option<CommitIdentity>,
+    
+    /// Directory holding cached clones; a folder under the system temp dir when unset
+    #[serde(default)]
+    pub repo_cache_root: Option<PathBuf>,
+    
+    /// Base URL cached clones are fetched from; the web root of `github_api_url` when unset
+    #[serde(default)]
+    pub repo_remote_base: Option<String>,
+    
+    /// Git LFS batch API endpoint; taken from `lfs.url` or the `origin` remote when unset
+    #[serde(default)]
+    pub lfs_endpoint: Option<String>,
+    
+    /// Number of finished jobs whose results are kept for `await_result`
+    #[serde(default = "default_job_result_capacity")]
+    pub job_result_capacity: usize,
+    
+    /// Journal file that keeps queued operations across restarts; the queue
+    /// lives only in memory when unset
+    #[serde(default)]
+    pub queue_journal: Option<PathBuf>,
+    
+    /// Retry policies for failed operations, by operation kind
+    #[serde(default)]
+    pub retry: RetryConfig,
+    
+    /// File the run history of recurring jobs is kept in; in memory only when unset
+    #[serde(default)]
+    pub recurring_state: Option<PathBuf>,
+    
+    /// How the agent authenticates to GitHub; secrets are referenced, never stored here
+    #[serde(default)]
+    pub auth: Auth,
+    
+    /// REST API root, e.g. `https://ghe.example.com/api/v3`; `https://api.github.com` when unset
+    #[serde(default)]
+    pub github_api_url: Option<String>,
+    
+    /// Release asset upload root; derived from `github_api_url` when unset
+    #[serde(default)]
+    pub github_upload_url: Option<String>,
+    
+    /// GraphQL endpoint; derived from `github_api_url` when unset
+    #[serde(default)]
+    pub github_graphql_url: Option<String>,
+    
+    /// PEM files with extra CA certificates trusted for GitHub API calls
+    #[serde(default)]
+    pub ca_certificates: Vec<PathBuf>,
+    
+    /// Proxy for GitHub API calls, e.g. `http://proxy.internal:3128`
+    #[serde(default)]
+    pub http_proxy: Option<String>,
+    
+    /// Directory GitHub API responses are cached in across restarts; in memory only when unset
+    #[serde(default)]
+    pub http_cache_dir: Option<PathBuf>,
+}
+
+fn default_job_result_capacity() -> usize {
+    1000
 }
 
 impl Default for AgentConfig {
@@ -91,6 +200,22 @@ impl Default for AgentConfig {
             ai_model_path: "models/github-agent-v2".to_string(),
             session_timeout: 1800, // 30 minutes default
             telemetry_enabled: true,
+            commit_author: None,
+            commit_committer: None,
+            repo_cache_root: None,
+            repo_remote_base: None,
+            lfs_endpoint: None,
+            job_result_capacity: default_job_result_capacity(),
+            queue_journal: None,
+            retry: RetryConfig::default(),
+            recurring_state: None,
+            auth: Auth::default(),
+            github_api_url: None,
+            github_upload_url: None,
+            github_graphql_url: None,
+            ca_certificates: Vec::new(),
+            http_proxy: None,
+            http_cache_dir: None,
         }
     }
 }
@@ -140,15 +265,20 @@ pub struct GitHubAgent {
     config: AgentConfig,
     github_client: Arc<GitHubClient>,
     git_engine: Arc<GitEngine>,
+    repo_store: Arc<RepoStore>,
     analyzer: Arc<CodeAnalyzer>,
     automation: Arc<AutomationEngine>,
     sessions: Arc<DashMap<Uuid, SessionInfo>>,
     metrics: Arc<RwLock<Vec<OperationMetric>>>,
-    operation_tx: mpsc::Sender<Operation>,
+    jobs: Arc<JobStore>,
+    dead_letters: Arc<DeadLetterQueue>,
+    events: Arc<EventBus>,
+    recurring: Arc<RecurringJobs>,
+    operation_tx: mpsc::Sender<QueuedJob>,
 }
 
 /// Operation to be executed by the agent
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Operation {
     SmartCommit {
         repo: String,
@@ -161,37 +291,120 @@ pub enum Operation {
     },
     SyncRepo {
         repo: String,
+        transport: SyncTransport,
+        /// Remote name or URL to mirror-push to after syncing
+        mirror: Option<String>,
         session_id: Uuid,
     },
     HealthCheck {
         repo: String,
         session_id: Uuid,
     },
+    /// Run `operation` on every repository of `target`, each as its own job
+    Batch {
+        target: BatchTarget,
+        operation: BatchOperation,
+        /// Repositories processed at once
+        max_concurrent: usize,
+        session_id: Uuid,
+    },
+}
+
+impl Operation {
+    /// Short name used in job status and metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Operation::SmartCommit { .. } => "smart_commit",
+            Operation::AnalyzeRepo { .. } => "analyze_repo",
+            Operation::SyncRepo { .. } => "sync_repo",
+            Operation::HealthCheck { .. } => "health_check",
+            Operation::Batch { .. } => "batch",
+        }
+    }
+    
+    /// Repository the operation targets; empty for batches
+    pub fn repo(&self) -> &str {
+        match self {
+            Operation::SmartCommit { repo, .. }
+            | Operation::AnalyzeRepo { repo, .. }
+            | Operation::SyncRepo { repo, .. }
+            | Operation::HealthCheck { repo, .. } => repo,
+            Operation::Batch { .. } => "",
+        }
+    }
+}
+
+/// How `Operation::SyncRepo` obtains upstream history
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SyncTransport {
+    /// Fetch from the repository's remotes
+    #[default]
+    Network,
+    /// Import a git bundle carried across a network boundary
+    Bundle(PathBuf),
 }
 
 impl GitHubAgent {
     /// Create a new GitHub Agent instance
     pub async fn new(config: AgentConfig) -> Result<Self, AgentError> {
-        let github_client = Arc::new(GitHubClient::new(&config).await?);
-        let git_engine = Arc::new(GitEngine::new(&config)?);
-        let analyzer = Arc::new(CodeAnalyzer::new(&config)?);
-        let automation = Arc::new(AutomationEngine::new(&config)?);
+        let events = Arc::new(EventBus::new());
+        let github_client = GitHubClient::new(&config).await?
+            .with_events(Arc::clone(&events));
+        let mut git_engine = GitEngine::new(&config)?;
+        let authenticator = github_client.authenticator();
+        if !authenticator.is_anonymous() {
+            git_engine = git_engine.with_credentials(Arc::new(AuthCredentials::new(authenticator)));
+        }
+        let github_client = Arc::new(github_client);
+        let git_engine = Arc::new(git_engine);
+        let repo_store = Arc::new(RepoStore::new(
+            &config,
+            Arc::clone(&git_engine),
+            Arc::clone(&events),
+        )?);
+        let analyzer = Arc::new(CodeAnalyzer::new(
+            &config,
+            Arc::clone(&repo_store),
+            Arc::clone(&events),
+        )?);
+        let automation = Arc::new(AutomationEngine::new(
+            &config,
+            Arc::clone(&repo_store),
+            Arc::clone(&git_engine),
+        )?);
         
-        let (operation_tx, operation_rx) = mpsc::channel(1000);
+        let (operation_tx, operation_rx) = mpsc::channel(OPERATION_QUEUE_CAPACITY);
+        
+        let (journal, replayed) = match &config.queue_journal {
+            Some(path) => {
+                let (journal, replayed) = QueueJournal::open(path)?;
+                (Some(journal), replayed)
+            }
+            None => (None, Vec::new()),
+        };
+        let jobs = Arc::new(JobStore::new(config.job_result_capacity, journal));
+        let dead_letters = Arc::new(DeadLetterQueue::new(config.job_result_capacity));
+        let recurring = Arc::new(RecurringJobs::open(config.recurring_state.as_deref())?);
         
         let agent = Self {
             config,
             github_client,
             git_engine,
+            repo_store,
             analyzer,
             automation,
             sessions: Arc::new(DashMap::new()),
             metrics: Arc::new(RwLock::new(Vec::new())),
+            jobs,
+            dead_letters,
+            events,
+            recurring,
             operation_tx,
         };
         
         // Start operation processor
         agent.start_operation_processor(operation_rx).await;
+        agent.replay_journal(replayed);
         
         Ok(agent)
     }
@@ -238,7 +451,7 @@ impl GitHubAgent {
         }
         
         // Perform analysis and automation
-        let (health, patterns, suggestions) = tokio::try_join!(
+        let (_health, _patterns, suggestions) = tokio::try_join!(
             self.analyzer.analyze_repo_health(repo),
             self.analyzer.detect_code_patterns(repo),
             self.automation.generate_ai_suggestions(repo)
@@ -253,38 +466,231 @@ impl GitHubAgent {
         Ok(result)
     }
     
+    /// Bring the cached clone of `repo` up to date and optionally mirror it
+    pub async fn sync_repo(
+        &self,
+        repo: &str,
+        transport: SyncTransport,
+        mirror: Option<String>,
+    ) -> Result<SyncReport, AgentError> {
+        let options = SyncOptions {
+            bundle: match transport {
+                SyncTransport::Network => None,
+                SyncTransport::Bundle(bundle) => Some(bundle),
+            },
+            mirror_remote: mirror,
+        };
+        
+        let checkout = self.repo_store.acquire(repo).await?;
+        let git = Arc::clone(&self.git_engine);
+        checkout.run(move |repository| futures::executor::block_on(git.sync(repository, &options))).await
+    }
+    
+    /// Local clones shared by every engine
+    pub fn repo_store(&self) -> &RepoStore {
+        &self.repo_store
+    }
+    
     /// Get session information
     pub fn get_session_info(&self, session_id: Uuid) -> Option<SessionInfo> {
         self.sessions.get(&session_id).map(|s| s.clone())
     }
     
-    /// Start operation processor
-    async fn start_operation_processor(&self, mut operation_rx: mpsc::Receiver<Operation>) {
-        let agent = self.clone();
+    /// Queue `operation` for background execution at its default priority,
+    /// waiting while the queue is full
+    pub async fn submit(&self, operation: Operation) -> Result<JobId, AgentError> {
+        let priority = operation.default_priority();
+        self.enqueue(operation, priority, None).await
+    }
+    
+    /// Queue `operation` at `priority`, waiting while the queue is full
+    pub async fn submit_with_priority(
+        &self,
+        operation: Operation,
+        priority: Priority,
+    ) -> Result<JobId, AgentError> {
+        self.enqueue(operation, priority, None).await
+    }
+    
+    /// Queue `operation` unless a job with the same `key` is still known, in
+    /// which case that job's id is returned. Replayed jobs keep their keys, so
+    /// resubmitting a batch after a restart does not duplicate work.
+    pub async fn submit_idempotent(
+        &self,
+        operation: Operation,
+        key: impl Into<String>,
+    ) -> Result<JobId, AgentError> {
+        let priority = operation.default_priority();
+        self.enqueue(operation, priority, Some(key.into())).await
+    }
+    
+    async fn enqueue(
+        &self,
+        operation: Operation,
+        priority: Priority,
+        idempotency_key: Option<String>,
+    ) -> Result<JobId, AgentError> {
+        let (id, created) = self.jobs.insert(&operation, priority, idempotency_key).await?;
+        if !created {
+            return Ok(id);
+        }
+        
+        self.announce(id, &operation);
+        let job = QueuedJob { id, operation, priority };
+        if self.operation_tx.send(job).await.is_err() {
+            let error = AgentError::InternalError("operation processor has stopped".to_string());
+            self.jobs.finish(id, Err(error.clone()));
+            return Err(error);
+        }
+        Ok(id)
+    }
+    
+    /// Queue `operation` without waiting; fails with `QueueFull` under backpressure.
+    /// The journal record is written in the background, so unlike `submit` the
+    /// job may be lost if the process dies right after this returns.
+    pub fn try_submit(&self, operation: Operation) -> Result<JobId, AgentError> {
+        let priority = operation.default_priority();
+        let id = self.jobs.try_insert(&operation, priority)?;
+        // Announced first so `Queued` always precedes the scheduler's `Started`
+        self.announce(id, &operation);
+        let job = QueuedJob { id, operation, priority };
+        if let Err(e) = self.operation_tx.try_send(job) {
+            let error = match e {
+                mpsc::error::TrySendError::Full(_) => AgentError::QueueFull,
+                mpsc::error::TrySendError::Closed(_) => {
+                    AgentError::InternalError("operation processor has stopped".to_string())
+                }
+            };
+            self.jobs.discard(id);
+            self.events.emit(AgentEvent::Failed {
+                job: id,
+                error: error.to_string(),
+            });
+            return Err(error);
+        }
+        Ok(id)
+    }
+    
+    fn announce(&self, id: JobId, operation: &Operation) {
+        self.events.emit(AgentEvent::Queued {
+            job: id,
+            operation: operation.kind().to_string(),
+            repo: operation.repo().to_string(),
+        });
+    }
+    
+    /// Current state of a submitted job; `None` once its result has expired
+    pub fn status(&self, job_id: JobId) -> Option<JobStatus> {
+        self.jobs.status(job_id)
+    }
+    
+    /// Wait for a submitted job to finish and return its result
+    pub async fn await_result(&self, job_id: JobId) -> Result<OperationResult, AgentError> {
+        self.jobs.wait(job_id).await
+    }
+    
+    /// Cancel a queued or running job; false if it already finished
+    pub fn cancel(&self, job_id: JobId) -> bool {
+        let was_queued = self.jobs.is_queued(job_id);
+        if !self.jobs.cancel(job_id) {
+            return false;
+        }
+        // Running jobs report their own failure once the abort lands
+        if was_queued {
+            self.events.emit(AgentEvent::Failed {
+                job: job_id,
+                error: AgentError::Cancelled.to_string(),
+            });
+        }
+        true
+    }
+    
+    /// Live job lifecycle, progress and log events
+    pub fn subscribe(&self) -> broadcast::Receiver<AgentEvent> {
+        self.events.subscribe()
+    }
+    
+    /// Last known GitHub API budgets by rate-limit resource
+    pub fn rate_limits(&self) -> HashMap<String, RateBudget> {
+        self.github_client.rate_limits()
+    }
+    
+    /// Jobs that failed with transient errors on every allowed attempt
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.dead_letters.list()
+    }
+    
+    /// Submit a dead-lettered job again as a new job with the same priority
+    pub async fn resubmit_dead_letter(&self, job_id: JobId) -> Result<JobId, AgentError> {
+        let letter = self.dead_letters.take(job_id)
+            .ok_or_else(|| AgentError::InternalError(format!("no dead letter for job {}", job_id)))?;
+        
+        let priority = letter.status.priority;
+        match self.enqueue(letter.operation.clone(), priority, None).await {
+            Ok(id) => Ok(id),
+            Err(e) => {
+                self.dead_letters.push(letter);
+                Err(e)
+            }
+        }
+    }
+    
+    /// Re-queue jobs a previous run journaled but never finished
+    fn replay_journal(&self, entries: Vec<JournalEntry>) {
+        if entries.is_empty() {
+            return;
+        }
+        
+        let queued: Vec<QueuedJob> = entries.into_iter()
+            .map(|entry| self.jobs.restore(entry))
+            .collect();
+        for job in &queued {
+            self.announce(job.id, &job.operation);
+        }
+        let operation_tx = self.operation_tx.clone();
         tokio::spawn(async move {
-            while let Some(operation) = operation_rx.recv().await {
-                let _ = agent.process_operation(operation).await;
+            for job in queued {
+                if operation_tx.send(job).await.is_err() {
+                    break;
+                }
             }
         });
     }
     
+    /// Start operation processor
+    async fn start_operation_processor(&self, operation_rx: mpsc::Receiver<QueuedJob>) {
+        let agent = self.clone();
+        let max_concurrent = self.config.max_concurrent_operations;
+        tokio::spawn(run_scheduler(agent, operation_rx, max_concurrent));
+    }
+    
     /// Process individual operation
-    async fn process_operation(&self, operation: Operation) -> Result<(), AgentError> {
+    async fn process_operation(&self, job_id: JobId, operation: Operation) -> Result<OperationResult, AgentError> {
         match operation {
             Operation::SmartCommit { repo, message, session_id } => {
-                let _ = self.execute_smart_commit(&repo, message, session_id).await;
+                self.execute_smart_commit(&repo, message, session_id).await
+                    .map(OperationResult::SmartCommit)
             }
             Operation::AnalyzeRepo { repo, session_id } => {
-                let _ = self.analyzer.analyze_repo_health(&repo).await;
+                let health = self.analyzer.analyze_repo_health(&repo).await?;
+                self.update_session_metrics(session_id, "analyze_repo").await;
+                Ok(OperationResult::AnalyzeRepo(health))
             }
-            Operation::SyncRepo { repo, session_id } => {
-                // Implementation for repo sync
+            Operation::SyncRepo { repo, transport, mirror, session_id } => {
+                let report = self.sync_repo(&repo, transport, mirror).await?;
+                self.update_session_metrics(session_id, "sync_repo").await;
+                Ok(OperationResult::SyncRepo(report))
             }
             Operation::HealthCheck { repo, session_id } => {
-                let _ = self.analyzer.analyze_repo_health(&repo).await;
+                let health = self.analyzer.analyze_repo_health(&repo).await?;
+                self.update_session_metrics(session_id, "health_check").await;
+                Ok(OperationResult::HealthCheck(health))
+            }
+            Operation::Batch { target, operation, max_concurrent, session_id } => {
+                self.run_batch(job_id, &target, &operation, max_concurrent, session_id).await
+                    .map(OperationResult::Batch)
             }
         }
-        Ok(())
     }
     
     /// Update session metrics
@@ -301,11 +707,11 @@ impl GitHubAgent {
             session_id,
         };
         
-        self.metrics.write().push(metric);
+        self.record_metric(metric);
     }
     
     /// Validate session and check permissions
-    pub fn validate_session(&self, session_id: Uuid, operation: &str) -> Result<(), AgentError> {
+    pub fn validate_session(&self, session_id: Uuid, _operation: &str) -> Result<(), AgentError> {
         let session = self.sessions
             .get(&session_id)
             .ok_or(AgentError::SessionError)?;
@@ -320,7 +726,7 @@ impl GitHubAgent {
                 if session.operations_count >= 5 {
                     return Err(AgentError::PermissionError("Demo limit reached".to_string()));
                 }
-                if session.repos_accessed.len() >= 1 {
+                if !session.repos_accessed.is_empty() {
                     return Err(AgentError::PermissionError("Demo repo limit reached".to_string()));
                 }
             },
@@ -391,10 +797,15 @@ impl Clone for GitHubAgent {
             config: self.config.clone(),
             github_client: Arc::clone(&self.github_client),
             git_engine: Arc::clone(&self.git_engine),
+            repo_store: Arc::clone(&self.repo_store),
             analyzer: Arc::clone(&self.analyzer),
             automation: Arc::clone(&self.automation),
             sessions: Arc::clone(&self.sessions),
             metrics: Arc::clone(&self.metrics),
+            jobs: Arc::clone(&self.jobs),
+            dead_letters: Arc::clone(&self.dead_letters),
+            events: Arc::clone(&self.events),
+            recurring: Arc::clone(&self.recurring),
             operation_tx: self.operation_tx.clone(),
         }
     }
@@ -0,0 +1,375 @@
+//! Paginated GitHub list endpoints exposed as streams
+
+use crate::github::RepoResponse;
+use crate::{AgentError, GitHubClient, RepoInfo};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Largest page the REST API serves
+const MAX_PAGE_SIZE: u8 = 100;
+
+/// How a list is paged through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageOptions {
+    /// Items requested per page, at most 100
+    pub per_page: u8,
+    /// Stop after this many items; the whole list when unset
+    pub max_items: Option<usize>,
+}
+
+impl Default for PageOptions {
+    fn default() -> Self {
+        Self {
+            per_page: MAX_PAGE_SIZE,
+            max_items: None,
+        }
+    }
+}
+
+/// Which issues or pull requests to list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IssueState {
+    #[default]
+    Open,
+    Closed,
+    All,
+}
+
+impl IssueState {
+    fn as_str(self) -> &'static str {
+        match self {
+            IssueState::Open => "open",
+            IssueState::Closed => "closed",
+            IssueState::All => "all",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssueInfo {
+    pub number: u64,
+    pub title: String,
+    pub state: String,
+    pub author: Option<String>,
+    /// The issues endpoint also lists pull requests
+    pub is_pull_request: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullRequestInfo {
+    pub number: u64,
+    pub title: String,
+    pub state: String,
+    pub author: Option<String>,
+    pub head: String,
+    pub base: String,
+    pub draft: bool,
+}
+
+/// A commit as listed by the GitHub API, as opposed to `CommitInfo` read from a local clone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitHubCommit {
+    pub sha: String,
+    pub message: String,
+    pub author_name: Option<String>,
+    /// GitHub account of the author, when the commit email maps to one
+    pub author_login: Option<String>,
+    pub date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContributorInfo {
+    pub login: String,
+    pub contributions: u64,
+}
+
+#[derive(Deserialize)]
+struct UserRef {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct BranchRef {
+    #[serde(rename = "ref")]
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct IssueResponse {
+    number: u64,
+    title: String,
+    state: String,
+    user: Option<UserRef>,
+    pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct PullRequestResponse {
+    number: u64,
+    title: String,
+    state: String,
+    user: Option<UserRef>,
+    head: BranchRef,
+    base: BranchRef,
+    #[serde(default)]
+    draft: bool,
+}
+
+#[derive(Deserialize)]
+struct CommitResponse {
+    sha: String,
+    commit: CommitDetails,
+    author: Option<UserRef>,
+}
+
+#[derive(Deserialize)]
+struct CommitDetails {
+    message: String,
+    author: Option<CommitSignature>,
+}
+
+#[derive(Deserialize)]
+struct CommitSignature {
+    name: Option<String>,
+    date: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct ContributorResponse {
+    login: String,
+    contributions: u64,
+}
+
+impl GitHubClient {
+    /// Repositories of organization `org`
+    pub fn org_repos(
+        &self,
+        org: &str,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<RepoInfo, AgentError>> + '_ {
+        self.paginate::<RepoResponse>(format!("/orgs/{}/repos", org), Vec::new(), options)
+            .map_ok(RepoInfo::from)
+    }
+    
+    /// Public repositories owned by `user`
+    pub fn user_repos(
+        &self,
+        user: &str,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<RepoInfo, AgentError>> + '_ {
+        self.paginate::<RepoResponse>(format!("/users/{}/repos", user), Vec::new(), options)
+            .map_ok(RepoInfo::from)
+    }
+    
+    /// Issues of `owner/repo`, pull requests included
+    pub fn issues(
+        &self,
+        owner: &str,
+        repo: &str,
+        state: IssueState,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<IssueInfo, AgentError>> + '_ {
+        let query = vec![("state", state.as_str().to_string())];
+        self.paginate::<IssueResponse>(format!("/repos/{}/{}/issues", owner, repo), query, options)
+            .map_ok(|issue| IssueInfo {
+                number: issue.number,
+                title: issue.title,
+                state: issue.state,
+                author: issue.user.map(|user| user.login),
+                is_pull_request: issue.pull_request.is_some(),
+            })
+    }
+    
+    /// Pull requests of `owner/repo`
+    pub fn pull_requests(
+        &self,
+        owner: &str,
+        repo: &str,
+        state: IssueState,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<PullRequestInfo, AgentError>> + '_ {
+        let query = vec![("state", state.as_str().to_string())];
+        self.paginate::<PullRequestResponse>(format!("/repos/{}/{}/pulls", owner, repo), query, options)
+            .map_ok(|pull| PullRequestInfo {
+                number: pull.number,
+                title: pull.title,
+                state: pull.state,
+                author: pull.user.map(|user| user.login),
+                head: pull.head.name,
+                base: pull.base.name,
+                draft: pull.draft,
+            })
+    }
+    
+    /// Commits reachable from the default branch, newest first
+    pub fn commits(
+        &self,
+        owner: &str,
+        repo: &str,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<GitHubCommit, AgentError>> + '_ {
+        self.paginate::<CommitResponse>(format!("/repos/{}/{}/commits", owner, repo), Vec::new(), options)
+            .map_ok(|commit| {
+                let signature = commit.commit.author;
+                GitHubCommit {
+                    sha: commit.sha,
+                    message: commit.commit.message,
+                    author_name: signature.as_ref().and_then(|author| author.name.clone()),
+                    author_login: commit.author.map(|author| author.login),
+                    date: signature.and_then(|author| author.date),
+                }
+            })
+    }
+    
+    /// Contributors with a GitHub account, most commits first
+    pub fn contributors(
+        &self,
+        owner: &str,
+        repo: &str,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<ContributorInfo, AgentError>> + '_ {
+        self.paginate::<ContributorResponse>(format!("/repos/{}/{}/contributors", owner, repo), Vec::new(), options)
+            .map_ok(|contributor| ContributorInfo {
+                login: contributor.login,
+                contributions: contributor.contributions,
+            })
+    }
+    
+    /// Items of the list at `path`, fetched a page at a time as the stream is
+    /// polled and following `Link: rel="next"` until the list or `max_items` ends
+    fn paginate<T: DeserializeOwned>(
+        &self,
+        path: String,
+        mut query: Vec<(&'static str, String)>,
+        options: PageOptions,
+    ) -> impl Stream<Item = Result<T, AgentError>> + '_ {
+        let max_items = options.max_items.unwrap_or(usize::MAX);
+        let per_page = options.per_page.clamp(1, MAX_PAGE_SIZE) as usize;
+        query.push(("per_page", per_page.min(max_items.max(1)).to_string()));
+        
+        // Later pages carry their query in the URL from the `Link` header
+        let first = (format!("{}{}", self.endpoints().api, path), query);
+        stream::try_unfold(Some(first), move |page| async move {
+            let (url, query) = match page {
+                Some(page) => page,
+                None => return Ok(None),
+            };
+            let (items, next): (Vec<T>, Option<String>) = self.get_page(&url, &query).await?;
+            let items = stream::iter(items.into_iter().map(Ok::<T, AgentError>));
+            Ok(Some((items, next.map(|url| (url, Vec::new())))))
+        })
+        .try_flatten()
+        .take(max_items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AgentConfig;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    
+    /// Serve the contributors of `o/r` as `pages` pages of three, each linking
+    /// to the next; returns the base URL and the request lines received
+    async fn contributors_server(pages: usize) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&requests);
+        let next_base = base.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut data = Vec::new();
+                let mut buf = [0u8; 4096];
+                while !data.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let read = stream.read(&mut buf).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    data.extend_from_slice(&buf[..read]);
+                }
+                let request = String::from_utf8_lossy(&data).lines().next().unwrap_or_default().to_string();
+                // The first request carries no `page`; later ones follow the link
+                let page: usize = request.split("&page=").nth(1)
+                    .and_then(|rest| rest.split(' ').next())
+                    .and_then(|page| page.parse().ok())
+                    .unwrap_or(1);
+                log.lock().push(request);
+                
+                let body = (1..=3)
+                    .map(|idx| format!(r#"{{"login":"user{}","contributions":1}}"#, (page - 1) * 3 + idx))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let link = if page < pages {
+                    format!("link: <{}/repos/o/r/contributors?per_page=3&page={}>; rel=\"next\"\r\n", next_base, page + 1)
+                } else {
+                    String::new()
+                };
+                let reply = format!(
+                    "HTTP/1.1 200 OK\r\n{}content-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n[{}]",
+                    link,
+                    body.len() + 2,
+                    body
+                );
+                let _ = stream.write_all(reply.as_bytes()).await;
+            }
+        });
+        (base, requests)
+    }
+    
+    async fn client(base: &str) -> GitHubClient {
+        GitHubClient::new(&AgentConfig {
+            github_api_url: Some(base.to_string()),
+            ..AgentConfig::default()
+        }).await.unwrap()
+    }
+    
+    fn logins(contributors: Vec<ContributorInfo>) -> Vec<String> {
+        contributors.into_iter().map(|contributor| contributor.login).collect()
+    }
+    
+    #[tokio::test]
+    async fn follows_next_links_to_the_end_of_the_list() {
+        let (base, requests) = contributors_server(3).await;
+        let client = client(&base).await;
+        let options = PageOptions { per_page: 3, max_items: None };
+        
+        let all: Vec<ContributorInfo> = client.contributors("o", "r", options).try_collect().await.unwrap();
+        
+        assert_eq!(all.len(), 9);
+        assert_eq!(all[8].login, "user9");
+        assert_eq!(requests.lock().len(), 3);
+    }
+    
+    #[tokio::test]
+    async fn max_items_cuts_a_page_short_and_stops_paging() {
+        let (base, requests) = contributors_server(3).await;
+        let client = client(&base).await;
+        let options = PageOptions { per_page: 3, max_items: Some(4) };
+        
+        let first: Vec<ContributorInfo> = client.contributors("o", "r", options).try_collect().await.unwrap();
+        
+        assert_eq!(logins(first), vec!["user1", "user2", "user3", "user4"]);
+        assert_eq!(requests.lock().len(), 2);
+    }
+    
+    #[tokio::test]
+    async fn small_limits_shrink_the_requested_page() {
+        let (base, requests) = contributors_server(3).await;
+        let client = client(&base).await;
+        let options = PageOptions { per_page: 100, max_items: Some(2) };
+        
+        let first: Vec<ContributorInfo> = client.contributors("o", "r", options).try_collect().await.unwrap();
+        
+        assert_eq!(logins(first), vec!["user1", "user2"]);
+        let requests = requests.lock();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].contains("per_page=2"), "{}", requests[0]);
+    }
+}
@@ -14,14 +14,12 @@ pub struct PerformanceMetrics {
 }
 
 pub struct PerformanceMonitor {
-    config: AgentConfig,
     metrics: HashMap<String, Vec<PerformanceMetrics>>,
 }
 
 impl PerformanceMonitor {
-    pub fn new(config: &AgentConfig) -> Result<Self, AgentError> {
+    pub fn new(_config: &AgentConfig) -> Result<Self, AgentError> {
         Ok(Self {
-            config: config.clone(),
             metrics: HashMap::new(),
         })
     }
@@ -36,7 +34,7 @@ impl PerformanceMonitor {
     pub fn record_metric(&mut self, metric: PerformanceMetrics) {
         self.metrics
             .entry(metric.operation.clone())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(metric);
     }
     
@@ -0,0 +1,248 @@
+//! GitHub API rate-limit budgets and throttling
+
+use crate::AgentError;
+use chrono::{DateTime, TimeZone, Utc};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Longest a request waits for budget before failing with `RateLimitError`
+const MAX_THROTTLE_WAIT_SECS: i64 = 300;
+
+/// Pause after a secondary rate limit that carries no `Retry-After`
+pub(crate) const SECONDARY_LIMIT_PAUSE_SECS: u64 = 60;
+
+/// Rate-limit budget of one API resource, e.g. `core`, `search` or `graphql`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateBudget {
+    pub limit: u64,
+    pub remaining: u64,
+    pub used: u64,
+    /// When the budget is restored in full
+    pub reset: DateTime<Utc>,
+}
+
+/// Tracks `X-RateLimit-*` headers and holds requests back before a limit is hit
+pub(crate) struct RateLimiter {
+    /// Requests kept in reserve per resource; throttling starts below it
+    buffer: u64,
+    budgets: DashMap<String, RateBudget>,
+    /// Set by secondary limits; nothing is sent before it
+    paused_until: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(buffer: u64) -> Self {
+        Self {
+            buffer,
+            budgets: DashMap::new(),
+            paused_until: Mutex::new(None),
+        }
+    }
+    
+    pub(crate) fn budget(&self, resource: &str) -> Option<RateBudget> {
+        self.budgets.get(resource).map(|budget| budget.clone())
+    }
+    
+    pub(crate) fn budgets(&self) -> HashMap<String, RateBudget> {
+        self.budgets.iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect()
+    }
+    
+    pub(crate) fn set_budget(&self, resource: &str, budget: RateBudget) {
+        self.budgets.insert(resource.to_string(), budget);
+    }
+    
+    /// Wait until a request against `resource` may be sent. Once the remaining
+    /// budget is under the buffer, requests are spread over the time left until
+    /// the reset; waits longer than the throttle limit fail instead.
+    pub(crate) async fn acquire(&self, resource: &str) -> Result<(), AgentError> {
+        let now = Utc::now();
+        let mut wait = self.paused_until.lock()
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+            .unwrap_or_else(chrono::Duration::zero);
+        
+        if let Some(mut budget) = self.budgets.get_mut(resource) {
+            if budget.reset > now {
+                if budget.remaining <= self.buffer {
+                    let until_reset = budget.reset - now;
+                    let spread = if budget.remaining == 0 {
+                        until_reset
+                    } else {
+                        until_reset / (budget.remaining as i32 + 1)
+                    };
+                    wait = wait.max(spread);
+                }
+                // Count the request now so concurrent callers see it before the response arrives
+                budget.remaining = budget.remaining.saturating_sub(1);
+                budget.used += 1;
+            }
+        }
+        
+        if wait > chrono::Duration::seconds(MAX_THROTTLE_WAIT_SECS) {
+            return Err(AgentError::RateLimitError);
+        }
+        if let Ok(wait) = wait.to_std() {
+            tokio::time::sleep(wait).await;
+        }
+        Ok(())
+    }
+    
+    /// Update the budget from a response's `X-RateLimit-*` headers
+    pub(crate) fn record(&self, resource: &str, headers: &HeaderMap) {
+        let header = |name: &str| -> Option<u64> {
+            headers.get(name)?.to_str().ok()?.trim().parse().ok()
+        };
+        let (limit, remaining, reset) = match (
+            header("x-ratelimit-limit"),
+            header("x-ratelimit-remaining"),
+            header("x-ratelimit-reset"),
+        ) {
+            (Some(limit), Some(remaining), Some(reset)) => (limit, remaining, reset),
+            _ => return,
+        };
+        let reset = match Utc.timestamp_opt(reset as i64, 0).single() {
+            Some(reset) => reset,
+            None => return,
+        };
+        let resource = headers.get("x-ratelimit-resource")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or(resource);
+        
+        self.set_budget(resource, RateBudget {
+            limit,
+            remaining,
+            used: header("x-ratelimit-used").unwrap_or(limit.saturating_sub(remaining)),
+            reset,
+        });
+    }
+    
+    /// Hold every request back for `duration`, as secondary limits require
+    pub(crate) fn pause(&self, duration: Duration) {
+        let until = Utc::now() + chrono::Duration::from_std(duration).unwrap_or_else(|_| chrono::Duration::zero());
+        let mut paused_until = self.paused_until.lock();
+        if paused_until.is_none_or(|current| current < until) {
+            *paused_until = Some(until);
+        }
+    }
+}
+
+/// `Retry-After` in seconds, if the response carries one
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds: u64 = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use std::time::Instant;
+    
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+    
+    fn budget(remaining: u64, reset_in: chrono::Duration) -> RateBudget {
+        RateBudget {
+            limit: 5000,
+            remaining,
+            used: 5000 - remaining,
+            reset: Utc::now() + reset_in,
+        }
+    }
+    
+    #[test]
+    fn budgets_are_recorded_under_the_resource_github_names() {
+        let limiter = RateLimiter::new(10);
+        limiter.record("core", &headers(&[
+            ("x-ratelimit-limit", "30"),
+            ("x-ratelimit-remaining", "12"),
+            ("x-ratelimit-used", "18"),
+            ("x-ratelimit-reset", "1700000000"),
+            ("x-ratelimit-resource", "search"),
+        ]));
+        assert_eq!(limiter.budget("search"), Some(RateBudget {
+            limit: 30,
+            remaining: 12,
+            used: 18,
+            reset: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        }));
+        assert_eq!(limiter.budget("core"), None);
+        
+        // Without a resource header the caller's resource is used, and `used` is derived
+        limiter.record("core", &headers(&[
+            ("x-ratelimit-limit", "5000"),
+            ("x-ratelimit-remaining", "4990"),
+            ("x-ratelimit-reset", "1700000000"),
+        ]));
+        assert_eq!(limiter.budget("core").unwrap().used, 10);
+        
+        // Incomplete headers leave the budget alone
+        limiter.record("core", &headers(&[("x-ratelimit-remaining", "1")]));
+        assert_eq!(limiter.budget("core").unwrap().remaining, 4990);
+    }
+    
+    #[tokio::test]
+    async fn requests_above_the_buffer_are_not_held_back() {
+        let limiter = RateLimiter::new(10);
+        limiter.set_budget("core", budget(100, chrono::Duration::minutes(30)));
+        
+        let started = Instant::now();
+        limiter.acquire("core").await.unwrap();
+        
+        assert!(started.elapsed() < Duration::from_millis(100));
+        let budget = limiter.budget("core").unwrap();
+        assert_eq!((budget.remaining, budget.used), (99, 4901));
+    }
+    
+    #[tokio::test]
+    async fn requests_under_the_buffer_are_spread_until_the_reset() {
+        let limiter = RateLimiter::new(10);
+        // Two requests left for the next 900ms: 900ms / (2 + 1) before each
+        limiter.set_budget("core", budget(2, chrono::Duration::milliseconds(900)));
+        
+        let started = Instant::now();
+        limiter.acquire("core").await.unwrap();
+        
+        let waited = started.elapsed();
+        assert!(waited >= Duration::from_millis(250), "waited {:?}", waited);
+        assert!(waited < Duration::from_millis(700), "waited {:?}", waited);
+        assert_eq!(limiter.budget("core").unwrap().remaining, 1);
+    }
+    
+    #[tokio::test]
+    async fn waits_past_the_throttle_limit_fail_fast() {
+        let limiter = RateLimiter::new(10);
+        limiter.set_budget("core", budget(0, chrono::Duration::seconds(MAX_THROTTLE_WAIT_SECS + 60)));
+        assert!(matches!(limiter.acquire("core").await, Err(AgentError::RateLimitError)));
+        
+        // Other resources have their own budget, but a pause holds back everything
+        limiter.acquire("search").await.unwrap();
+        limiter.pause(Duration::from_secs(MAX_THROTTLE_WAIT_SECS as u64 + 60));
+        assert!(matches!(limiter.acquire("search").await, Err(AgentError::RateLimitError)));
+    }
+    
+    #[test]
+    fn a_shorter_pause_never_cuts_a_longer_one_short() {
+        let limiter = RateLimiter::new(10);
+        let paused_until = || limiter.paused_until.lock().unwrap();
+        
+        limiter.pause(Duration::from_secs(60));
+        let long = paused_until();
+        limiter.pause(Duration::from_secs(5));
+        assert_eq!(paused_until(), long);
+        
+        limiter.pause(Duration::from_secs(120));
+        assert!(paused_until() > long);
+    }
+}
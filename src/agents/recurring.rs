@@ -0,0 +1,424 @@
+//! Recurring operations triggered by cron expressions or fixed intervals
+
+use crate::retry::random_fraction;
+use crate::{AgentError, GitHubAgent, JobId, Operation};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, TimeZone, Timelike, Utc};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::task::AbortHandle;
+
+/// Give up looking for the next cron match after this many steps
+const CRON_SEARCH_LIMIT: usize = 100_000;
+
+/// When a recurring job fires
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Schedule {
+    /// Every `seconds`, starting as soon as the job is registered
+    Every { seconds: u64 },
+    /// Five-field cron expression evaluated in UTC, e.g. `0 2 * * *`
+    Cron(String),
+}
+
+/// An operation submitted on a schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringJob {
+    /// Unique name; registering the same name again replaces the job
+    pub name: String,
+    pub schedule: Schedule,
+    pub operation: Operation,
+    
+    /// Each run is delayed by a random amount up to this many seconds
+    #[serde(default)]
+    pub jitter_seconds: u64,
+}
+
+/// Run history of a recurring job, persisted across restarts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecurringState {
+    /// Scheduled time of the most recent run, whether submitted or skipped
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_job: Option<JobId>,
+    pub next_run: Option<DateTime<Utc>>,
+    pub runs: u64,
+    /// Runs skipped because the previous job was still queued or running
+    pub skipped: u64,
+    /// Why the most recent submission failed, if it did
+    pub last_error: Option<String>,
+}
+
+/// A registered recurring job and its history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringStatus {
+    pub job: RecurringJob,
+    pub state: RecurringState,
+}
+
+/// Parsed five-field cron expression: minute, hour, day of month, month, day of week.
+/// Fields accept `*`, numbers, ranges `a-b`, steps `*/n` or `a-b/n` and comma lists;
+/// `@hourly`, `@daily`/`@nightly`, `@weekly` and `@monthly` are shorthands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpression {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronExpression {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@nightly" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("expected 5 fields, found {}", fields.len()));
+        }
+        
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // Both 0 and 7 mean Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        
+        Ok(Self {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+    
+    /// First matching minute strictly after `after`
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        
+        for _ in 0..CRON_SEARCH_LIMIT {
+            if !bit(self.months, time.month()) {
+                let (year, month) = if time.month() == 12 {
+                    (time.year() + 1, 1)
+                } else {
+                    (time.year(), time.month() + 1)
+                };
+                time = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+            } else if !self.day_matches(&time) {
+                time = start_of_day(time)? + ChronoDuration::days(1);
+            } else if !bit(self.hours, time.hour()) {
+                time = time.with_minute(0)? + ChronoDuration::hours(1);
+            } else if !bit(self.minutes, time.minute()) {
+                time += ChronoDuration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+    
+    /// Cron's rule: when both day fields are restricted, i.e. anything but a
+    /// bare `*`, either may match
+    fn day_matches(&self, time: &DateTime<Utc>) -> bool {
+        let day = bit(self.days, time.day());
+        let weekday = bit(self.weekdays, time.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+impl Schedule {
+    /// Time of the first run after `last_run`, or the first run ever when `None`
+    fn next_run(&self, cron: Option<&CronExpression>, last_run: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+        match (self, last_run) {
+            (Schedule::Every { seconds }, Some(last)) => {
+                Some(last + ChronoDuration::seconds((*seconds).max(1) as i64))
+            }
+            (Schedule::Every { .. }, None) => Some(Utc::now()),
+            (Schedule::Cron(_), last) => cron?.next_after(last.unwrap_or_else(Utc::now)),
+        }
+    }
+}
+
+/// Registered recurring jobs, their timer tasks and persisted history
+pub(crate) struct RecurringJobs {
+    path: Option<PathBuf>,
+    states: Mutex<HashMap<String, RecurringState>>,
+    jobs: DashMap<String, (RecurringJob, AbortHandle)>,
+}
+
+impl RecurringJobs {
+    /// Load history from `path`, if one is configured
+    pub(crate) fn open(path: Option<&Path>) -> Result<Self, AgentError> {
+        let states = match path {
+            Some(path) if path.exists() => {
+                let data = std::fs::read(path)
+                    .map_err(|e| AgentError::InternalError(format!("recurring job state: {}", e)))?;
+                serde_json::from_slice(&data)
+                    .map_err(|e| AgentError::InternalError(format!("recurring job state: {}", e)))?
+            }
+            _ => HashMap::new(),
+        };
+        
+        Ok(Self {
+            path: path.map(Path::to_path_buf),
+            states: Mutex::new(states),
+            jobs: DashMap::new(),
+        })
+    }
+    
+    fn state(&self, name: &str) -> RecurringState {
+        self.states.lock().get(name).cloned().unwrap_or_default()
+    }
+    
+    /// Apply `update` to the state of `name` and persist it
+    fn update<F: FnOnce(&mut RecurringState)>(&self, name: &str, update: F) {
+        let mut states = self.states.lock();
+        update(states.entry(name.to_string()).or_default());
+        
+        // History is best effort; a failed write only loses the latest run
+        if let Some(path) = &self.path {
+            if let Ok(data) = serde_json::to_vec_pretty(&*states) {
+                let staging = path.with_extension("tmp");
+                if std::fs::write(&staging, data).is_ok() {
+                    let _ = std::fs::rename(&staging, path);
+                }
+            }
+        }
+    }
+}
+
+impl GitHubAgent {
+    /// Register `job`, replacing any recurring job with the same name. Its
+    /// history is kept, so a job re-registered after a restart picks up where
+    /// it left off and runs once to catch up on a missed slot.
+    pub fn schedule(&self, job: RecurringJob) -> Result<(), AgentError> {
+        let cron = match &job.schedule {
+            Schedule::Cron(expression) => {
+                let cron = CronExpression::parse(expression)
+                    .map_err(|e| AgentError::InternalError(format!("invalid cron expression '{}': {}", expression, e)))?;
+                // e.g. `0 0 31 2 *` parses but would leave the timer task with nothing to wait for
+                if cron.next_after(Utc::now()).is_none() {
+                    return Err(AgentError::InternalError(format!("cron expression '{}' never matches", expression)));
+                }
+                Some(cron)
+            }
+            Schedule::Every { .. } => None,
+        };
+        
+        if let Some((_, (_, previous))) = self.recurring.jobs.remove(&job.name) {
+            previous.abort();
+        }
+        let agent = self.clone();
+        let definition = job.clone();
+        let task = tokio::spawn(async move {
+            agent.run_recurring(definition, cron).await;
+        });
+        self.recurring.jobs.insert(job.name.clone(), (job, task.abort_handle()));
+        
+        Ok(())
+    }
+    
+    /// Stop scheduling the job called `name`; false if there is none
+    pub fn unschedule(&self, name: &str) -> bool {
+        match self.recurring.jobs.remove(name) {
+            Some((_, (_, task))) => {
+                task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Registered recurring jobs with their history
+    pub fn recurring_jobs(&self) -> Vec<RecurringStatus> {
+        let mut jobs: Vec<RecurringStatus> = self.recurring.jobs.iter()
+            .map(|entry| RecurringStatus {
+                job: entry.value().0.clone(),
+                state: self.recurring.state(entry.key()),
+            })
+            .collect();
+        jobs.sort_by(|a, b| a.job.name.cmp(&b.job.name));
+        jobs
+    }
+    
+    async fn run_recurring(&self, job: RecurringJob, cron: Option<CronExpression>) {
+        loop {
+            let last_run = self.recurring.state(&job.name).last_run;
+            // Slots missed while the agent was down collapse into one run now
+            let next = match job.schedule.next_run(cron.as_ref(), last_run) {
+                Some(next) => next.max(Utc::now()),
+                None => return,
+            };
+            self.recurring.update(&job.name, |state| state.next_run = Some(next));
+            
+            let jitter = Duration::from_secs_f64(job.jitter_seconds as f64 * random_fraction());
+            let wait = (next - Utc::now()).to_std().unwrap_or(Duration::ZERO) + jitter;
+            tokio::time::sleep(wait).await;
+            
+            self.fire_recurring(&job, next).await;
+        }
+    }
+    
+    /// Submit one run of `job` for the slot at `slot`, unless its previous run is unfinished
+    async fn fire_recurring(&self, job: &RecurringJob, slot: DateTime<Utc>) {
+        let overlapping = self.recurring.state(&job.name).last_job
+            .and_then(|previous| self.status(previous))
+            .map(|status| !status.state.is_finished())
+            .unwrap_or(false);
+        if overlapping {
+            self.recurring.update(&job.name, |state| {
+                state.last_run = Some(slot);
+                state.skipped += 1;
+            });
+            return;
+        }
+        
+        let submitted = self.submit(job.operation.clone()).await;
+        self.recurring.update(&job.name, |state| {
+            state.last_run = Some(slot);
+            match submitted {
+                Ok(id) => {
+                    state.last_job = Some(id);
+                    state.runs += 1;
+                    state.last_error = None;
+                }
+                Err(e) => state.last_error = Some(e.to_string()),
+            }
+        });
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("invalid step in '{}'", item))?;
+                if step == 0 {
+                    return Err(format!("zero step in '{}'", item));
+                }
+                (range, step)
+            }
+            None => (item, 1),
+        };
+        
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start, min, max)?, parse_value(end, min, max)?)
+        } else {
+            let start = parse_value(range, min, max)?;
+            // `a/n` runs from `a` to the end of the range
+            (start, if item.contains('/') { max } else { start })
+        };
+        if start > end {
+            return Err(format!("empty range '{}'", item));
+        }
+        
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_value(value: &str, min: u32, max: u32) -> Result<u32, String> {
+    let parsed: u32 = value.parse().map_err(|_| format!("invalid value '{}'", value))?;
+    if parsed < min || parsed > max {
+        return Err(format!("{} is outside {}-{}", parsed, min, max));
+    }
+    Ok(parsed)
+}
+
+fn bit(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+fn start_of_day(time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    Utc.with_ymd_and_hms(time.year(), time.month(), time.day(), 0, 0, 0).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+    }
+    
+    fn next(expression: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        CronExpression::parse(expression).unwrap().next_after(after)
+    }
+    
+    #[test]
+    fn parses_fields() {
+        assert_eq!(parse_field("*", 0, 5), Ok(0b111111));
+        assert_eq!(parse_field("1,3", 0, 5), Ok(0b1010));
+        assert_eq!(parse_field("2-4", 0, 5), Ok(0b11100));
+        assert_eq!(parse_field("*/2", 0, 5), Ok(0b10101));
+        assert_eq!(parse_field("1-5/2", 0, 5), Ok(0b101010));
+        assert_eq!(parse_field("3/1", 0, 5), Ok(0b111000));
+    }
+    
+    #[test]
+    fn rejects_invalid_fields() {
+        assert!(parse_field("6", 0, 5).is_err());
+        assert!(parse_field("0", 1, 5).is_err());
+        assert!(parse_field("4-2", 0, 5).is_err());
+        assert!(parse_field("*/0", 0, 5).is_err());
+        assert!(parse_field("a", 0, 5).is_err());
+        assert!(parse_field("", 0, 5).is_err());
+        assert!(CronExpression::parse("* * * *").is_err());
+        assert!(CronExpression::parse("* * * * * *").is_err());
+        assert!(CronExpression::parse("60 * * * *").is_err());
+    }
+    
+    #[test]
+    fn expands_shorthands_and_sunday() {
+        assert_eq!(CronExpression::parse("@daily"), CronExpression::parse("0 0 * * *"));
+        assert_eq!(CronExpression::parse("@weekly"), CronExpression::parse("0 0 * * 0"));
+        assert_eq!(CronExpression::parse("0 0 * * 7"), CronExpression::parse("0 0 * * 0"));
+    }
+    
+    #[test]
+    fn finds_next_match() {
+        assert_eq!(next("*/15 * * * *", at(2024, 1, 1, 10, 7)), Some(at(2024, 1, 1, 10, 15)));
+        assert_eq!(next("0 2 * * *", at(2024, 1, 1, 2, 0)), Some(at(2024, 1, 2, 2, 0)));
+        assert_eq!(next("30 9 1 * *", at(2024, 12, 5, 0, 0)), Some(at(2025, 1, 1, 9, 30)));
+        assert_eq!(next("0 0 29 2 *", at(2024, 3, 1, 0, 0)), Some(at(2028, 2, 29, 0, 0)));
+        // 2024-01-06 is a Saturday
+        assert_eq!(next("0 8 * * 1-5", at(2024, 1, 5, 9, 0)), Some(at(2024, 1, 8, 8, 0)));
+    }
+    
+    #[test]
+    fn restricted_day_fields_match_either() {
+        // The 15th, or any Monday; 2024-01-08 is a Monday
+        assert_eq!(next("0 0 15 * 1", at(2024, 1, 2, 0, 0)), Some(at(2024, 1, 8, 0, 0)));
+        assert_eq!(next("0 0 15 * 1", at(2024, 1, 13, 0, 0)), Some(at(2024, 1, 15, 0, 0)));
+        // A stepped star restricts its field, so it is not a wildcard either
+        assert_eq!(next("0 0 */10 * 1", at(2024, 1, 2, 0, 0)), Some(at(2024, 1, 8, 0, 0)));
+        assert_eq!(next("0 0 */10 * *", at(2024, 1, 2, 0, 0)), Some(at(2024, 1, 11, 0, 0)));
+    }
+    
+    #[test]
+    fn impossible_expressions_never_match() {
+        assert_eq!(next("0 0 31 2 *", at(2024, 1, 1, 0, 0)), None);
+        assert_eq!(next("0 0 30 2 *", at(2024, 1, 1, 0, 0)), None);
+    }
+}
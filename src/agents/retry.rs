@@ -0,0 +1,185 @@
+//! Retry policies and the dead-letter list for failed operations
+
+use crate::{AgentError, JobId, JobStatus, Operation};
+use git2::{ErrorClass, ErrorCode};
+use parking_lot::Mutex;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// How often and how patiently an operation kind is retried
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Total attempts including the first; 1 disables retries
+    pub max_attempts: u32,
+    
+    /// Delay before the second attempt in milliseconds
+    pub initial_backoff_ms: u64,
+    
+    /// Upper bound for any single delay in milliseconds
+    pub max_backoff_ms: u64,
+    
+    /// Factor the delay grows by after each attempt
+    pub multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 60_000,
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay after failed attempt number `attempt` (starting at 1): exponential
+    /// backoff with jitter drawn from the upper half of the interval
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(63) as i32;
+        let ceiling = (self.initial_backoff_ms as f64 * self.multiplier.max(1.0).powi(exponent))
+            .min(self.max_backoff_ms as f64)
+            .max(0.0);
+        
+        Duration::from_millis((ceiling * (0.5 + random_fraction() / 2.0)) as u64)
+    }
+}
+
+/// Uniform random number in `[0, 1]`; 1 if the system RNG fails
+pub(crate) fn random_fraction() -> f64 {
+    let mut bytes = [0u8; 8];
+    match SystemRandom::new().fill(&mut bytes) {
+        Ok(()) => u64::from_le_bytes(bytes) as f64 / u64::MAX as f64,
+        Err(_) => 1.0,
+    }
+}
+
+/// Retry policies by `Operation::kind`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Policy for kinds without an entry in `operations`
+    #[serde(default)]
+    pub default: RetryPolicy,
+    
+    /// Overrides keyed by operation kind, e.g. `sync_repo`
+    #[serde(default)]
+    pub operations: HashMap<String, RetryPolicy>,
+}
+
+impl RetryConfig {
+    pub fn policy(&self, kind: &str) -> &RetryPolicy {
+        self.operations.get(kind).unwrap_or(&self.default)
+    }
+}
+
+impl AgentError {
+    /// Whether retrying the same operation later may succeed: rate limits and
+    /// network or server failures. A full queue is not retried in place, as the
+    /// retry would only wait behind the backlog that rejected it.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, AgentError::RateLimitError | AgentError::NetworkError(_))
+    }
+    
+    /// Error for a failed fetch, push or clone: `NetworkError` when the
+    /// transport failed, `other` for the rest, e.g. rejected credentials
+    pub(crate) fn from_remote(error: git2::Error, other: impl FnOnce(String) -> Self) -> Self {
+        let transport = matches!(error.class(), ErrorClass::Net | ErrorClass::Http)
+            && error.code() != ErrorCode::Auth;
+        if transport {
+            AgentError::NetworkError(error.to_string())
+        } else {
+            other(error.to_string())
+        }
+    }
+    
+    /// Error for a failed HTTP request: `NetworkError` for timeouts, failed
+    /// connections and 5xx responses, `other` for the rest
+    pub(crate) fn from_request(error: reqwest::Error, other: impl FnOnce(String) -> Self) -> Self {
+        let transient = error.is_timeout()
+            || error.is_connect()
+            || error.status().is_some_and(|status| status.is_server_error());
+        if transient {
+            AgentError::NetworkError(error.to_string())
+        } else {
+            other(error.to_string())
+        }
+    }
+}
+
+/// A job that kept failing with transient errors until it ran out of attempts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// Final status of the job, including its last error
+    pub status: JobStatus,
+    pub operation: Operation,
+}
+
+/// Most recent dead letters, oldest dropped first beyond `capacity`
+pub(crate) struct DeadLetterQueue {
+    capacity: usize,
+    entries: Mutex<VecDeque<DeadLetter>>,
+}
+
+impl DeadLetterQueue {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+    
+    pub(crate) fn push(&self, letter: DeadLetter) {
+        let mut entries = self.entries.lock();
+        entries.push_back(letter);
+        while entries.len() > self.capacity {
+            entries.pop_front();
+        }
+    }
+    
+    pub(crate) fn list(&self) -> Vec<DeadLetter> {
+        self.entries.lock().iter().cloned().collect()
+    }
+    
+    /// Remove and return the dead letter of `id`
+    pub(crate) fn take(&self, id: JobId) -> Option<DeadLetter> {
+        let mut entries = self.entries.lock();
+        let index = entries.iter().position(|letter| letter.status.id == id)?;
+        entries.remove(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn only_transport_failures_of_remote_operations_are_transient() {
+        let timeout = git2::Error::new(ErrorCode::GenericError, ErrorClass::Net, "connection timed out");
+        let refused = git2::Error::new(ErrorCode::Auth, ErrorClass::Http, "authentication required");
+        let missing = git2::Error::new(ErrorCode::NotFound, ErrorClass::Reference, "no such ref");
+        
+        assert!(AgentError::from_remote(timeout, AgentError::GitError).is_transient());
+        assert!(!AgentError::from_remote(refused, AgentError::GitError).is_transient());
+        assert!(!AgentError::from_remote(missing, AgentError::GitError).is_transient());
+    }
+    
+    #[test]
+    fn a_full_queue_is_not_retried_in_place() {
+        assert!(AgentError::RateLimitError.is_transient());
+        assert!(!AgentError::QueueFull.is_transient());
+        assert!(!AgentError::GitHubError("connection timed out".to_string()).is_transient());
+    }
+    
+    #[tokio::test]
+    async fn refused_connections_are_transient() {
+        // Bind and drop a listener to find a port nothing listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let error = reqwest::get(format!("http://127.0.0.1:{}/", port)).await.unwrap_err();
+        
+        let error = AgentError::from_request(error, AgentError::GitHubError);
+        assert!(matches!(error, AgentError::NetworkError(_)));
+    }
+}
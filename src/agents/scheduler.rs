@@ -0,0 +1,350 @@
+//! Concurrent operation scheduler with priorities and per-repository ordering
+
+use crate::{AgentError, AgentEvent, DeadLetter, GitHubAgent, JobId, Operation, OperationResult, RetryPolicy};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use tokio::sync::{mpsc, oneshot};
+
+/// Capacity of the submission channel; `submit` waits once it is full
+pub(crate) const OPERATION_QUEUE_CAPACITY: usize = 1000;
+
+/// Scheduling priority; higher priorities are started first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Priority {
+    Background,
+    Normal,
+    Interactive,
+}
+
+impl Operation {
+    /// Priority used by `GitHubAgent::submit`
+    pub fn default_priority(&self) -> Priority {
+        match self {
+            Operation::SmartCommit { .. } => Priority::Interactive,
+            Operation::AnalyzeRepo { .. } | Operation::SyncRepo { .. } | Operation::Batch { .. } => {
+                Priority::Normal
+            }
+            Operation::HealthCheck { .. } => Priority::Background,
+        }
+    }
+}
+
+/// An operation waiting in the submission channel
+pub(crate) struct QueuedJob {
+    pub(crate) id: JobId,
+    pub(crate) operation: Operation,
+    pub(crate) priority: Priority,
+}
+
+/// Run queued jobs until the submission channel closes. At most
+/// `max_concurrent` jobs run at once and never two on the same repository.
+/// Jobs are buffered up to the channel capacity, after which submitters wait.
+pub(crate) async fn run_scheduler(
+    agent: GitHubAgent,
+    operation_rx: mpsc::Receiver<QueuedJob>,
+    max_concurrent: usize,
+) {
+    let jobs = agent.clone();
+    schedule(
+        operation_rx,
+        max_concurrent,
+        move |id| jobs.jobs.is_queued(id),
+        move |job| {
+            let worker = agent.clone();
+            async move { worker.run_job(job).await }
+        },
+    ).await
+}
+
+/// The loop behind `run_scheduler`, with the job store lookup and the job
+/// itself passed in
+async fn schedule<F>(
+    mut operation_rx: mpsc::Receiver<QueuedJob>,
+    max_concurrent: usize,
+    is_queued: impl Fn(JobId) -> bool,
+    run: impl Fn(QueuedJob) -> F,
+) where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let max_concurrent = max_concurrent.max(1);
+    let (done_tx, mut done_rx) = mpsc::unbounded_channel::<String>();
+    
+    // Ordered by priority, then submission order
+    let mut pending: BTreeMap<(Reverse<Priority>, u64), QueuedJob> = BTreeMap::new();
+    let mut busy_repos: HashSet<String> = HashSet::new();
+    let mut sequence = 0u64;
+    let mut running = 0usize;
+    let mut closed = false;
+    
+    loop {
+        tokio::select! {
+            received = operation_rx.recv(), if !closed && pending.len() < OPERATION_QUEUE_CAPACITY => {
+                match received {
+                    Some(job) => {
+                        pending.insert((Reverse(job.priority), sequence), job);
+                        sequence += 1;
+                    }
+                    None => closed = true,
+                }
+            }
+            Some(repo) = done_rx.recv(), if running > 0 => {
+                busy_repos.remove(&repo);
+                running -= 1;
+            }
+            else => break,
+        }
+        
+        // Jobs cancelled while queued never start
+        pending.retain(|_, job| is_queued(job.id));
+        
+        // Batches only wait on the jobs they submit, so they bypass both limits
+        let batches: Vec<(Reverse<Priority>, u64)> = pending.iter()
+            .filter(|(_, job)| matches!(job.operation, Operation::Batch { .. }))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in batches {
+            if let Some(job) = pending.remove(&key) {
+                tokio::spawn(run(job));
+            }
+        }
+        
+        while running < max_concurrent {
+            let next = pending.iter()
+                .find(|(_, job)| !busy_repos.contains(&repo_key(job.operation.repo())))
+                .map(|(key, _)| *key);
+            let job = match next.and_then(|key| pending.remove(&key)) {
+                Some(job) => job,
+                None => break,
+            };
+            
+            let repo = repo_key(job.operation.repo());
+            busy_repos.insert(repo.clone());
+            running += 1;
+            
+            let task = run(job);
+            let done_tx = done_tx.clone();
+            tokio::spawn(async move {
+                task.await;
+                let _ = done_tx.send(repo);
+            });
+        }
+        
+        if closed && pending.is_empty() && running == 0 {
+            break;
+        }
+    }
+}
+
+impl GitHubAgent {
+    /// Execute one job, recording its outcome in the job store. Jobs that
+    /// exhaust their retries on transient errors are dead-lettered.
+    async fn run_job(&self, job: QueuedJob) {
+        let QueuedJob { id: job_id, operation, .. } = job;
+        self.events.attach(operation.repo(), job_id);
+        let policy = self.config.retry.policy(operation.kind()).clone();
+        let worker = self.clone();
+        let attempted = operation.clone();
+        // The task waits until the store holds its abort handle, so a cancel
+        // can never find the job running without a way to stop it
+        let (started, start) = oneshot::channel::<()>();
+        let task = tokio::spawn(async move {
+            start.await.map_err(|_| AgentError::Cancelled)?;
+            worker.process_with_retries(job_id, attempted, &policy).await
+        });
+        if !self.jobs.start(job_id, task.abort_handle()) {
+            // Cancelled while queued; `cancel` already reported it
+            task.abort();
+            self.events.detach(operation.repo(), job_id);
+            return;
+        }
+        let _ = started.send(());
+        
+        let result = match task.await {
+            Ok(result) => result,
+            Err(e) if e.is_cancelled() => Err(AgentError::Cancelled),
+            Err(e) => Err(AgentError::InternalError(e.to_string())),
+        };
+        let exhausted = matches!(&result, Err(e) if e.is_transient());
+        self.events.detach(operation.repo(), job_id);
+        let event = match &result {
+            Ok(_) => AgentEvent::Completed { job: job_id },
+            Err(e) => AgentEvent::Failed { job: job_id, error: e.to_string() },
+        };
+        self.jobs.finish(job_id, result);
+        self.events.emit(event);
+        
+        if exhausted {
+            if let Some(status) = self.jobs.status(job_id) {
+                self.dead_letters.push(DeadLetter { status, operation });
+            }
+        }
+    }
+    
+    /// Run `operation` until it succeeds, fails permanently or runs out of attempts
+    async fn process_with_retries(
+        &self,
+        job_id: JobId,
+        operation: Operation,
+        policy: &RetryPolicy,
+    ) -> Result<OperationResult, AgentError> {
+        let mut attempt = 1;
+        loop {
+            self.jobs.record_attempt(job_id, attempt);
+            self.events.emit(AgentEvent::Started { job: job_id, attempt });
+            match self.process_operation(job_id, operation.clone()).await {
+                Err(e) if e.is_transient() && attempt < policy.max_attempts => {
+                    tokio::time::sleep(policy.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Repository identity used for serialization and deduplication. Clone URLs
+/// reduce to their path, so `https://github.com/Owner/Repo`, `Owner/Repo.git`
+/// and `owner/repo` match.
+pub(crate) fn repo_key(repo: &str) -> String {
+    let repo = repo.trim().trim_end_matches('/');
+    // Drop the scheme and host of `https://host/o/r`, `ssh://git@host/o/r` and `git@host:o/r`
+    let path = match repo.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').map_or(rest, |(_, path)| path),
+        None => match repo.split_once(':') {
+            Some((host, path)) if !host.contains('/') => path,
+            _ => repo,
+        },
+    };
+    path.trim_start_matches('/').trim_end_matches(".git").to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use uuid::Uuid;
+    
+    #[test]
+    fn clone_urls_and_names_of_one_repository_share_a_key() {
+        for repo in [
+            "o/r",
+            "O/R",
+            "o/r.git",
+            " o/r/ ",
+            "https://github.com/o/r",
+            "https://github.com/O/R.git",
+            "ssh://git@github.com/o/r.git",
+            "git@github.com:o/r.git",
+        ] {
+            assert_eq!(repo_key(repo), "o/r", "{}", repo);
+        }
+        assert_ne!(repo_key("o/r"), repo_key("o/s"));
+    }
+    
+    fn job(repo: &str, priority: Priority) -> QueuedJob {
+        QueuedJob {
+            id: JobId::new(),
+            operation: Operation::HealthCheck {
+                repo: repo.to_string(),
+                session_id: Uuid::nil(),
+            },
+            priority,
+        }
+    }
+    
+    /// Running and peak job counts, overall and per `repo_key`
+    #[derive(Default)]
+    struct Tracker {
+        started: Vec<String>,
+        running: usize,
+        peak: usize,
+        per_repo: HashMap<String, usize>,
+        repo_peak: usize,
+    }
+    
+    /// Queue `jobs`, then schedule them with jobs that hold their slot for a
+    /// few milliseconds, and report what ran when
+    async fn run_jobs(jobs: Vec<QueuedJob>, max_concurrent: usize) -> Tracker {
+        let (tx, rx) = mpsc::channel(OPERATION_QUEUE_CAPACITY);
+        for job in jobs {
+            tx.send(job).await.unwrap();
+        }
+        drop(tx);
+        
+        let tracker = Arc::new(Mutex::new(Tracker::default()));
+        let shared = tracker.clone();
+        schedule(rx, max_concurrent, |_| true, move |job| {
+            let tracker = shared.clone();
+            async move {
+                let repo = job.operation.repo().to_string();
+                {
+                    let mut tracker = tracker.lock();
+                    tracker.started.push(repo.clone());
+                    tracker.running += 1;
+                    tracker.peak = tracker.peak.max(tracker.running);
+                    let same_repo = tracker.per_repo.entry(repo_key(&repo)).or_default();
+                    *same_repo += 1;
+                    let same_repo = *same_repo;
+                    tracker.repo_peak = tracker.repo_peak.max(same_repo);
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                let mut tracker = tracker.lock();
+                tracker.running -= 1;
+                *tracker.per_repo.get_mut(&repo_key(&repo)).unwrap() -= 1;
+            }
+        }).await;
+        
+        Arc::try_unwrap(tracker).ok().unwrap().into_inner()
+    }
+    
+    #[tokio::test]
+    async fn higher_priorities_start_first() {
+        // The first job starts as soon as it arrives and holds the only slot
+        // while the rest queue up behind it
+        let tracker = run_jobs(vec![
+            job("octo/first", Priority::Background),
+            job("octo/background", Priority::Background),
+            job("octo/normal", Priority::Normal),
+            job("octo/interactive", Priority::Interactive),
+            job("octo/normal-later", Priority::Normal),
+        ], 1).await;
+        
+        assert_eq!(tracker.started, vec![
+            "octo/first",
+            "octo/interactive",
+            "octo/normal",
+            "octo/normal-later",
+            "octo/background",
+        ]);
+    }
+    
+    #[tokio::test]
+    async fn jobs_on_one_repository_run_one_at_a_time() {
+        let tracker = run_jobs(vec![
+            job("Octo/Hello", Priority::Normal),
+            job("octo/hello.git", Priority::Normal),
+            job("octo/hello", Priority::Normal),
+            job("octo/other", Priority::Normal),
+        ], 4).await;
+        
+        assert_eq!(tracker.started.len(), 4);
+        assert_eq!(tracker.repo_peak, 1);
+        // Only the unrelated repository ran alongside
+        assert_eq!(tracker.peak, 2);
+    }
+    
+    #[tokio::test]
+    async fn at_most_max_concurrent_jobs_run() {
+        let jobs = (0..8).map(|n| job(&format!("octo/repo-{}", n), Priority::Normal)).collect();
+        
+        let tracker = run_jobs(jobs, 3).await;
+        
+        assert_eq!(tracker.started.len(), 8);
+        assert_eq!(tracker.peak, 3);
+    }
+}
@@ -1,17 +1,15 @@
 //! Security and protection systems
 
 use crate::{AgentConfig, AgentError};
-use ring::{digest, rand};
+use ring::digest;
 use base64::{Engine as _, engine::general_purpose};
 
 pub struct SecurityManager {
-    config: AgentConfig,
 }
 
 impl SecurityManager {
-    pub fn new(config: &AgentConfig) -> Result<Self, AgentError> {
+    pub fn new(_config: &AgentConfig) -> Result<Self, AgentError> {
         Ok(Self {
-            config: config.clone(),
         })
     }
     
@@ -0,0 +1,298 @@
+//! Commit signing for verified agent commits
+
+use crate::AgentError;
+use base64::{Engine as _, engine::general_purpose};
+use ring::digest;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Namespace git uses for SSH commit signatures
+const SSHSIG_NAMESPACE: &str = "git";
+
+/// Column width ssh-keygen uses when armoring signatures
+const SSHSIG_ARMOR_WIDTH: usize = 70;
+
+/// Produces detached signatures over raw commit objects
+pub trait CommitSigner: Send + Sync {
+    /// Sign the commit buffer and return the armored signature
+    fn sign(&self, commit_content: &str) -> Result<String, AgentError>;
+}
+
+/// SSH (ed25519) commit signer, equivalent to `gpg.format = ssh`
+pub struct SshCommitSigner {
+    key_pair: Ed25519KeyPair,
+}
+
+impl SshCommitSigner {
+    /// Create a signer from a raw 32-byte ed25519 seed
+    pub fn from_seed(seed: &[u8]) -> Result<Self, AgentError> {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(seed)
+            .map_err(|e| AgentError::InternalError(format!("invalid ed25519 seed: {}", e)))?;
+        Ok(Self { key_pair })
+    }
+
+    /// Create a signer from an unencrypted OpenSSH ed25519 private key file
+    pub fn from_openssh_key(pem: &str) -> Result<Self, AgentError> {
+        let body: String = pem
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+            .collect();
+        let blob = general_purpose::STANDARD.decode(body)
+            .map_err(|e| AgentError::InternalError(format!("invalid OpenSSH key: {}", e)))?;
+
+        let mut reader = SshReader::new(&blob);
+        if reader.take(15)? != b"openssh-key-v1\0" {
+            return Err(AgentError::InternalError("not an OpenSSH private key".to_string()));
+        }
+        if reader.string()? != b"none" {
+            return Err(AgentError::InternalError(
+                "encrypted OpenSSH keys are not supported; load the key into an agent or decrypt it".to_string(),
+            ));
+        }
+        reader.string()?; // kdfname
+        reader.string()?; // kdfoptions
+        if reader.u32()? != 1 {
+            return Err(AgentError::InternalError("expected exactly one key".to_string()));
+        }
+        reader.string()?; // public key blob
+
+        let private = reader.string()?;
+        let mut reader = SshReader::new(private);
+        if reader.u32()? != reader.u32()? {
+            return Err(AgentError::InternalError("corrupt OpenSSH private key".to_string()));
+        }
+        if reader.string()? != b"ssh-ed25519" {
+            return Err(AgentError::InternalError("only ed25519 keys are supported".to_string()));
+        }
+        let public = reader.string()?;
+        let secret = reader.string()?;
+        if secret.len() != 64 {
+            return Err(AgentError::InternalError("corrupt ed25519 private key".to_string()));
+        }
+
+        let key_pair = Ed25519KeyPair::from_seed_and_public_key(&secret[..32], public)
+            .map_err(|e| AgentError::InternalError(format!("invalid ed25519 key: {}", e)))?;
+        Ok(Self { key_pair })
+    }
+
+    /// Public key in `authorized_keys` format, for GitHub or `allowed_signers`
+    pub fn public_key_openssh(&self) -> String {
+        format!(
+            "ssh-ed25519 {}",
+            general_purpose::STANDARD.encode(self.public_key_blob())
+        )
+    }
+
+    fn public_key_blob(&self) -> Vec<u8> {
+        let mut blob = Vec::new();
+        put_string(&mut blob, b"ssh-ed25519");
+        put_string(&mut blob, self.key_pair.public_key().as_ref());
+        blob
+    }
+}
+
+impl CommitSigner for SshCommitSigner {
+    fn sign(&self, commit_content: &str) -> Result<String, AgentError> {
+        let hash = digest::digest(&digest::SHA512, commit_content.as_bytes());
+
+        let mut signed_data = b"SSHSIG".to_vec();
+        put_string(&mut signed_data, SSHSIG_NAMESPACE.as_bytes());
+        put_string(&mut signed_data, b"");
+        put_string(&mut signed_data, b"sha512");
+        put_string(&mut signed_data, hash.as_ref());
+
+        let mut signature = Vec::new();
+        put_string(&mut signature, b"ssh-ed25519");
+        put_string(&mut signature, self.key_pair.sign(&signed_data).as_ref());
+
+        let mut blob = b"SSHSIG".to_vec();
+        blob.extend_from_slice(&1u32.to_be_bytes());
+        put_string(&mut blob, &self.public_key_blob());
+        put_string(&mut blob, SSHSIG_NAMESPACE.as_bytes());
+        put_string(&mut blob, b"");
+        put_string(&mut blob, b"sha512");
+        put_string(&mut blob, &signature);
+
+        let encoded = general_purpose::STANDARD.encode(blob);
+        let mut armored = String::from("-----BEGIN SSH SIGNATURE-----\n");
+        for line in encoded.as_bytes().chunks(SSHSIG_ARMOR_WIDTH) {
+            armored.push_str(std::str::from_utf8(line).unwrap_or_default());
+            armored.push('\n');
+        }
+        armored.push_str("-----END SSH SIGNATURE-----");
+        Ok(armored)
+    }
+}
+
+/// Signer that pipes the commit through an external program such as
+/// `gpg --detach-sign --armor -u <key>` and reads the signature from stdout
+#[derive(Debug, Clone)]
+pub struct ExternalCommitSigner {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl ExternalCommitSigner {
+    /// GnuPG signer using the given key id
+    pub fn gpg(key_id: &str) -> Self {
+        Self {
+            program: "gpg".to_string(),
+            args: vec![
+                "--batch".to_string(),
+                "--detach-sign".to_string(),
+                "--armor".to_string(),
+                "--local-user".to_string(),
+                key_id.to_string(),
+            ],
+        }
+    }
+}
+
+impl CommitSigner for ExternalCommitSigner {
+    fn sign(&self, commit_content: &str) -> Result<String, AgentError> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| AgentError::InternalError(format!("failed to run {}: {}", self.program, e)))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(commit_content.as_bytes())
+                .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        }
+
+        let output = child.wait_with_output()
+            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        if !output.status.success() {
+            return Err(AgentError::InternalError(format!(
+                "{} failed to sign commit: {}",
+                self.program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        let signature = String::from_utf8(output.stdout)
+            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        Ok(signature.trim_end().to_string())
+    }
+}
+
+fn put_string(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Reader for the SSH wire encoding
+struct SshReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> SshReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], AgentError> {
+        if self.data.len() < len {
+            return Err(AgentError::InternalError("truncated OpenSSH key".to_string()));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, AgentError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], AgentError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{UnparsedPublicKey, ED25519};
+
+    const COMMIT: &str = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+        author Agent <agent@example.com> 1700000000 +0000\n\
+        committer Agent <agent@example.com> 1700000000 +0000\n\nInitial commit\n";
+
+    #[test]
+    fn ssh_signature_is_armored_sshsig_over_the_commit() {
+        let signer = SshCommitSigner::from_seed(&[7u8; 32]).unwrap();
+        let armored = signer.sign(COMMIT).unwrap();
+
+        let lines: Vec<&str> = armored.lines().collect();
+        assert_eq!(lines.first(), Some(&"-----BEGIN SSH SIGNATURE-----"));
+        assert_eq!(lines.last(), Some(&"-----END SSH SIGNATURE-----"));
+        let body = &lines[1..lines.len() - 1];
+        assert!(body.iter().all(|line| line.len() <= SSHSIG_ARMOR_WIDTH));
+
+        let blob = general_purpose::STANDARD.decode(body.concat()).unwrap();
+        let mut reader = SshReader::new(&blob);
+        assert_eq!(reader.take(6).unwrap(), b"SSHSIG");
+        assert_eq!(reader.u32().unwrap(), 1);
+        assert_eq!(reader.string().unwrap(), signer.public_key_blob().as_slice());
+        assert_eq!(reader.string().unwrap(), SSHSIG_NAMESPACE.as_bytes());
+        assert_eq!(reader.string().unwrap(), b"");
+        assert_eq!(reader.string().unwrap(), b"sha512");
+
+        let mut signature = SshReader::new(reader.string().unwrap());
+        assert_eq!(signature.string().unwrap(), b"ssh-ed25519");
+        let signature = signature.string().unwrap();
+
+        let mut signed_data = b"SSHSIG".to_vec();
+        put_string(&mut signed_data, SSHSIG_NAMESPACE.as_bytes());
+        put_string(&mut signed_data, b"");
+        put_string(&mut signed_data, b"sha512");
+        put_string(&mut signed_data, digest::digest(&digest::SHA512, COMMIT.as_bytes()).as_ref());
+        UnparsedPublicKey::new(&ED25519, signer.key_pair.public_key().as_ref())
+            .verify(&signed_data, signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn ssh_keygen_accepts_signatures_from_openssh_keys() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let key = dir.path().join("id_ed25519");
+        let generated = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", "agent", "-f"])
+            .arg(&key)
+            .status();
+        // Only checked where OpenSSH is installed
+        if !generated.is_ok_and(|status| status.success()) {
+            return;
+        }
+
+        let signer = SshCommitSigner::from_openssh_key(&std::fs::read_to_string(&key).unwrap()).unwrap();
+        let public = std::fs::read_to_string(key.with_extension("pub")).unwrap();
+        assert!(public.starts_with(&signer.public_key_openssh()));
+
+        let signature = dir.path().join("commit.sig");
+        let allowed = dir.path().join("allowed_signers");
+        std::fs::write(&signature, signer.sign(COMMIT).unwrap()).unwrap();
+        std::fs::write(&allowed, format!("agent@example.com {}\n", signer.public_key_openssh())).unwrap();
+
+        let mut verify = Command::new("ssh-keygen")
+            .args(["-Y", "verify", "-I", "agent@example.com", "-n", SSHSIG_NAMESPACE, "-f"])
+            .arg(&allowed)
+            .arg("-s")
+            .arg(&signature)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        verify.stdin.take().unwrap().write_all(COMMIT.as_bytes()).unwrap();
+        let output = verify.wait_with_output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+}
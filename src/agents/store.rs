@@ -0,0 +1,498 @@
+//! Local repository cache keyed by `owner/name`
+
+use crate::scheduler::repo_key;
+use crate::{AgentConfig, AgentError, CloneOptions, CloneStage, EventBus, GitEngine, GitHubEndpoints};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use futures::executor::block_on;
+use git2::build::CheckoutBuilder;
+use git2::{BranchType, Repository};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{Mutex as RepoLock, OwnedMutexGuard};
+
+/// A clone tracked by `RepoStore`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedRepo {
+    /// Normalized `owner/name`
+    pub repo: String,
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub last_used: DateTime<Utc>,
+}
+
+/// Exclusive access to a cached clone; the repository stays locked until drop
+pub struct RepoHandle {
+    repo: String,
+    path: PathBuf,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl RepoHandle {
+    /// Normalized `owner/name`
+    pub fn name(&self) -> &str {
+        &self.repo
+    }
+    
+    /// Working directory of the clone
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// Open the clone with git2
+    pub fn open(&self) -> Result<Repository, AgentError> {
+        Repository::open(&self.path)
+            .map_err(|e| AgentError::GitError(e.to_string()))
+    }
+    
+    /// Open the clone and run `task` on it on the blocking pool. The handle
+    /// moves into the blocking task, so the repository stays locked until
+    /// `task` returns even if the awaiting job is cancelled.
+    pub async fn run<T, F>(self, task: F) -> Result<T, AgentError>
+    where
+        F: FnOnce(&Repository) -> Result<T, AgentError> + Send + 'static,
+        T: Send + 'static,
+    {
+        run_git(move || {
+            let repo = self.open()?;
+            task(&repo)
+        }).await
+    }
+}
+
+/// Resolves `owner/name` to a cached clone under a configurable root.
+/// Clones are fetched on demand, locked per repository while in use and
+/// evicted least-recently-used first once `cache_size_mb` is exceeded.
+pub struct RepoStore {
+    root: PathBuf,
+    remote_base: String,
+    capacity_bytes: u64,
+    git: Arc<GitEngine>,
+    events: Arc<EventBus>,
+    entries: Mutex<HashMap<String, CachedRepo>>,
+    locks: DashMap<String, Arc<RepoLock<()>>>,
+}
+
+impl RepoStore {
+    /// Open the cache at `AgentConfig::repo_cache_root`, indexing clones left by earlier runs
+    pub fn new(
+        config: &AgentConfig,
+        git: Arc<GitEngine>,
+        events: Arc<EventBus>,
+    ) -> Result<Self, AgentError> {
+        let root = config.repo_cache_root.clone()
+            .unwrap_or_else(|| std::env::temp_dir().join("github-agent").join("repos"));
+        std::fs::create_dir_all(&root)
+            .map_err(|e| AgentError::InternalError(format!("cannot create repo cache: {}", e)))?;
+        
+        let store = Self {
+            root,
+            remote_base: config.repo_remote_base.clone()
+                .unwrap_or_else(|| GitHubEndpoints::from_config(config).web),
+            capacity_bytes: config.cache_size_mb as u64 * 1024 * 1024,
+            git,
+            events,
+            entries: Mutex::new(HashMap::new()),
+            locks: DashMap::new(),
+        };
+        store.index_existing()?;
+        
+        Ok(store)
+    }
+    
+    /// Local path `repo` is cached at, whether or not it has been cloned yet
+    pub fn path_for(&self, repo: &str) -> Result<PathBuf, AgentError> {
+        let (owner, name) = parse_repo_name(repo)?;
+        Ok(self.root.join(owner).join(name))
+    }
+    
+    /// Lock the clone of `repo`, cloning it first if it is not cached
+    pub async fn acquire(&self, repo: &str) -> Result<RepoHandle, AgentError> {
+        self.checkout(repo, false).await
+    }
+    
+    /// Like `acquire`, but also fetch and fast-forward a clean checkout
+    pub async fn acquire_updated(&self, repo: &str) -> Result<RepoHandle, AgentError> {
+        self.checkout(repo, true).await
+    }
+    
+    /// Currently cached clones, most recently used first
+    pub fn cached(&self) -> Vec<CachedRepo> {
+        let mut cached: Vec<CachedRepo> = self.entries.lock().values().cloned().collect();
+        cached.sort_by_key(|entry| Reverse(entry.last_used));
+        cached
+    }
+    
+    /// Delete the clone of `repo`, waiting for current users to finish
+    pub async fn remove(&self, repo: &str) -> Result<(), AgentError> {
+        let (owner, name) = parse_repo_name(repo)?;
+        let key = format!("{}/{}", owner, name);
+        let _guard = self.lock_for(&key).lock_owned().await;
+        
+        let path = self.root.join(&owner).join(name);
+        if path.exists() {
+            tokio::fs::remove_dir_all(&path).await
+                .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        }
+        let _ = tokio::fs::remove_dir(self.root.join(owner)).await;
+        self.entries.lock().remove(&key);
+        self.prune_locks();
+        
+        Ok(())
+    }
+    
+    async fn checkout(&self, repo: &str, update: bool) -> Result<RepoHandle, AgentError> {
+        let (owner, name) = parse_repo_name(repo)?;
+        let key = format!("{}/{}", owner, name);
+        let path = self.root.join(owner).join(name);
+        let mut guard = self.lock_for(&key).lock_owned().await;
+        
+        // The guard travels with the blocking work, so a cancelled job cannot
+        // unlock the clone while git is still writing to it
+        if !path.join(".git").exists() {
+            // Leftovers of an interrupted clone
+            if path.exists() {
+                tokio::fs::remove_dir_all(&path).await
+                    .map_err(|e| AgentError::InternalError(e.to_string()))?;
+            }
+            let url = format!("{}/{}.git", self.remote_base.trim_end_matches('/'), key);
+            let git = Arc::clone(&self.git);
+            let target = path.clone();
+            let options = self.clone_options(&key);
+            guard = run_git(move || {
+                if let Err(e) = block_on(git.clone_repo_with(&url, &target, &options)) {
+                    let _ = std::fs::remove_dir_all(&target);
+                    return Err(e);
+                }
+                Ok(guard)
+            }).await?;
+        } else if update {
+            self.events.progress(&key, "fetch", 0.0);
+            let git = Arc::clone(&self.git);
+            let target = path.clone();
+            guard = run_git(move || fast_forward_clean(&git, &target).map(|_| guard)).await?;
+            self.events.progress(&key, "fetch", 100.0);
+        }
+        
+        let measured = path.clone();
+        let size_bytes = tokio::task::spawn_blocking(move || dir_size(&measured)).await
+            .map_err(|e| AgentError::InternalError(e.to_string()))?
+            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        self.entries.lock().insert(key.clone(), CachedRepo {
+            repo: key.clone(),
+            path: path.clone(),
+            size_bytes,
+            last_used: Utc::now(),
+        });
+        self.evict(&key).await?;
+        
+        Ok(RepoHandle {
+            repo: key,
+            path,
+            _guard: guard,
+        })
+    }
+    
+    /// Clone options that publish progress and remote output for `key`.
+    /// Progress is only published when the stage or whole percentage changes.
+    fn clone_options(&self, key: &str) -> CloneOptions {
+        let last: Arc<Mutex<Option<(CloneStage, u32)>>> = Arc::new(Mutex::new(None));
+        let progress_events = Arc::clone(&self.events);
+        let progress_key = key.to_string();
+        let log_events = Arc::clone(&self.events);
+        let log_key = key.to_string();
+        
+        CloneOptions::new()
+            .recurse_submodules(true)
+            .fetch_lfs(true)
+            .on_progress(move |progress| {
+                let percent = progress.percent();
+                let step = Some((progress.stage, percent as u32));
+                let mut last = last.lock();
+                if *last != step {
+                    *last = step;
+                    let stage = match progress.stage {
+                        CloneStage::Receiving => "receiving",
+                        CloneStage::Resolving => "resolving",
+                        CloneStage::Checkout => "checkout",
+                    };
+                    progress_events.progress(&progress_key, stage, percent);
+                }
+            })
+            .on_remote_message(move |line| log_events.log(&log_key, line))
+    }
+    
+    /// Delete least-recently-used clones other than `keep` until the cache fits.
+    /// Clones that are currently locked are skipped.
+    async fn evict(&self, keep: &str) -> Result<Vec<String>, AgentError> {
+        // Victims stay locked until their directories are gone
+        let mut victims = Vec::new();
+        {
+            let mut entries = self.entries.lock();
+            let mut total: u64 = entries.values().map(|entry| entry.size_bytes).sum();
+            
+            let mut candidates: Vec<(DateTime<Utc>, String)> = entries.values()
+                .filter(|entry| entry.repo != keep)
+                .map(|entry| (entry.last_used, entry.repo.clone()))
+                .collect();
+            candidates.sort();
+            
+            for (_, key) in candidates {
+                if total <= self.capacity_bytes {
+                    break;
+                }
+                let guard = match self.lock_for(&key).try_lock_owned() {
+                    Ok(guard) => guard,
+                    Err(_) => continue,
+                };
+                if let Some(entry) = entries.remove(&key) {
+                    total = total.saturating_sub(entry.size_bytes);
+                    victims.push((entry, guard));
+                }
+            }
+        }
+        
+        let mut evicted = Vec::new();
+        for (entry, _guard) in victims {
+            if entry.path.exists() {
+                tokio::fs::remove_dir_all(&entry.path).await
+                    .map_err(|e| AgentError::InternalError(e.to_string()))?;
+            }
+            if let Some(owner_dir) = entry.path.parent() {
+                let _ = tokio::fs::remove_dir(owner_dir).await;
+            }
+            evicted.push(entry.repo);
+        }
+        self.prune_locks();
+        
+        Ok(evicted)
+    }
+    
+    fn lock_for(&self, key: &str) -> Arc<RepoLock<()>> {
+        Arc::clone(&self.locks.entry(key.to_string()).or_default())
+    }
+    
+    /// Forget locks of repositories that are neither cached nor in use, so
+    /// the map does not grow with every repository ever requested
+    fn prune_locks(&self) {
+        let entries = self.entries.lock();
+        self.locks.retain(|key, lock| entries.contains_key(key) || Arc::strong_count(lock) > 1);
+        self.locks.shrink_to_fit();
+    }
+    
+    /// Register clones found under the root, oldest modification first in LRU order
+    fn index_existing(&self) -> Result<(), AgentError> {
+        // Listed up front so clones moved below are not visited twice
+        let owners: Vec<_> = std::fs::read_dir(&self.root)
+            .map_err(|e| AgentError::InternalError(e.to_string()))?
+            .flatten()
+            .collect();
+        
+        let mut entries = self.entries.lock();
+        for owner in owners {
+            let repos = match std::fs::read_dir(owner.path()) {
+                Ok(repos) => repos,
+                Err(_) => continue,
+            };
+            for repo in repos.flatten() {
+                let mut path = repo.path();
+                if !path.join(".git").exists() {
+                    continue;
+                }
+                let mut key = format!(
+                    "{}/{}",
+                    owner.file_name().to_string_lossy(),
+                    repo.file_name().to_string_lossy()
+                );
+                // Clones under names that are not normalized, such as ones made
+                // by hand, cannot be reached by any key; adopt them under the
+                // normalized name when it is free and leave them alone otherwise
+                let normalized = repo_key(&key);
+                if key != normalized {
+                    let target = self.root.join(&normalized);
+                    if let Err(e) = adopt(&path, &target) {
+                        tracing::warn!("not indexing {}: {}", path.display(), e);
+                        continue;
+                    }
+                    key = normalized;
+                    path = target;
+                }
+                let last_used = std::fs::metadata(&path)
+                    .and_then(|meta| meta.modified())
+                    .map(DateTime::<Utc>::from)
+                    .unwrap_or_else(|_| Utc::now());
+                let size_bytes = dir_size(&path)
+                    .map_err(|e| AgentError::InternalError(e.to_string()))?;
+                entries.insert(key.clone(), CachedRepo {
+                    repo: key,
+                    path,
+                    size_bytes,
+                    last_used,
+                });
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+/// Run git work on the blocking pool. `git2` handles are not `Sync`, so engine
+/// futures borrowing a `Repository` cannot be held inside spawned tasks.
+pub(crate) async fn run_git<T, F>(task: F) -> Result<T, AgentError>
+where
+    F: FnOnce() -> Result<T, AgentError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(task).await
+        .map_err(|e| AgentError::InternalError(e.to_string()))?
+}
+
+/// Fetch `origin` and fast-forward the checked-out branch when nothing local would be lost
+fn fast_forward_clean(git: &GitEngine, path: &Path) -> Result<(), AgentError> {
+    let repo = Repository::open(path)
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    block_on(git.fetch(&repo, "origin", &[]))?;
+    
+    let head = match repo.head() {
+        Ok(head) if head.is_branch() => head,
+        _ => return Ok(()),
+    };
+    let branch_name = head.shorthand().unwrap_or_default().to_string();
+    let upstream = match repo.find_branch(&branch_name, BranchType::Local)
+        .and_then(|branch| branch.upstream())
+    {
+        Ok(upstream) => upstream,
+        Err(_) => return Ok(()),
+    };
+    
+    let (local, remote) = match (head.target(), upstream.get().target()) {
+        (Some(local), Some(remote)) => (local, remote),
+        _ => return Ok(()),
+    };
+    let fast_forward = local != remote
+        && repo.graph_descendant_of(remote, local)
+            .map_err(|e| AgentError::GitError(e.to_string()))?;
+    if !fast_forward || !block_on(git.status(&repo))?.is_clean() {
+        return Ok(());
+    }
+    
+    let mut head = head;
+    head.set_target(remote, "repo cache: fast-forward")
+        .map_err(|e| AgentError::GitError(e.to_string()))?;
+    repo.checkout_head(Some(CheckoutBuilder::new().force()))
+        .map_err(|e| AgentError::GitError(e.to_string()))
+}
+
+/// Split `owner/name` (optionally suffixed with `.git`) into its parts,
+/// normalized with `repo_key` so the cache and the scheduler agree on which
+/// names refer to the same repository
+fn parse_repo_name(repo: &str) -> Result<(String, String), AgentError> {
+    let key = repo_key(repo);
+    let valid_part = |part: &str| {
+        !part.is_empty()
+            && part != "."
+            && part != ".."
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    
+    match key.split_once('/') {
+        Some((owner, name)) if valid_part(owner) && valid_part(name) => Ok((owner.to_string(), name.to_string())),
+        _ => Err(AgentError::InternalError(format!(
+            "invalid repository '{}', expected owner/name",
+            repo
+        ))),
+    }
+}
+
+/// Move the clone at `path` to the unused `target`
+fn adopt(path: &Path, target: &Path) -> std::io::Result<()> {
+    if target.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} is already taken", target.display()),
+        ));
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(path, target)
+}
+
+fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut total = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        total += if meta.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            meta.len()
+        };
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn normalizes_repo_names_like_the_scheduler() {
+        let expected = ("octo".to_string(), "hello-world".to_string());
+        assert_eq!(parse_repo_name("octo/hello-world").unwrap(), expected);
+        assert_eq!(parse_repo_name("Octo/Hello-World.git").unwrap(), expected);
+        assert_eq!(parse_repo_name(" OCTO/hello-world ").unwrap(), expected);
+        
+        assert!(parse_repo_name("octo").is_err());
+        assert!(parse_repo_name("../hello").is_err());
+        assert!(parse_repo_name("octo/hello/world").is_err());
+    }
+    
+    fn open_store(root: &Path) -> RepoStore {
+        let config = AgentConfig {
+            repo_cache_root: Some(root.to_path_buf()),
+            ..AgentConfig::default()
+        };
+        let git = Arc::new(GitEngine::new(&config).unwrap());
+        RepoStore::new(&config, git, Arc::new(EventBus::new())).unwrap()
+    }
+    
+    fn init_with_branch(path: &Path, branch: &str) {
+        let repo = Repository::init(path).unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        let tree = repo.find_tree(repo.index().unwrap().write_tree().unwrap()).unwrap();
+        let commit = repo.commit(Some("HEAD"), &signature, &signature, "Initial", &tree, &[]).unwrap();
+        repo.branch(branch, &repo.find_commit(commit).unwrap(), false).unwrap();
+    }
+    
+    #[test]
+    fn mixed_case_clones_are_adopted_not_deleted() {
+        let root = tempfile::TempDir::new().unwrap();
+        init_with_branch(&root.path().join("Octo").join("Hello-World"), "local-work");
+        
+        let store = open_store(root.path());
+        
+        let adopted = Repository::open(root.path().join("octo").join("hello-world")).unwrap();
+        assert!(adopted.find_branch("local-work", BranchType::Local).is_ok());
+        let cached: Vec<String> = store.cached().into_iter().map(|repo| repo.repo).collect();
+        assert_eq!(cached, vec!["octo/hello-world".to_string()]);
+    }
+    
+    #[test]
+    fn clones_shadowed_by_a_normalized_one_are_left_alone() {
+        let root = tempfile::TempDir::new().unwrap();
+        init_with_branch(&root.path().join("octo").join("hello-world"), "main-clone");
+        init_with_branch(&root.path().join("Octo").join("Hello-World"), "hand-made");
+        
+        let store = open_store(root.path());
+        
+        let kept = Repository::open(root.path().join("Octo").join("Hello-World")).unwrap();
+        assert!(kept.find_branch("hand-made", BranchType::Local).is_ok());
+        let cached: Vec<String> = store.cached().into_iter().map(|repo| repo.repo).collect();
+        assert_eq!(cached, vec!["octo/hello-world".to_string()]);
+    }
+}